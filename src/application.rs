@@ -9,6 +9,7 @@ use gtk::{
 
 use crate::{
     config::{APP_ID, PKGDATADIR, PROFILE, VERSION},
+    preferences_window::PreferencesWindow,
     window::Window,
 };
 
@@ -125,12 +126,29 @@ impl Application {
     fn setup_gactions(&self) {
         let action_quit = gio::SimpleAction::new("quit", None);
         action_quit.connect_activate(clone!(@weak self as obj => move |_, _| {
-            // This is needed to trigger the delete event and saving the window state
-            obj.main_window().close();
-            obj.quit();
+            // Closing the main window is needed to trigger the delete event and saving the window
+            // state, and it waits for the last changes to be synced. The other windows, e.g., the
+            // preferences, would otherwise keep the app running.
+            obj.main_window()
+                .connect_destroy(clone!(@weak obj => move |_| {
+                    obj.quit();
+                }));
+
+            for window in obj.windows() {
+                window.close();
+            }
         }));
         self.add_action(&action_quit);
 
+        let action_preferences = gio::SimpleAction::new("preferences", None);
+        action_preferences.connect_activate(clone!(@weak self as obj => move |_, _| {
+            let preferences_window = PreferencesWindow::new(&obj.main_window());
+            preferences_window.present();
+        }));
+        self.add_action(&action_preferences);
+
+        self.add_action(&self.settings().create_action("is-auto-sync"));
+
         let action_about = gio::SimpleAction::new("about", None);
         action_about.connect_activate(clone!(@weak self as obj => move |_, _| {
             obj.show_about_dialog();
@@ -140,6 +158,7 @@ impl Application {
 
     fn setup_accels(&self) {
         self.set_accels_for_action("app.quit", &["<Control>q"]);
+        self.set_accels_for_action("app.preferences", &["<Control>comma"]);
     }
 }
 
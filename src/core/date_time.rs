@@ -1,4 +1,4 @@
-use chrono::Local;
+use chrono::{Local, TimeZone};
 use gtk::glib;
 use serde::{Deserialize, Serialize};
 
@@ -21,6 +21,11 @@ impl DateTime {
         Self(Local::now())
     }
 
+    /// Returns `None` if `secs` is out of range
+    pub fn from_unix(secs: i64) -> Option<Self> {
+        Local.timestamp_opt(secs, 0).single().map(Self)
+    }
+
     pub fn fuzzy_display(&self) -> String {
         let now = Local::now();
 
@@ -16,6 +16,11 @@ pub use self::{
     clock_time::ClockTime,
     date_time::DateTime,
     file_type::FileType,
-    note_repository::{NoteRepository, SyncState},
+    note_repository::{
+        find_conflict, render_with_markers, AuthError, Cancelled, ChangedFile, Credentials,
+        Identity, LineChange, MetadataChange, NoteConflict, NoteDiff, NoteRepository, Problem,
+        PullStrategy, RawNote, RemoteUrl, Revision, SigningFormat, SigningOptions, SyncError,
+        SyncPhase, SyncProgress, SyncState, TextChunk, TransferMonitor, Upstream, OURS_MARKER,
+    },
     point::Point,
 };
@@ -0,0 +1,194 @@
+//! Human readable commit messages, so the history is useful when browsed outside of the app.
+
+/// Trailer of the commit message that holds the name of the device where the commit was made
+const DEVICE_TRAILER: &str = "Noteworthy-Device";
+
+/// Notes changed the same way are named one by one up to this count, then only counted
+const MAX_NAMED_NOTES: usize = 3;
+
+/// A change staged in the repository
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    NoteAdded(String),
+    NoteEdited(String),
+    NoteTrashed(String),
+    NoteRestored(String),
+    NoteDeleted(String),
+    AttachmentAdded,
+    AttachmentEdited,
+    AttachmentDeleted,
+    TagsEdited,
+}
+
+/// Describe the changes in one line, e.g., "Edit 'Meeting notes', add 2 attachments"
+pub fn describe(changes: &[Change]) -> String {
+    let note_titles = |verb: &str, f: fn(&Change) -> Option<&str>| {
+        let titles = changes.iter().filter_map(f).collect::<Vec<_>>();
+        describe_notes(verb, &titles)
+    };
+    let attachment_count = |verb: &str, change: &Change| {
+        let count = changes.iter().filter(|other| *other == change).count();
+        describe_attachments(verb, count)
+    };
+
+    let parts = [
+        note_titles("edit", |change| match change {
+            Change::NoteEdited(title) => Some(title.as_str()),
+            _ => None,
+        }),
+        note_titles("add", |change| match change {
+            Change::NoteAdded(title) => Some(title.as_str()),
+            _ => None,
+        }),
+        note_titles("trash", |change| match change {
+            Change::NoteTrashed(title) => Some(title.as_str()),
+            _ => None,
+        }),
+        note_titles("restore", |change| match change {
+            Change::NoteRestored(title) => Some(title.as_str()),
+            _ => None,
+        }),
+        note_titles("delete", |change| match change {
+            Change::NoteDeleted(title) => Some(title.as_str()),
+            _ => None,
+        }),
+        attachment_count("add", &Change::AttachmentAdded),
+        attachment_count("edit", &Change::AttachmentEdited),
+        attachment_count("remove", &Change::AttachmentDeleted),
+        changes
+            .contains(&Change::TagsEdited)
+            .then(|| "update tags".to_string()),
+    ];
+
+    let message = parts.into_iter().flatten().collect::<Vec<_>>().join(", ");
+
+    if message.is_empty() {
+        return "Update notes".to_string();
+    }
+
+    capitalize(&message)
+}
+
+/// Message of the commit that merges the changes from `source`, e.g., a device or remote
+pub fn describe_merge(source: &str) -> String {
+    format!("Merge changes from {}", source)
+}
+
+/// Append the device trailer to `message`
+pub fn with_device(message: &str, device_name: &str) -> String {
+    format!(
+        "{}\n\n{}: {}\n",
+        message.trim_end(),
+        DEVICE_TRAILER,
+        device_name
+    )
+}
+
+/// The device name in the trailer of `message`, if there is any
+pub fn device(message: &str) -> Option<&str> {
+    message.lines().rev().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key.trim() == DEVICE_TRAILER)
+            .then(|| value.trim())
+            .filter(|value| !value.is_empty())
+    })
+}
+
+fn describe_notes(verb: &str, titles: &[&str]) -> Option<String> {
+    let quoted = |title: &&str| format!("'{}'", title);
+
+    match titles {
+        [] => None,
+        [title] => Some(format!("{} {}", verb, quoted(title))),
+        [rest @ .., last] if titles.len() <= MAX_NAMED_NOTES => {
+            let rest = rest.iter().map(quoted).collect::<Vec<_>>().join(", ");
+            Some(format!("{} {} and {}", verb, rest, quoted(last)))
+        }
+        _ => Some(format!("{} {} notes", verb, titles.len())),
+    }
+}
+
+fn describe_attachments(verb: &str, count: usize) -> Option<String> {
+    match count {
+        0 => None,
+        1 => Some(format!("{} an attachment", verb)),
+        n => Some(format!("{} {} attachments", verb, n)),
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn single_change() {
+        assert_eq!(
+            describe(&[Change::NoteEdited("Meeting notes".into())]),
+            "Edit 'Meeting notes'"
+        );
+        assert_eq!(describe(&[Change::AttachmentAdded]), "Add an attachment");
+        assert_eq!(describe(&[Change::TagsEdited]), "Update tags");
+    }
+
+    #[test]
+    fn mixed_changes() {
+        let changes = [
+            Change::AttachmentAdded,
+            Change::NoteTrashed("Old draft".into()),
+            Change::NoteEdited("Meeting notes".into()),
+            Change::AttachmentAdded,
+        ];
+        assert_eq!(
+            describe(&changes),
+            "Edit 'Meeting notes', trash 'Old draft', add 2 attachments"
+        );
+    }
+
+    #[test]
+    fn many_notes() {
+        let two = [Change::NoteAdded("A".into()), Change::NoteAdded("B".into())];
+        assert_eq!(describe(&two), "Add 'A' and 'B'");
+
+        let three = [
+            Change::NoteDeleted("A".into()),
+            Change::NoteDeleted("B".into()),
+            Change::NoteDeleted("C".into()),
+        ];
+        assert_eq!(describe(&three), "Delete 'A', 'B' and 'C'");
+
+        let four = ["A", "B", "C", "D"].map(|title| Change::NoteEdited(title.into()));
+        assert_eq!(describe(&four), "Edit 4 notes");
+    }
+
+    #[test]
+    fn no_changes() {
+        assert_eq!(describe(&[]), "Update notes");
+    }
+
+    #[test]
+    fn device_trailer() {
+        let message = with_device("Edit 'Note'", "Dave's Laptop");
+        assert_eq!(message, "Edit 'Note'\n\nNoteworthy-Device: Dave's Laptop\n");
+        assert_eq!(device(&message), Some("Dave's Laptop"));
+
+        assert_eq!(device("Edit 'Note'"), None);
+        assert_eq!(device("Edit 'Note'\n\nNoteworthy-Device: \n"), None);
+    }
+
+    #[test]
+    fn merge() {
+        assert_eq!(
+            describe_merge("origin (github.com)"),
+            "Merge changes from origin (github.com)"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use std::path::Path;
+
+use super::remote_url::RemoteUrl;
+use crate::config::APP_ID;
+
+/// Username and personal access token used to authenticate with HTTPS remotes
+///
+/// These are stored in the Secret Service, keyed by the normalized remote url.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    username: String,
+    token: String,
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Credentials {
+    pub fn new(username: &str, token: &str) -> anyhow::Result<Self> {
+        // This is not allowed in basic authentication anyway
+        anyhow::ensure!(
+            !username.contains(':'),
+            "Username `{}` must not contain `:`",
+            username
+        );
+
+        Ok(Self {
+            username: username.to_string(),
+            token: token.to_string(),
+        })
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Look up the credentials stored for `remote_url`
+    pub fn lookup(remote_url: &RemoteUrl) -> anyhow::Result<Option<Self>> {
+        match Self::entry(remote_url)?.get_password() {
+            Ok(secret) => Ok(Some(Self::from_secret(&secret)?)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Store the credentials for `remote_url`, replacing the existing ones
+    pub fn store(&self, remote_url: &RemoteUrl) -> anyhow::Result<()> {
+        Self::entry(remote_url)?.set_password(&self.to_secret())?;
+        log::info!("Stored credentials for `{}`", remote_url);
+        Ok(())
+    }
+
+    fn entry(remote_url: &RemoteUrl) -> anyhow::Result<keyring::Entry> {
+        Ok(keyring::Entry::new(APP_ID, &remote_url.to_string())?)
+    }
+
+    fn to_secret(&self) -> String {
+        format!("{}:{}", self.username, self.token)
+    }
+
+    fn from_secret(secret: &str) -> anyhow::Result<Self> {
+        let (username, token) = secret
+            .split_once(':')
+            .ok_or_else(|| anyhow::anyhow!("Stored credentials are malformed"))?;
+        Self::new(username, token)
+    }
+}
+
+pub fn lookup_ssh_key_passphrase(key_path: &Path) -> anyhow::Result<Option<String>> {
+    match ssh_key_passphrase_entry(key_path)?.get_password() {
+        Ok(passphrase) => Ok(Some(passphrase)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub fn store_ssh_key_passphrase(key_path: &Path, passphrase: &str) -> anyhow::Result<()> {
+    ssh_key_passphrase_entry(key_path)?.set_password(passphrase)?;
+    log::info!("Stored passphrase for `{}`", key_path.display());
+    Ok(())
+}
+
+fn ssh_key_passphrase_entry(key_path: &Path) -> anyhow::Result<keyring::Entry> {
+    let user = format!("ssh-key:{}", key_path.display());
+    Ok(keyring::Entry::new(APP_ID, &user)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn secret() {
+        let credentials = Credentials::new("user", "ghp_token").unwrap();
+        assert_eq!(credentials.to_secret(), "user:ghp_token");
+        assert_eq!(
+            Credentials::from_secret("user:ghp_token").unwrap(),
+            credentials
+        );
+    }
+
+    #[test]
+    fn secret_with_colon_in_token() {
+        let credentials = Credentials::from_secret("user:to:ken").unwrap();
+        assert_eq!(credentials.username(), "user");
+        assert_eq!(credentials.token(), "to:ken");
+    }
+
+    #[test]
+    fn malformed_secret() {
+        assert!(Credentials::from_secret("token").is_err());
+    }
+
+    #[test]
+    fn username_with_colon() {
+        assert!(Credentials::new("us:er", "token").is_err());
+    }
+
+    #[test]
+    fn debug_hides_token() {
+        let credentials = Credentials::new("user", "ghp_token").unwrap();
+        assert!(!format!("{:?}", credentials).contains("ghp_token"));
+    }
+}
@@ -0,0 +1,253 @@
+//! Differences between two versions of a note, to show what was changed.
+//!
+//! The markdown body is compared line by line, while the front matter is summarized into the
+//! changes that matter to the user, as the raw YAML is never shown.
+
+use super::merge::{lcs_matches, RawNote, RawNoteMetadata};
+
+/// How a line of the body was changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineChange {
+    Unchanged,
+    Added,
+    Removed,
+}
+
+/// A line of the body, without the line break
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffLine {
+    pub change: LineChange,
+    pub text: String,
+}
+
+/// A change on the front matter of a note
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataChange {
+    TitleChanged { old: String, new: String },
+    TagsAdded(Vec<String>),
+    TagsRemoved(Vec<String>),
+    Pinned,
+    Unpinned,
+    Trashed,
+    Restored,
+    AttachmentsAdded(usize),
+    AttachmentsRemoved(usize),
+}
+
+/// What was changed from one version of a note to another
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoteDiff {
+    pub metadata_changes: Vec<MetadataChange>,
+    pub lines: Vec<DiffLine>,
+}
+
+impl NoteDiff {
+    pub fn new(old: &RawNote, new: &RawNote) -> Self {
+        Self {
+            metadata_changes: diff_metadata(&old.metadata, &new.metadata),
+            lines: diff_lines(&old.body, &new.body),
+        }
+    }
+
+    /// Compare the unparsed texts of two versions. A missing `old` is treated as an empty note,
+    /// e.g., when the note was just created.
+    pub fn from_texts(old: Option<&str>, new: &str) -> anyhow::Result<Self> {
+        let old = old.map(RawNote::parse).transpose()?.unwrap_or_default();
+        let new = RawNote::parse(new)?;
+        Ok(Self::new(&old, &new))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.metadata_changes.is_empty()
+            && self
+                .lines
+                .iter()
+                .all(|line| line.change == LineChange::Unchanged)
+    }
+}
+
+/// Compare `old` and `new` line by line, with removed lines coming before the lines that
+/// replaced them
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let old_lines = old.lines().collect::<Vec<_>>();
+    let new_lines = new.lines().collect::<Vec<_>>();
+    let matches = lcs_matches(&old_lines, &new_lines);
+
+    let line = |change, text: &str| DiffLine {
+        change,
+        text: text.to_string(),
+    };
+
+    let mut lines = Vec::with_capacity(old_lines.len().max(new_lines.len()));
+    let mut j = 0;
+
+    for (old_line, matched) in old_lines.iter().zip(&matches) {
+        match *matched {
+            Some(matched) => {
+                lines.extend(
+                    new_lines[j..matched]
+                        .iter()
+                        .map(|new_line| line(LineChange::Added, new_line)),
+                );
+                lines.push(line(LineChange::Unchanged, old_line));
+                j = matched + 1;
+            }
+            None => lines.push(line(LineChange::Removed, old_line)),
+        }
+    }
+
+    lines.extend(
+        new_lines[j..]
+            .iter()
+            .map(|new_line| line(LineChange::Added, new_line)),
+    );
+
+    lines
+}
+
+fn diff_metadata(old: &RawNoteMetadata, new: &RawNoteMetadata) -> Vec<MetadataChange> {
+    let mut changes = Vec::new();
+
+    if old.title != new.title {
+        changes.push(MetadataChange::TitleChanged {
+            old: old.title.clone(),
+            new: new.title.clone(),
+        });
+    }
+
+    let added_tags = difference(&new.tag_list, &old.tag_list);
+    if !added_tags.is_empty() {
+        changes.push(MetadataChange::TagsAdded(added_tags));
+    }
+    let removed_tags = difference(&old.tag_list, &new.tag_list);
+    if !removed_tags.is_empty() {
+        changes.push(MetadataChange::TagsRemoved(removed_tags));
+    }
+
+    match (old.is_pinned, new.is_pinned) {
+        (false, true) => changes.push(MetadataChange::Pinned),
+        (true, false) => changes.push(MetadataChange::Unpinned),
+        _ => {}
+    }
+
+    match (old.is_trashed, new.is_trashed) {
+        (false, true) => changes.push(MetadataChange::Trashed),
+        (true, false) => changes.push(MetadataChange::Restored),
+        _ => {}
+    }
+
+    // Attachments are told apart by their file, so renaming one is not counted as a change
+    let old_files = old
+        .attachment_list
+        .iter()
+        .map(attachment_file)
+        .collect::<Vec<_>>();
+    let new_files = new
+        .attachment_list
+        .iter()
+        .map(attachment_file)
+        .collect::<Vec<_>>();
+
+    let n_added = difference(&new_files, &old_files).len();
+    if n_added > 0 {
+        changes.push(MetadataChange::AttachmentsAdded(n_added));
+    }
+    let n_removed = difference(&old_files, &new_files).len();
+    if n_removed > 0 {
+        changes.push(MetadataChange::AttachmentsRemoved(n_removed));
+    }
+
+    changes
+}
+
+/// Items of `a` that are not in `b`, in the order of `a`
+fn difference<T: PartialEq + Clone>(a: &[T], b: &[T]) -> Vec<T> {
+    a.iter().filter(|item| !b.contains(item)).cloned().collect()
+}
+
+fn attachment_file(attachment: &serde_yaml::Value) -> &serde_yaml::Value {
+    attachment.get("file").unwrap_or(attachment)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn changes(lines: &[DiffLine]) -> Vec<(LineChange, &str)> {
+        lines
+            .iter()
+            .map(|line| (line.change, line.text.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn lines_changed() {
+        let lines = diff_lines("a\nb\nc\nd\n", "a\nB\nc\nd\ne\n");
+        assert_eq!(
+            changes(&lines),
+            vec![
+                (LineChange::Unchanged, "a"),
+                (LineChange::Removed, "b"),
+                (LineChange::Added, "B"),
+                (LineChange::Unchanged, "c"),
+                (LineChange::Unchanged, "d"),
+                (LineChange::Added, "e"),
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_from_empty() {
+        let lines = diff_lines("", "a\nb");
+        assert_eq!(
+            changes(&lines),
+            vec![(LineChange::Added, "a"), (LineChange::Added, "b")]
+        );
+
+        let lines = diff_lines("a\n", "");
+        assert_eq!(changes(&lines), vec![(LineChange::Removed, "a")]);
+    }
+
+    #[test]
+    fn metadata_changed() {
+        let old = "title: Groceries\ntag_list: [Food, Errands]\nattachment_list:\n  - file: /a.ogg\n    title: A\nis_pinned: false\nis_trashed: false\n---\nMilk\n";
+        let new = "title: Shopping\ntag_list: [Food, Home]\nattachment_list:\n  - file: /a.ogg\n    title: Renamed\n  - file: /b.png\nis_pinned: true\nis_trashed: false\n---\nMilk\n";
+
+        let diff = NoteDiff::from_texts(Some(old), new).unwrap();
+        assert_eq!(
+            diff.metadata_changes,
+            vec![
+                MetadataChange::TitleChanged {
+                    old: "Groceries".into(),
+                    new: "Shopping".into()
+                },
+                MetadataChange::TagsAdded(vec!["Home".into()]),
+                MetadataChange::TagsRemoved(vec!["Errands".into()]),
+                MetadataChange::Pinned,
+                MetadataChange::AttachmentsAdded(1),
+            ]
+        );
+        assert!(!diff.is_empty());
+    }
+
+    #[test]
+    fn unchanged() {
+        let text = "title: Note\n---\nBody\n";
+        let diff = NoteDiff::from_texts(Some(text), text).unwrap();
+        assert!(diff.metadata_changes.is_empty());
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn new_note() {
+        let diff = NoteDiff::from_texts(None, "title: Note\n---\nBody\n").unwrap();
+        assert_eq!(
+            diff.metadata_changes,
+            vec![MetadataChange::TitleChanged {
+                old: String::new(),
+                new: "Note".into()
+            }]
+        );
+        assert_eq!(changes(&diff.lines), vec![(LineChange::Added, "Body")]);
+    }
+}
@@ -0,0 +1,106 @@
+use super::commit_message;
+
+const DEFAULT_AUTHOR_NAME: &str = "NoteworthyApp";
+const DEFAULT_AUTHOR_EMAIL: &str = "app@noteworthy.io";
+
+/// Who makes the commits, and on which device
+///
+/// The author fields override the `user.name` and `user.email` of the git config when set.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub author_name: Option<String>,
+    pub author_email: Option<String>,
+    pub device_name: Option<String>,
+}
+
+impl Identity {
+    /// Resolve the author from this, then from `config`, then from the app defaults
+    pub fn signature(&self, config: &git2::Config) -> anyhow::Result<git2::Signature<'static>> {
+        let resolve = |value: &Option<String>, key: &str, default: &str| {
+            value
+                .clone()
+                .filter(|value| !value.trim().is_empty())
+                .or_else(|| config.get_string(key).ok())
+                .filter(|value| !value.trim().is_empty())
+                .unwrap_or_else(|| default.to_string())
+        };
+
+        let name = resolve(&self.author_name, "user.name", DEFAULT_AUTHOR_NAME);
+        let email = resolve(&self.author_email, "user.email", DEFAULT_AUTHOR_EMAIL);
+
+        Ok(git2::Signature::now(&name, &email)?)
+    }
+
+    /// Add the device name to `message`, so it can be told where the commit was made
+    pub fn sign_message(&self, message: &str) -> String {
+        match self.device_name.as_deref() {
+            Some(device_name) if !device_name.trim().is_empty() => {
+                commit_message::with_device(message, device_name.trim())
+            }
+            _ => message.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(entries: &[(&str, &str)]) -> (temp_dir::TempDir, git2::Config) {
+        let dir = temp_dir::TempDir::new().unwrap();
+        let mut config = git2::Config::open(&dir.path().join("config")).unwrap();
+        for (key, value) in entries {
+            config.set_str(key, value).unwrap();
+        }
+        (dir, config)
+    }
+
+    #[test]
+    fn signature_from_config() {
+        let (_dir, config) = config(&[("user.name", "Dave"), ("user.email", "dave@example.com")]);
+
+        let signature = Identity::default().signature(&config).unwrap();
+        assert_eq!(signature.name(), Some("Dave"));
+        assert_eq!(signature.email(), Some("dave@example.com"));
+    }
+
+    #[test]
+    fn signature_overrides_config() {
+        let (_dir, config) = config(&[("user.name", "Dave"), ("user.email", "dave@example.com")]);
+
+        let identity = Identity {
+            author_name: Some("Patrick".into()),
+            author_email: Some("  ".into()),
+            ..Default::default()
+        };
+        let signature = identity.signature(&config).unwrap();
+        assert_eq!(signature.name(), Some("Patrick"));
+        assert_eq!(signature.email(), Some("dave@example.com"));
+    }
+
+    #[test]
+    fn signature_default() {
+        let (_dir, config) = config(&[]);
+
+        let signature = Identity::default().signature(&config).unwrap();
+        assert_eq!(signature.name(), Some(DEFAULT_AUTHOR_NAME));
+        assert_eq!(signature.email(), Some(DEFAULT_AUTHOR_EMAIL));
+    }
+
+    #[test]
+    fn sign_message() {
+        let identity = Identity {
+            device_name: Some("Laptop".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            commit_message::device(&identity.sign_message("Edit 'Note'")),
+            Some("Laptop")
+        );
+
+        assert_eq!(
+            Identity::default().sign_message("Edit 'Note'"),
+            "Edit 'Note'"
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use std::path::{Path, PathBuf};
+
+/// Files that other apps leave in the notes directory, which are never worth syncing
+pub const DEFAULT_PATTERNS: &[&str] = &[
+    ".DS_Store",
+    "._*",
+    "Thumbs.db",
+    "desktop.ini",
+    ".thumbnails/",
+    ".Trash-*/",
+    "*.swp",
+    "*.swo",
+    "*~",
+    ".#*",
+    "*.tmp",
+];
+
+/// Part of an ignore file that is written by the app, between a start and an end marker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    /// [`DEFAULT_PATTERNS`] in the synced `.gitignore`
+    DefaultPatterns,
+    /// Patterns chosen by the user, in `info/exclude`, so they only apply on this device
+    UserPatterns,
+    /// Notes kept out of sync on this device, in `info/exclude`
+    LocalOnlyNotes,
+}
+
+impl Section {
+    const fn start_marker(self) -> &'static str {
+        match self {
+            Self::DefaultPatterns => {
+                "# Managed by Noteworthy. Lines until the end marker are overwritten."
+            }
+            Self::UserPatterns => "# Ignored by Noteworthy on this device",
+            Self::LocalOnlyNotes => "# Notes kept on this device only by Noteworthy",
+        }
+    }
+
+    const fn end_marker(self) -> &'static str {
+        match self {
+            Self::DefaultPatterns => "# End of lines managed by Noteworthy",
+            Self::UserPatterns => "# End of lines ignored by Noteworthy on this device",
+            Self::LocalOnlyNotes => "# End of notes kept on this device only by Noteworthy",
+        }
+    }
+}
+
+/// Patterns in `section` of the ignore file `content`
+pub fn managed_patterns(content: &str, section: Section) -> Vec<String> {
+    content
+        .lines()
+        .skip_while(|line| *line != section.start_marker())
+        .skip(1)
+        .take_while(|line| *line != section.end_marker())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Replace `section` of the ignore file `content` with `patterns`, keeping the other lines. The
+/// section is appended if there is none yet.
+pub fn with_managed_patterns(content: &str, section: Section, patterns: &[String]) -> String {
+    let lines = content.lines().collect::<Vec<_>>();
+    let start = lines
+        .iter()
+        .position(|line| *line == section.start_marker());
+    let end = start.and_then(|start| {
+        lines[start..]
+            .iter()
+            .position(|line| *line == section.end_marker())
+            .map(|end| start + end)
+    });
+
+    let (before, after) = match (start, end) {
+        (Some(start), Some(end)) => (&lines[..start], &lines[end + 1..]),
+        // A section without end runs until the end of the file
+        (Some(start), None) => (&lines[..start], &lines[lines.len()..]),
+        _ => (&lines[..], &lines[lines.len()..]),
+    };
+
+    let mut new_lines = before.to_vec();
+    if start.is_none() && !matches!(new_lines.last(), None | Some(&"")) {
+        new_lines.push("");
+    }
+    new_lines.push(section.start_marker());
+    new_lines.extend(patterns.iter().map(String::as_str));
+    new_lines.push(section.end_marker());
+    new_lines.extend(after);
+
+    let mut new_content = new_lines.join("\n");
+    new_content.push('\n');
+    new_content
+}
+
+/// Pattern that matches only the file at `path`, relative to the repository
+pub fn literal_pattern(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    let mut pattern = String::with_capacity(path.len() + 1);
+
+    // Anchored, so it is not matched in subdirectories, nor read as a comment or a negation
+    pattern.push('/');
+    for c in path.chars() {
+        if matches!(c, '\\' | '*' | '?' | '[' | ' ') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+
+    pattern
+}
+
+/// Path matched by a pattern made by [`literal_pattern`], or `None` if it is another kind of
+/// pattern
+pub fn literal_path(pattern: &str) -> Option<PathBuf> {
+    let escaped = pattern.strip_prefix('/')?;
+    let mut path = String::with_capacity(escaped.len());
+
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => path.push(chars.next()?),
+            '*' | '?' | '[' => return None,
+            c => path.push(c),
+        }
+    }
+
+    Some(PathBuf::from(path))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    const SECTION: Section = Section::DefaultPatterns;
+
+    #[test]
+    fn managed_section_is_appended() {
+        let content = with_managed_patterns("", SECTION, &patterns(&["*.swp"]));
+        assert_eq!(
+            content,
+            format!(
+                "{}\n*.swp\n{}\n",
+                SECTION.start_marker(),
+                SECTION.end_marker()
+            )
+        );
+        assert_eq!(managed_patterns(&content, SECTION), patterns(&["*.swp"]));
+
+        let content = with_managed_patterns("build/\n", SECTION, &patterns(&["*.swp"]));
+        assert_eq!(
+            content,
+            format!(
+                "build/\n\n{}\n*.swp\n{}\n",
+                SECTION.start_marker(),
+                SECTION.end_marker()
+            )
+        );
+    }
+
+    #[test]
+    fn managed_section_is_replaced() {
+        let content = format!(
+            "build/\n{}\n*.swp\n{}\n!keep.tmp\n",
+            SECTION.start_marker(),
+            SECTION.end_marker()
+        );
+        let new_content = with_managed_patterns(&content, SECTION, &patterns(&["*.tmp", "*~"]));
+        assert_eq!(
+            new_content,
+            format!(
+                "build/\n{}\n*.tmp\n*~\n{}\n!keep.tmp\n",
+                SECTION.start_marker(),
+                SECTION.end_marker()
+            )
+        );
+        assert_eq!(
+            managed_patterns(&new_content, SECTION),
+            patterns(&["*.tmp", "*~"])
+        );
+
+        // Writing the same patterns again changes nothing
+        assert_eq!(
+            with_managed_patterns(&new_content, SECTION, &patterns(&["*.tmp", "*~"])),
+            new_content
+        );
+    }
+
+    #[test]
+    fn sections_are_separate() {
+        let content = with_managed_patterns("", Section::UserPatterns, &patterns(&["*.bak"]));
+        let content =
+            with_managed_patterns(&content, Section::LocalOnlyNotes, &patterns(&["/Note.md"]));
+        assert_eq!(
+            managed_patterns(&content, Section::UserPatterns),
+            patterns(&["*.bak"])
+        );
+        assert_eq!(
+            managed_patterns(&content, Section::LocalOnlyNotes),
+            patterns(&["/Note.md"])
+        );
+
+        let content = with_managed_patterns(&content, Section::UserPatterns, &[]);
+        assert!(managed_patterns(&content, Section::UserPatterns).is_empty());
+        assert_eq!(
+            managed_patterns(&content, Section::LocalOnlyNotes),
+            patterns(&["/Note.md"])
+        );
+    }
+
+    #[test]
+    fn literal_patterns() {
+        let path = Path::new("Notes/Note [1] *draft*.md");
+        let pattern = literal_pattern(path);
+        assert_eq!(pattern, "/Notes/Note\\ \\[1]\\ \\*draft\\*.md");
+        assert_eq!(literal_path(&pattern).as_deref(), Some(path));
+
+        assert_eq!(literal_path("*.swp"), None);
+        assert_eq!(literal_path("/*.swp"), None);
+        assert_eq!(
+            literal_path("/#Note.md").as_deref(),
+            Some(Path::new("#Note.md"))
+        );
+    }
+}
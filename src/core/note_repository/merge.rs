@@ -0,0 +1,668 @@
+//! Three-way merge of the files stored in the notes repository.
+//!
+//! Notes are merged field by field on their front matter and line by line on their markdown body,
+//! so edits made on different devices are combined instead of one side being thrown away. Hunks
+//! that overlap end up as conflict markers in the body, and front matter fields that were changed
+//! on both sides are reported so the caller can surface them.
+
+use serde::{Deserialize, Serialize};
+
+use std::{collections::HashSet, ops::Range, path::PathBuf};
+
+const FRONT_MATTER_DELIMITER: &str = "---";
+
+pub const OURS_MARKER: &str = "<<<<<<< ours";
+pub const SEPARATOR_MARKER: &str = "=======";
+pub const THEIRS_MARKER: &str = ">>>>>>> theirs";
+
+/// A section of a merged text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextChunk {
+    /// Lines that are either unchanged or changed only on one side
+    Resolved(String),
+    /// Lines that were changed differently on both sides
+    Conflict { ours: String, theirs: String },
+}
+
+impl TextChunk {
+    pub const fn is_conflict(&self) -> bool {
+        matches!(self, Self::Conflict { .. })
+    }
+}
+
+/// Metadata as it is stored in the front matter of a note.
+///
+/// This mirrors `NoteMetadata` but only uses plain types, so it can be used on any thread and
+/// without a loaded `TagList`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RawNoteMetadata {
+    pub title: String,
+    pub tag_list: Vec<String>,
+    pub attachment_list: Vec<serde_yaml::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<chrono::DateTime<chrono::FixedOffset>>,
+    pub is_pinned: bool,
+    pub is_trashed: bool,
+    #[serde(flatten)]
+    pub other: serde_yaml::Mapping,
+}
+
+/// A note split into its front matter and markdown body
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RawNote {
+    pub metadata: RawNoteMetadata,
+    pub body: String,
+    has_leading_delimiter: bool,
+}
+
+impl RawNote {
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let (front_matter, body, has_leading_delimiter) = split_front_matter(text);
+
+        let metadata = match front_matter {
+            Some(front_matter) if !front_matter.trim().is_empty() => {
+                serde_yaml::from_str(front_matter)?
+            }
+            _ => RawNoteMetadata::default(),
+        };
+
+        Ok(Self {
+            metadata,
+            body: body.to_string(),
+            has_leading_delimiter,
+        })
+    }
+
+    /// Serialize the same way as `Note::save`
+    pub fn to_text(&self) -> anyhow::Result<String> {
+        let mut text = String::new();
+
+        if self.has_leading_delimiter {
+            text.push_str(FRONT_MATTER_DELIMITER);
+            text.push('\n');
+        }
+
+        text.push_str(&serde_yaml::to_string(&self.metadata)?);
+        text.push_str(FRONT_MATTER_DELIMITER);
+        text.push('\n');
+        text.push_str(&self.body);
+
+        Ok(text)
+    }
+}
+
+/// The result of merging a note
+#[derive(Debug)]
+pub struct NoteMergeOutcome {
+    /// The merged note, with conflict markers on conflicting body hunks
+    pub note: RawNote,
+    /// Names of front matter fields that were changed differently on both sides. These took the
+    /// value from the side that was modified most recently.
+    pub conflicting_fields: Vec<&'static str>,
+    pub body_chunks: Vec<TextChunk>,
+}
+
+impl NoteMergeOutcome {
+    pub fn has_body_conflict(&self) -> bool {
+        self.body_chunks.iter().any(TextChunk::is_conflict)
+    }
+
+    pub fn is_conflicted(&self) -> bool {
+        self.has_body_conflict() || !self.conflicting_fields.is_empty()
+    }
+}
+
+/// A note that was changed on both sides in a way that needs the user to decide
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NoteConflict {
+    /// Path of the note relative to the repository
+    pub path: PathBuf,
+    pub base: Option<String>,
+    pub ours: String,
+    pub theirs: String,
+}
+
+impl NoteConflict {
+    pub fn merge(&self) -> anyhow::Result<NoteMergeOutcome> {
+        merge_note(self.base.as_deref(), &self.ours, &self.theirs)
+    }
+
+    pub fn ours_note(&self) -> anyhow::Result<RawNote> {
+        RawNote::parse(&self.ours)
+    }
+
+    pub fn theirs_note(&self) -> anyhow::Result<RawNote> {
+        RawNote::parse(&self.theirs)
+    }
+
+    /// Merge only the bodies, which works even when the front matter of a side does not parse
+    pub fn merge_bodies(&self) -> Vec<TextChunk> {
+        let body = |text: &str| split_front_matter(text).1.to_string();
+
+        merge_text(
+            &self.base.as_deref().map(body).unwrap_or_default(),
+            &body(&self.ours),
+            &body(&self.theirs),
+        )
+    }
+}
+
+/// Merge two versions of a note that both derive from `base`.
+///
+/// When the note was added on both sides, `base` is `None`.
+pub fn merge_note(
+    base: Option<&str>,
+    ours: &str,
+    theirs: &str,
+) -> anyhow::Result<NoteMergeOutcome> {
+    let base = base.map(RawNote::parse).transpose()?.unwrap_or_default();
+    let ours = RawNote::parse(ours)?;
+    let theirs = RawNote::parse(theirs)?;
+
+    let (metadata, conflicting_fields) =
+        merge_metadata(&base.metadata, &ours.metadata, &theirs.metadata);
+
+    let body_chunks = merge_text(&base.body, &ours.body, &theirs.body);
+
+    Ok(NoteMergeOutcome {
+        note: RawNote {
+            metadata,
+            body: render_with_markers(&body_chunks),
+            has_leading_delimiter: ours.has_leading_delimiter,
+        },
+        conflicting_fields,
+        body_chunks,
+    })
+}
+
+/// Merge the metadata field by field.
+///
+/// Returns the merged metadata and the fields that were changed on both sides.
+pub fn merge_metadata(
+    base: &RawNoteMetadata,
+    ours: &RawNoteMetadata,
+    theirs: &RawNoteMetadata,
+) -> (RawNoteMetadata, Vec<&'static str>) {
+    let mut conflicting_fields = Vec::new();
+    let is_ours_newer = ours.last_modified >= theirs.last_modified;
+
+    macro_rules! merge_field {
+        ($field:ident) => {
+            merge_value(&base.$field, &ours.$field, &theirs.$field).unwrap_or_else(|| {
+                conflicting_fields.push(stringify!($field));
+                if is_ours_newer {
+                    ours.$field.clone()
+                } else {
+                    theirs.$field.clone()
+                }
+            })
+        };
+    }
+
+    let title = merge_field!(title);
+    let is_pinned = merge_field!(is_pinned);
+    let is_trashed = merge_field!(is_trashed);
+
+    let tag_list = merge_set(&base.tag_list, &ours.tag_list, &theirs.tag_list, |tag| {
+        tag.clone()
+    });
+    let attachment_list = merge_set(
+        &base.attachment_list,
+        &ours.attachment_list,
+        &theirs.attachment_list,
+        |attachment| attachment.get("file").cloned().unwrap_or_default(),
+    );
+
+    let mut other = ours.other.clone();
+    for (key, value) in &theirs.other {
+        if !other.contains_key(key) {
+            other.insert(key.clone(), value.clone());
+        }
+    }
+
+    let metadata = RawNoteMetadata {
+        title,
+        tag_list,
+        attachment_list,
+        last_modified: ours.last_modified.max(theirs.last_modified),
+        is_pinned,
+        is_trashed,
+        other,
+    };
+
+    (metadata, conflicting_fields)
+}
+
+/// Merge the list of tags of two `data.nwty` files.
+///
+/// Tags are only removed when one side removed them and the other left them untouched.
+pub fn merge_data_file(base: Option<&str>, ours: &str, theirs: &str) -> anyhow::Result<String> {
+    fn tag_list(text: &str) -> anyhow::Result<(serde_yaml::Mapping, Vec<String>)> {
+        let mut mapping: serde_yaml::Mapping = serde_yaml::from_str(text)?;
+        let tag_list = mapping
+            .remove("tag_list")
+            .map(serde_yaml::from_value)
+            .transpose()?
+            .unwrap_or_default();
+        Ok((mapping, tag_list))
+    }
+
+    let (_, base_tags) = base.map(tag_list).transpose()?.unwrap_or_default();
+    let (mut mapping, our_tags) = tag_list(ours)?;
+    let (_, their_tags) = tag_list(theirs)?;
+
+    let merged_tags = merge_set(&base_tags, &our_tags, &their_tags, Clone::clone);
+    mapping.insert("tag_list".into(), serde_yaml::to_value(merged_tags)?);
+
+    Ok(serde_yaml::to_string(&mapping)?)
+}
+
+/// Merge a text line by line, the same way as diff3.
+pub fn merge_text(base: &str, ours: &str, theirs: &str) -> Vec<TextChunk> {
+    let base_lines = base.split_inclusive('\n').collect::<Vec<_>>();
+    let our_lines = ours.split_inclusive('\n').collect::<Vec<_>>();
+    let their_lines = theirs.split_inclusive('\n').collect::<Vec<_>>();
+
+    let our_matches = lcs_matches(&base_lines, &our_lines);
+    let their_matches = lcs_matches(&base_lines, &their_lines);
+
+    let mut chunks = Vec::new();
+    let mut resolved = String::new();
+
+    let (mut i, mut i_ours, mut i_theirs) = (0, 0, 0);
+
+    loop {
+        // Lines that are unchanged on both sides
+        while i < base_lines.len()
+            && our_matches[i] == Some(i_ours)
+            && their_matches[i] == Some(i_theirs)
+        {
+            resolved.push_str(base_lines[i]);
+            i += 1;
+            i_ours += 1;
+            i_theirs += 1;
+        }
+
+        if i == base_lines.len() && i_ours == our_lines.len() && i_theirs == their_lines.len() {
+            break;
+        }
+
+        // Find the next line that is unchanged on both sides to synchronize on
+        let next_stable =
+            (i..base_lines.len()).find(|&j| our_matches[j].is_some() && their_matches[j].is_some());
+        let (j, j_ours, j_theirs) = match next_stable {
+            Some(j) => (j, our_matches[j].unwrap(), their_matches[j].unwrap()),
+            None => (base_lines.len(), our_lines.len(), their_lines.len()),
+        };
+
+        let base_hunk = &base_lines[i..j];
+        let our_hunk = &our_lines[i_ours..j_ours];
+        let their_hunk = &their_lines[i_theirs..j_theirs];
+
+        if our_hunk == base_hunk || our_hunk == their_hunk {
+            resolved.extend(their_hunk.iter().copied());
+        } else if their_hunk == base_hunk {
+            resolved.extend(our_hunk.iter().copied());
+        } else {
+            if !resolved.is_empty() {
+                chunks.push(TextChunk::Resolved(std::mem::take(&mut resolved)));
+            }
+            chunks.push(TextChunk::Conflict {
+                ours: our_hunk.concat(),
+                theirs: their_hunk.concat(),
+            });
+        }
+
+        i = j;
+        i_ours = j_ours;
+        i_theirs = j_theirs;
+    }
+
+    if !resolved.is_empty() {
+        chunks.push(TextChunk::Resolved(resolved));
+    }
+
+    chunks
+}
+
+/// Join the chunks, putting conflict markers around the conflicting ones
+pub fn render_with_markers(chunks: &[TextChunk]) -> String {
+    fn push_line(text: &mut String, line: &str) {
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push_str(line);
+        text.push('\n');
+    }
+
+    let mut text = String::new();
+
+    for chunk in chunks {
+        match chunk {
+            TextChunk::Resolved(resolved) => text.push_str(resolved),
+            TextChunk::Conflict { ours, theirs } => {
+                push_line(&mut text, OURS_MARKER);
+                text.push_str(ours);
+                push_line(&mut text, SEPARATOR_MARKER);
+                text.push_str(theirs);
+                push_line(&mut text, THEIRS_MARKER);
+            }
+        }
+    }
+
+    text
+}
+
+/// Returns `None` when both sides changed the value differently
+fn merge_value<T: PartialEq + Clone>(base: &T, ours: &T, theirs: &T) -> Option<T> {
+    if ours == theirs || theirs == base {
+        Some(ours.clone())
+    } else if ours == base {
+        Some(theirs.clone())
+    } else {
+        None
+    }
+}
+
+/// Keeps items that are on both sides or are newly added on either side. Items are compared
+/// using the key returned by `key_fn`, and items from our side take precedence.
+fn merge_set<T: Clone, K: Eq + std::hash::Hash>(
+    base: &[T],
+    ours: &[T],
+    theirs: &[T],
+    key_fn: impl Fn(&T) -> K,
+) -> Vec<T> {
+    let base_keys = base.iter().map(&key_fn).collect::<HashSet<_>>();
+    let our_keys = ours.iter().map(&key_fn).collect::<HashSet<_>>();
+    let their_keys = theirs.iter().map(&key_fn).collect::<HashSet<_>>();
+
+    let mut merged_keys = HashSet::new();
+    let mut merged = Vec::new();
+
+    for item in ours.iter().chain(theirs) {
+        let key = key_fn(item);
+
+        let is_on_both = our_keys.contains(&key) && their_keys.contains(&key);
+        if (is_on_both || !base_keys.contains(&key)) && !merged_keys.contains(&key) {
+            merged.push(item.clone());
+            merged_keys.insert(key);
+        }
+    }
+
+    merged
+}
+
+/// For each item in `a`, the index of its matching item in `b` within their longest common
+/// subsequence
+pub(super) fn lcs_matches<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; a.len()];
+
+    // Common prefix and suffix are matched directly to keep the table small
+    let prefix_len = a.iter().zip(b).take_while(|(a, b)| a == b).count();
+    let suffix_len = a[prefix_len..]
+        .iter()
+        .rev()
+        .zip(b[prefix_len..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    for (i, item) in matches.iter_mut().enumerate().take(prefix_len) {
+        *item = Some(i);
+    }
+    for k in 0..suffix_len {
+        matches[a.len() - 1 - k] = Some(b.len() - 1 - k);
+    }
+
+    let a_mid = &a[prefix_len..a.len() - suffix_len];
+    let b_mid = &b[prefix_len..b.len() - suffix_len];
+
+    // lengths[i][j] is the length of the LCS of a_mid[i..] and b_mid[j..]
+    let width = b_mid.len() + 1;
+    let mut lengths = vec![0_u32; (a_mid.len() + 1) * width];
+    for i in (0..a_mid.len()).rev() {
+        for j in (0..b_mid.len()).rev() {
+            lengths[i * width + j] = if a_mid[i] == b_mid[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < a_mid.len() && j < b_mid.len() {
+        if a_mid[i] == b_mid[j] {
+            matches[prefix_len + i] = Some(prefix_len + j);
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    matches
+}
+
+fn split_front_matter(text: &str) -> (Option<&str>, &str, bool) {
+    let leading = format!("{}\n", FRONT_MATTER_DELIMITER);
+    let (front_matter_start, has_leading_delimiter) = if text.starts_with(&leading) {
+        (leading.len(), true)
+    } else {
+        (0, false)
+    };
+
+    let mut offset = front_matter_start;
+    for line in text[front_matter_start..].split_inclusive('\n') {
+        if line.trim_end() == FRONT_MATTER_DELIMITER {
+            let front_matter = &text[front_matter_start..offset];
+            let body = &text[offset + line.len()..];
+            return (Some(front_matter), body, has_leading_delimiter);
+        }
+        offset += line.len();
+    }
+
+    (None, text, false)
+}
+
+/// Byte range of the first conflict between `ours` and `theirs` in `text`, markers included, as
+/// it is rendered by [`render_with_markers`]. This is `None` if the conflict is not in the text
+/// anymore, e.g., when it was edited by hand.
+pub fn find_conflict(text: &str, ours: &str, theirs: &str) -> Option<Range<usize>> {
+    let block = render_with_markers(&[TextChunk::Conflict {
+        ours: ours.to_string(),
+        theirs: theirs.to_string(),
+    }]);
+
+    text.match_indices(&block)
+        .map(|(start, _)| start)
+        .find(|&start| start == 0 || text[..start].ends_with('\n'))
+        .map(|start| start..start + block.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn text_changed_on_one_side() {
+        let chunks = merge_text("a\nb\nc\n", "a\nB\nc\n", "a\nb\nc\n");
+        assert_eq!(chunks, vec![TextChunk::Resolved("a\nB\nc\n".into())]);
+
+        let chunks = merge_text("a\nb\nc\n", "a\nb\nc\n", "a\nb\nc\nd\n");
+        assert_eq!(chunks, vec![TextChunk::Resolved("a\nb\nc\nd\n".into())]);
+    }
+
+    #[test]
+    fn text_changed_on_both_sides_without_overlap() {
+        let chunks = merge_text("a\nb\nc\nd\ne\n", "A\nb\nc\nd\ne\n", "a\nb\nc\nd\nE\n");
+        assert_eq!(chunks, vec![TextChunk::Resolved("A\nb\nc\nd\nE\n".into())]);
+    }
+
+    #[test]
+    fn text_changed_the_same_on_both_sides() {
+        let chunks = merge_text("a\nb\n", "a\nB\n", "a\nB\n");
+        assert_eq!(chunks, vec![TextChunk::Resolved("a\nB\n".into())]);
+    }
+
+    #[test]
+    fn text_conflict() {
+        let chunks = merge_text("a\nb\nc\n", "a\nours\nc\n", "a\ntheirs\nc\n");
+        assert_eq!(
+            chunks,
+            vec![
+                TextChunk::Resolved("a\n".into()),
+                TextChunk::Conflict {
+                    ours: "ours\n".into(),
+                    theirs: "theirs\n".into()
+                },
+                TextChunk::Resolved("c\n".into()),
+            ]
+        );
+        assert_eq!(
+            render_with_markers(&chunks),
+            "a\n<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\nc\n"
+        );
+    }
+
+    #[test]
+    fn conflict_with_broken_front_matter() {
+        let conflict = NoteConflict {
+            path: PathBuf::from("note.md"),
+            base: Some("---\ntitle: Title\n---\na\nb\n".into()),
+            ours: "---\ntitle: [Title\n---\na\nours\n".into(),
+            theirs: "---\ntitle: Title\n---\na\ntheirs\n".into(),
+        };
+        assert!(conflict.merge().is_err());
+        assert_eq!(
+            conflict.merge_bodies(),
+            vec![
+                TextChunk::Resolved("a\n".into()),
+                TextChunk::Conflict {
+                    ours: "ours\n".into(),
+                    theirs: "theirs\n".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn text_conflict_without_trailing_newline() {
+        let chunks = merge_text("a", "b", "c");
+        assert_eq!(
+            render_with_markers(&chunks),
+            "<<<<<<< ours\nb\n=======\nc\n>>>>>>> theirs\n"
+        );
+    }
+
+    #[test]
+    fn metadata() {
+        let base = RawNoteMetadata {
+            title: "Title".into(),
+            tag_list: vec!["A".into(), "B".into()],
+            ..Default::default()
+        };
+        let ours = RawNoteMetadata {
+            title: "New Title".into(),
+            tag_list: vec!["A".into(), "B".into(), "C".into()],
+            ..Default::default()
+        };
+        let theirs = RawNoteMetadata {
+            title: "Title".into(),
+            tag_list: vec!["B".into(), "D".into()],
+            is_pinned: true,
+            ..Default::default()
+        };
+
+        let (merged, conflicting_fields) = merge_metadata(&base, &ours, &theirs);
+        assert!(conflicting_fields.is_empty());
+        assert_eq!(merged.title, "New Title");
+        assert_eq!(merged.tag_list, vec!["B", "C", "D"]);
+        assert!(merged.is_pinned);
+        assert!(!merged.is_trashed);
+    }
+
+    #[test]
+    fn metadata_conflict_takes_newer() {
+        let base = RawNoteMetadata {
+            title: "Title".into(),
+            ..Default::default()
+        };
+        let ours = RawNoteMetadata {
+            title: "Ours".into(),
+            last_modified: chrono::DateTime::parse_from_rfc3339("2022-01-01T00:00:00+00:00").ok(),
+            ..Default::default()
+        };
+        let theirs = RawNoteMetadata {
+            title: "Theirs".into(),
+            last_modified: chrono::DateTime::parse_from_rfc3339("2022-01-02T00:00:00+00:00").ok(),
+            ..Default::default()
+        };
+
+        let (merged, conflicting_fields) = merge_metadata(&base, &ours, &theirs);
+        assert_eq!(conflicting_fields, vec!["title"]);
+        assert_eq!(merged.title, "Theirs");
+        assert_eq!(merged.last_modified, theirs.last_modified);
+    }
+
+    #[test]
+    fn note() {
+        let base = "title: Title\ntag_list: []\nattachment_list: []\nis_pinned: false\nis_trashed: false\n---\nLine 1\nLine 2\nLine 3\n";
+        let ours = "title: Title\ntag_list:\n- A\nattachment_list: []\nis_pinned: false\nis_trashed: false\n---\nLine 1 edited\nLine 2\nLine 3\n";
+        let theirs = "title: Title\ntag_list: []\nattachment_list: []\nis_pinned: true\nis_trashed: false\n---\nLine 1\nLine 2\nLine 3 edited\n";
+
+        let outcome = merge_note(Some(base), ours, theirs).unwrap();
+        assert!(!outcome.is_conflicted());
+        assert_eq!(outcome.note.metadata.tag_list, vec!["A"]);
+        assert!(outcome.note.metadata.is_pinned);
+        assert_eq!(outcome.note.body, "Line 1 edited\nLine 2\nLine 3 edited\n");
+
+        let text = outcome.note.to_text().unwrap();
+        assert_eq!(RawNote::parse(&text).unwrap(), outcome.note);
+    }
+
+    #[test]
+    fn note_with_leading_delimiter() {
+        let text = "---\ntitle: Title\n---\nBody\n---\nMore body\n";
+        let note = RawNote::parse(text).unwrap();
+        assert_eq!(note.metadata.title, "Title");
+        assert_eq!(note.body, "Body\n---\nMore body\n");
+        assert!(note.to_text().unwrap().starts_with("---\ntitle: Title\n"));
+    }
+
+    #[test]
+    fn data_file() {
+        let base = "tag_list:\n- A\n- B\n";
+        let ours = "tag_list:\n- A\n- B\n- C\n";
+        let theirs = "tag_list:\n- B\n";
+
+        let merged = merge_data_file(Some(base), ours, theirs).unwrap();
+        assert_eq!(merged, "tag_list:\n- B\n- C\n");
+    }
+
+    #[test]
+    fn find_conflict_in_edited_text() {
+        let chunks = merge_text("A\nB\nC\nD\nE\n", "A\nB1\nC\nD1\nE\n", "A\nB2\nC\nD2\nE\n");
+        let text = render_with_markers(&chunks);
+        let edited = text.replacen("A\n", "A edited\n", 1);
+
+        let (ours, theirs) = match &chunks[3] {
+            TextChunk::Conflict { ours, theirs } => (ours, theirs),
+            TextChunk::Resolved(_) => panic!("Expected a conflict"),
+        };
+        let range = find_conflict(&edited, ours, theirs).unwrap();
+        assert_eq!(
+            &edited[range],
+            format!(
+                "{}\nD1\n{}\nD2\n{}\n",
+                OURS_MARKER, SEPARATOR_MARKER, THEIRS_MARKER
+            )
+        );
+
+        // Edited by hand
+        let resolved = edited.replacen("D1\n", "D3\n", 1);
+        assert_eq!(find_conflict(&resolved, ours, theirs), None);
+    }
+}
@@ -1,6 +1,20 @@
+mod auth;
+mod commit_message;
+mod credentials;
+mod diff;
+mod identity;
+mod ignore_file;
+mod merge;
+mod problem;
+mod pull_strategy;
+mod remote_url;
 mod repository;
 mod repository_watcher;
+mod signing;
+mod sync_error;
+mod sync_progress;
 mod sync_state;
+mod upstream;
 
 use gtk::{
     gio,
@@ -9,24 +23,38 @@ use gtk::{
     subclass::prelude::*,
 };
 use once_cell::{sync::Lazy, unsync::OnceCell};
-use regex::Regex;
 
 use std::{
-    cell::Cell,
-    path::PathBuf,
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
-pub use self::sync_state::SyncState;
-use self::{repository::Repository, repository_watcher::RepositoryWatcher};
+pub use self::{
+    auth::AuthError,
+    credentials::Credentials,
+    diff::{LineChange, MetadataChange, NoteDiff},
+    identity::Identity,
+    merge::{find_conflict, render_with_markers, NoteConflict, RawNote, TextChunk, OURS_MARKER},
+    problem::Problem,
+    pull_strategy::PullStrategy,
+    remote_url::RemoteUrl,
+    repository::{ChangedFile, Revision},
+    signing::{SigningFormat, SigningOptions},
+    sync_error::SyncError,
+    sync_progress::{Cancelled, SyncPhase, SyncProgress, TransferMonitor},
+    sync_state::SyncState,
+    upstream::Upstream,
+};
+use self::{
+    auth::AuthOptions, repository::Repository, repository_watcher::RepositoryWatcher,
+    upstream::DEFAULT_REMOTE_NAME,
+};
 use crate::{spawn, spawn_blocking};
 
-const DEFAULT_REMOTE_NAME: &str = "origin";
-const DEFAULT_AUTHOR_NAME: &str = "NoteworthyApp";
-const DEFAULT_AUTHOR_EMAIL: &str = "app@noteworthy.io";
-
-static RE_VALIDATE_URL: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"(git@[\w\.]+)(:(//)?)([\w\.@:/\-~]+)(\.git)(/)?").unwrap());
+const SYNC_PROGRESS_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 struct SyncOptions {
     is_skip_pull: bool,
@@ -35,13 +63,23 @@ struct SyncOptions {
 
 mod imp {
     use super::*;
+    use glib::subclass::Signal;
 
     #[derive(Default, Debug)]
     pub struct NoteRepository {
         pub base_path: OnceCell<gio::File>,
         pub sync_state: Cell<SyncState>,
         pub repository: OnceCell<Arc<Mutex<Repository>>>,
-        pub watcher: OnceCell<RepositoryWatcher>,
+        pub monitor: OnceCell<Arc<TransferMonitor>>,
+        pub sync_progress: Cell<SyncProgress>,
+        pub sync_progress_source_id: RefCell<Option<glib::SourceId>>,
+        pub sync_error: RefCell<Option<SyncError>>,
+        pub watcher: RefCell<Option<RepositoryWatcher>>,
+        /// Copy of the auth options of the repository, for the watcher
+        pub auth_options: RefCell<AuthOptions>,
+        pub remote_poll_interval: Cell<Option<u32>>,
+        pub pull_strategy: Cell<PullStrategy>,
+        pub problems: RefCell<Vec<Problem>>,
     }
 
     #[glib::object_subclass]
@@ -51,6 +89,16 @@ mod imp {
     }
 
     impl ObjectImpl for NoteRepository {
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: Lazy<Vec<Signal>> = Lazy::new(|| {
+                vec![
+                    Signal::builder("remote-changed", &[], <()>::static_type().into()).build(),
+                    Signal::builder("problems-changed", &[], <()>::static_type().into()).build(),
+                ]
+            });
+            SIGNALS.as_ref()
+        }
+
         fn properties() -> &'static [glib::ParamSpec] {
             static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
                 vec![
@@ -69,6 +117,20 @@ mod imp {
                         SyncState::default() as i32,
                         glib::ParamFlags::READWRITE | glib::ParamFlags::EXPLICIT_NOTIFY,
                     ),
+                    glib::ParamSpecBoxed::new(
+                        "sync-progress",
+                        "Sync Progress",
+                        "Progress of the current fetch or push",
+                        SyncProgress::static_type(),
+                        glib::ParamFlags::READABLE | glib::ParamFlags::EXPLICIT_NOTIFY,
+                    ),
+                    glib::ParamSpecBoxed::new(
+                        "sync-error",
+                        "Sync Error",
+                        "Why the last sync failed, if it did",
+                        SyncError::static_type(),
+                        glib::ParamFlags::READABLE | glib::ParamFlags::EXPLICIT_NOTIFY,
+                    ),
                 ]
             });
             PROPERTIES.as_ref()
@@ -98,9 +160,21 @@ mod imp {
             match pspec.name() {
                 "base-path" => obj.base_path().to_value(),
                 "sync-state" => obj.sync_state().to_value(),
+                "sync-progress" => obj.sync_progress().to_value(),
+                "sync-error" => obj.sync_error().to_value(),
                 _ => unimplemented!(),
             }
         }
+
+        fn dispose(&self, _obj: &Self::Type) {
+            if let Some(watcher) = self.watcher.take() {
+                spawn!(watcher.stop());
+            }
+
+            if let Some(source_id) = self.sync_progress_source_id.take() {
+                source_id.remove();
+            }
+        }
     }
 }
 
@@ -115,11 +189,38 @@ impl NoteRepository {
         Ok(Self::new(base_path, repository))
     }
 
-    pub async fn clone(remote_url: String, base_path: &gio::File) -> anyhow::Result<Self> {
+    /// Clone the remote at `remote_url` and check out `branch_name`, or the default branch of the
+    /// remote if it is `None`. The remote is named `origin` if `remote_name` is `None`.
+    ///
+    /// The download can be followed and cancelled with `monitor`.
+    pub async fn clone(
+        remote_url: String,
+        remote_name: Option<String>,
+        branch_name: Option<String>,
+        base_path: &gio::File,
+        ssh_key_path: Option<PathBuf>,
+        monitor: Arc<TransferMonitor>,
+    ) -> anyhow::Result<Self> {
         let repository_path = base_path.path().unwrap();
-        let repository =
-            spawn_blocking!(move || Repository::clone(&repository_path, &remote_url)).await?;
-        Ok(Self::new(base_path, repository))
+        let auth_options = AuthOptions {
+            ssh_key_path,
+            ..Default::default()
+        };
+        let clone_auth_options = auth_options.clone();
+        let repository = spawn_blocking!(move || {
+            Repository::clone(
+                &repository_path,
+                &remote_url,
+                remote_name.as_deref().unwrap_or(DEFAULT_REMOTE_NAME),
+                branch_name.as_deref(),
+                clone_auth_options,
+                monitor,
+            )
+        })
+        .await?;
+        let obj = Self::new(base_path, repository);
+        obj.imp().auth_options.replace(auth_options);
+        Ok(obj)
     }
 
     pub async fn open(base_path: &gio::File) -> anyhow::Result<Self> {
@@ -135,61 +236,507 @@ impl NoteRepository {
         obj
     }
 
-    pub fn validate_remote_url(remote_url: &str) -> bool {
-        if remote_url.is_empty() {
-            return false;
-        }
+    /// Use the key at `ssh_key_path` instead of the default ones in `~/.ssh`
+    pub async fn set_ssh_key_path(&self, ssh_key_path: Option<PathBuf>) {
+        self.imp().auth_options.borrow_mut().ssh_key_path = ssh_key_path.clone();
+        self.update_watcher_auth_options();
 
-        RE_VALIDATE_URL.is_match(remote_url)
+        let repo = self.repository();
+
+        spawn_blocking!(move || {
+            let mut repo = repo.lock().unwrap();
+
+            repo.set_ssh_key_path(ssh_key_path);
+        })
+        .await;
+    }
+
+    /// Set who makes the commits and on which device
+    pub async fn set_identity(&self, identity: Identity) {
+        let repo = self.repository();
+
+        spawn_blocking!(move || {
+            let mut repo = repo.lock().unwrap();
+
+            repo.set_identity(identity);
+        })
+        .await;
+    }
+
+    /// Set whether and how commits are signed, overriding the git config
+    pub async fn set_signing_options(&self, signing_options: SigningOptions) {
+        let repo = self.repository();
+
+        spawn_blocking!(move || {
+            let mut repo = repo.lock().unwrap();
+
+            repo.set_signing_options(signing_options);
+        })
+        .await;
+    }
+
+    /// Unlock the key at `key_path` with `passphrase`. The passphrase is also stored in the
+    /// keyring, so it is not asked again on the next sessions.
+    pub async fn set_ssh_key_passphrase(
+        &self,
+        key_path: PathBuf,
+        passphrase: String,
+    ) -> anyhow::Result<()> {
+        self.imp()
+            .auth_options
+            .borrow_mut()
+            .ssh_key_passphrases
+            .insert(key_path.clone(), passphrase.clone());
+        self.update_watcher_auth_options();
+
+        let repo = self.repository();
+
+        spawn_blocking!(move || {
+            let res = credentials::store_ssh_key_passphrase(&key_path, &passphrase);
+
+            let mut repo = repo.lock().unwrap();
+            repo.set_ssh_key_passphrase(key_path, passphrase);
+
+            res
+        })
+        .await
+    }
+
+    pub async fn store_credentials(
+        remote_url: RemoteUrl,
+        credentials: Credentials,
+    ) -> anyhow::Result<()> {
+        spawn_blocking!(move || credentials.store(&remote_url)).await
     }
 
     pub fn sync_state(&self) -> SyncState {
         self.imp().sync_state.get()
     }
 
+    /// Progress of the current fetch or push. This is only updated while syncing.
+    pub fn sync_progress(&self) -> SyncProgress {
+        self.imp().sync_progress.get()
+    }
+
+    /// Why the last sync failed. This is kept until a sync succeeds.
+    pub fn sync_error(&self) -> Option<SyncError> {
+        self.imp().sync_error.borrow().clone()
+    }
+
+    /// Abort the current fetch, or the push if it has not started uploading yet. The sync then
+    /// fails with [`SyncError::Cancelled`].
+    pub fn cancel_sync(&self) {
+        if !self.sync_state().is_syncing() {
+            return;
+        }
+
+        log::info!("Cancelling sync...");
+        self.monitor().cancel();
+    }
+
+    /// Emitted when the remote has changes that are not merged yet. This is only emitted while
+    /// the watcher is started.
     pub fn connect_remote_changed<F>(&self, f: F) -> glib::SignalHandlerId
     where
-        F: Fn(&RepositoryWatcher) + 'static,
+        F: Fn(&Self) + 'static,
+    {
+        self.connect_local("remote-changed", true, move |values| {
+            let obj = values[0].get::<Self>().unwrap();
+            f(&obj);
+            None
+        })
+    }
+
+    /// Problems of the repository that were found by the last [`NoteRepository::check_health`] and
+    /// could not be repaired without asking
+    pub fn problems(&self) -> Vec<Problem> {
+        self.imp().problems.borrow().clone()
+    }
+
+    pub fn connect_problems_changed<F>(&self, f: F) -> glib::SignalHandlerId
+    where
+        F: Fn(&Self) + 'static,
     {
-        spawn!(clone!(@weak self as obj => async move {
-            assert!(!obj.is_offline_mode().await, "Trying to connect remote change even it is offline mode");
+        self.connect_local("problems-changed", true, move |values| {
+            let obj = values[0].get::<Self>().unwrap();
+            f(&obj);
+            None
+        })
+    }
+
+    /// Look for what a crash may have left broken in the repository, and repair what can be without
+    /// losing anything. The rest is stored in [`NoteRepository::problems`]. Returns the files that
+    /// were changed by the repairs.
+    pub async fn check_health(&self) -> anyhow::Result<Vec<ChangedFile>> {
+        let repo = self.repository();
+
+        let (problems, changed_files) = spawn_blocking!(move || {
+            let repo = repo.lock().unwrap();
+
+            repo.repair_safe_problems()
+        })
+        .await?;
+        self.set_problems(problems);
+
+        Ok(changed_files)
+    }
+
+    /// Repair `problem`, even if it loses something, then check for others. Returns the files that
+    /// were changed by the repairs.
+    pub async fn repair(&self, problem: Problem) -> anyhow::Result<Vec<ChangedFile>> {
+        anyhow::ensure!(
+            !self.sync_state().is_syncing(),
+            "Cannot repair repository while syncing"
+        );
+
+        let repo = self.repository();
+
+        let mut changed_files = spawn_blocking!(move || {
+            let repo = repo.lock().unwrap();
+
+            repo.repair(&problem)
+        })
+        .await?;
+        changed_files.extend(self.check_health().await?);
+
+        Ok(changed_files)
+    }
+
+    /// Set the seconds to wait between checks for remote changes
+    pub fn set_remote_poll_interval(&self, poll_interval: u32) {
+        self.imp().remote_poll_interval.set(Some(poll_interval));
+
+        if let Some(ref watcher) = *self.imp().watcher.borrow() {
+            watcher.set_poll_interval(poll_interval);
+        }
+    }
+
+    pub fn set_pull_strategy(&self, pull_strategy: PullStrategy) {
+        self.imp().pull_strategy.set(pull_strategy);
+    }
+
+    /// Start checking the remote for changes, if not started yet
+    pub fn start_watcher(&self) {
+        let imp = self.imp();
+
+        if imp.watcher.borrow().is_some() {
+            return;
+        }
+
+        let watcher = RepositoryWatcher::new(&self.base_path());
+        if let Some(poll_interval) = imp.remote_poll_interval.get() {
+            watcher.set_poll_interval(poll_interval);
+        }
+        watcher.set_auth_options(imp.auth_options.borrow().clone());
+        watcher.connect_remote_changed(clone!(@weak self as obj => move |_| {
+            obj.emit_by_name::<()>("remote-changed", &[]);
         }));
 
-        let watcher = self
-            .imp()
-            .watcher
-            .get_or_init(|| RepositoryWatcher::new(&self.base_path(), DEFAULT_REMOTE_NAME));
+        imp.watcher.replace(Some(watcher));
+    }
+
+    fn update_watcher_auth_options(&self) {
+        let imp = self.imp();
+
+        if let Some(ref watcher) = *imp.watcher.borrow() {
+            watcher.set_auth_options(imp.auth_options.borrow().clone());
+        }
+    }
+
+    /// Stop checking the remote for changes, e.g., when there is no remote anymore. Resolves once
+    /// the watcher thread has ended.
+    pub async fn stop_watcher(&self) {
+        if let Some(watcher) = self.imp().watcher.take() {
+            watcher.stop().await;
+        }
+    }
+
+    /// Start syncing the notes with the branch `branch_name` of the remote at `remote_url`, which
+    /// is named `remote_name`, or `origin` if it is `None`. Without `branch_name`, the remote
+    /// branch with the same name as the local one is used, or else the only one of the remote.
+    ///
+    /// Local changes are committed first, then merged with the notes already on the remote before
+    /// everything is pushed. Returns the files that were changed by the merge.
+    pub async fn attach_remote(
+        &self,
+        remote_url: String,
+        remote_name: Option<String>,
+        branch_name: Option<String>,
+    ) -> anyhow::Result<Vec<ChangedFile>> {
+        anyhow::ensure!(
+            self.is_offline_mode().await,
+            "Repository is already synced with a remote"
+        );
+
+        self.sync_offline().await?;
+
+        self.set_sync_state(SyncState::Pulling);
+        let repo = self.repository();
+        let res = spawn_blocking!(move || {
+            let repo = repo.lock().unwrap();
+
+            repo.attach_remote(
+                remote_name.as_deref().unwrap_or(DEFAULT_REMOTE_NAME),
+                branch_name.as_deref(),
+                &remote_url,
+            )
+        })
+        .await;
+        let changed_files = match res {
+            Ok(changed_files) => changed_files,
+            Err(err) => {
+                self.finish_sync_state();
+                return Err(err);
+            }
+        };
+
+        // Conflicts are pushed once resolved, on the next sync
+        if self.conflicts().await?.is_empty() {
+            self.set_sync_state(SyncState::Pushing);
+            let res = self.push().await;
+            if res.is_ok() {
+                self.set_sync_error(None);
+            }
+            self.finish_sync_state();
+            res?;
+        } else {
+            log::warn!("Attaching remote resulted to conflicts; skipping push");
+            self.finish_sync_state();
+        }
+
+        Ok(changed_files)
+    }
+
+    /// Create and push the first commit if there is none yet, e.g., when the cloned remote was
+    /// empty
+    pub async fn initialize_if_empty(&self) -> anyhow::Result<()> {
+        let repo = self.repository();
+
+        let is_initialized = spawn_blocking!(move || {
+            let repo = repo.lock().unwrap();
+
+            repo.initialize_if_empty()
+        })
+        .await?;
+
+        if is_initialized {
+            self.push().await?;
+        }
+
+        Ok(())
+    }
+
+    /// URL of the remote the notes are synced with, if there is any
+    pub async fn remote_url(&self) -> Option<String> {
+        let repo = self.repository();
+
+        spawn_blocking!(move || {
+            let repo = repo.lock().unwrap();
+
+            repo.remote_url()
+        })
+        .await
+    }
+
+    /// Remote branch the notes are synced with
+    pub async fn upstream(&self) -> anyhow::Result<Upstream> {
+        let repo = self.repository();
+
+        spawn_blocking!(move || {
+            let repo = repo.lock().unwrap();
 
-        watcher.connect_remote_changed(f)
+            repo.upstream()
+        })
+        .await
     }
 
-    pub async fn sync(&self) -> anyhow::Result<Vec<(PathBuf, git2::Delta)>> {
+    /// Stop syncing with the remote. The notes and their history are kept locally.
+    pub async fn detach_remote(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            !self.sync_state().is_syncing(),
+            "Cannot detach remote while syncing"
+        );
+
+        self.stop_watcher().await;
+
+        let repo = self.repository();
+
+        spawn_blocking!(move || {
+            let repo = repo.lock().unwrap();
+
+            repo.detach_remote()
+        })
+        .await
+    }
+
+    pub async fn sync(&self) -> Result<Vec<ChangedFile>, SyncError> {
         let sync_opts = SyncOptions {
             is_skip_pull: false,
             is_skip_push: false,
         };
 
-        let changed_files = self.sync_full(sync_opts).await?.unwrap();
+        // This is `None` when pulling is skipped because of unresolved conflicts
+        let changed_files = self.sync_full(sync_opts).await?.unwrap_or_default();
         Ok(changed_files)
     }
 
-    pub async fn sync_offline(&self) -> anyhow::Result<()> {
+    pub async fn sync_offline(&self) -> Result<(), SyncError> {
         let sync_opts = SyncOptions {
             is_skip_pull: true,
             is_skip_push: true,
         };
 
-        match self.sync_full(sync_opts).await {
-            Ok(_) => Ok(()),
-            Err(err) => Err(err),
+        self.sync_full(sync_opts).await.map(|_| ())
+    }
+
+    /// Commit the changed files without syncing with the remote. Returns whether a commit was
+    /// created.
+    pub async fn commit_changes(&self) -> anyhow::Result<bool> {
+        if !self.is_file_changed_in_workdir().await? {
+            log::info!("There is no changed files in directory; skipped commit");
+            return Ok(false);
         }
+
+        log::info!("Found changes, adding all...");
+        self.add_all().await?;
+        log::info!("Added all files");
+
+        log::info!("Creating commit...");
+        self.commit().await?;
+        log::info!("Created commit");
+
+        Ok(true)
+    }
+
+    /// Notes that were merged with conflicts and still need to be resolved by the user
+    pub async fn conflicts(&self) -> anyhow::Result<Vec<NoteConflict>> {
+        let repo = self.repository();
+
+        spawn_blocking!(move || {
+            let repo = repo.lock().unwrap();
+
+            repo.conflicts()
+        })
+        .await
+    }
+
+    /// Name of the device where each file was last changed, keyed by the path relative to the
+    /// base path
+    pub async fn last_edit_devices(&self) -> anyhow::Result<HashMap<PathBuf, String>> {
+        let repo = self.repository();
+
+        spawn_blocking!(move || {
+            let repo = repo.lock().unwrap();
+
+            repo.last_edit_devices()
+        })
+        .await
+    }
+
+    /// Commits that changed the file at `path`, newest first
+    pub async fn file_history(&self, path: &Path) -> anyhow::Result<Vec<Revision>> {
+        let repo = self.repository();
+        let relative_path = path
+            .strip_prefix(self.base_path().path().unwrap())?
+            .to_owned();
+
+        spawn_blocking!(move || {
+            let repo = repo.lock().unwrap();
+
+            repo.file_history(&relative_path)
+        })
+        .await
+    }
+
+    /// Keep the files matching `patterns`, besides the default ones, out of sync on this device.
+    /// The default ones are written to `.gitignore`, which is synced too.
+    pub async fn set_ignored_patterns(&self, patterns: Vec<String>) -> anyhow::Result<()> {
+        let repo = self.repository();
+
+        spawn_blocking!(move || {
+            let repo = repo.lock().unwrap();
+
+            repo.set_ignored_patterns(&patterns)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Files kept out of sync on this device, relative to the base path
+    pub async fn local_only_paths(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let repo = self.repository();
+
+        spawn_blocking!(move || {
+            let repo = repo.lock().unwrap();
+
+            repo.local_only_paths()
+        })
+        .await
+    }
+
+    /// Keep the file at `path` out of sync on this device. The remote, and so the other devices,
+    /// keep its last synced version, which never overwrites the one on this device.
+    pub async fn set_local_only(&self, path: &Path, is_local_only: bool) -> anyhow::Result<()> {
+        let repo = self.repository();
+        let relative_path = path
+            .strip_prefix(self.base_path().path().unwrap())?
+            .to_owned();
+
+        spawn_blocking!(move || {
+            let repo = repo.lock().unwrap();
+
+            repo.set_local_only(&relative_path, is_local_only)
+        })
+        .await
+    }
+
+    /// Mark the conflict on the note at `path`, relative to the base path, as resolved
+    pub async fn resolve_conflict(&self, path: PathBuf) -> anyhow::Result<()> {
+        let repo = self.repository();
+
+        spawn_blocking!(move || {
+            let repo = repo.lock().unwrap();
+
+            repo.remove_conflict(&path)
+        })
+        .await
     }
 
     async fn sync_full(
         &self,
         sync_opts: SyncOptions,
-    ) -> anyhow::Result<Option<Vec<(PathBuf, git2::Delta)>>> {
+    ) -> Result<Option<Vec<ChangedFile>>, SyncError> {
         self.set_sync_state(SyncState::Syncing);
+        let res = self.sync_steps(sync_opts).await.map_err(SyncError::from);
+
+        match res {
+            // Without pulling, the remote was not reached, so it may still be failing
+            Ok(None) if matches!(self.sync_error(), Some(err) if err.is_remote()) => {}
+            Ok(_) => self.set_sync_error(None),
+            // Cancelling is not a failure, but neither does it fix the previous one
+            Err(SyncError::Cancelled) => log::info!("Sync: Cancelled"),
+            Err(ref err) => self.set_sync_error(Some(err.clone())),
+        }
+
+        self.finish_sync_state();
+
+        res
+    }
+
+    async fn sync_steps(
+        &self,
+        mut sync_opts: SyncOptions,
+    ) -> anyhow::Result<Option<Vec<ChangedFile>>> {
+        // Don't spread conflict markers to the remote, nor merge on top of them
+        if !self.conflicts().await?.is_empty() {
+            log::warn!("Sync: There are unresolved conflicts; skipping pull and push");
+            sync_opts.is_skip_pull = true;
+            sync_opts.is_skip_push = true;
+        }
+
+        // Local changes are committed before pulling, so they get merged with the remote ones
+        self.commit_changes().await?;
 
         let changed_files = if sync_opts.is_skip_pull {
             None
@@ -198,46 +745,37 @@ impl NoteRepository {
             self.set_sync_state(SyncState::Pulling);
             let changed_files = self.pull().await?;
             log::info!("Sync: Repo pulled changes");
-            Some(changed_files)
-        };
 
-        if self.is_file_changed_in_workdir().await? {
-            log::info!("Sync: Found changes, adding all...");
-            self.add_all().await?;
-            log::info!("Sync: Added all files");
+            if !self.conflicts().await?.is_empty() {
+                log::warn!("Sync: Pull resulted to conflicts; skipping push");
+                sync_opts.is_skip_push = true;
+            }
 
-            log::info!("Sync: Creating commit...");
-            self.commit().await?;
-            log::info!("Sync: Created commit");
+            Some(changed_files)
+        };
 
-            if !sync_opts.is_skip_push {
-                log::info!("Sync: Repo pushing changes...");
-                self.set_sync_state(SyncState::Pushing);
-                self.push().await?;
-                log::info!("Sync: Pushed chanes to remote");
+        if !sync_opts.is_skip_push {
+            log::info!("Sync: Repo pushing changes...");
+            self.set_sync_state(SyncState::Pushing);
+            match self.push().await {
+                Ok(()) => log::info!("Sync: Pushed chanes to remote"),
+                // The pulled changes still have to be handled, and these are pushed next time
+                Err(err) if err.is::<Cancelled>() => log::info!("Sync: Push cancelled"),
+                Err(err) => return Err(err),
             }
-        } else {
-            log::info!("Sync: There is no changed files in directory");
-            log::info!("Sync: Skipped pushing and commit");
         }
 
-        self.set_sync_state(SyncState::Idle);
-
         Ok(changed_files)
     }
 
-    // FIXME (CRITICAL) handle conflicts gracefully
-    async fn pull(&self) -> anyhow::Result<Vec<(PathBuf, git2::Delta)>> {
+    async fn pull(&self) -> anyhow::Result<Vec<ChangedFile>> {
         let repo = self.repository();
+        let pull_strategy = self.imp().pull_strategy.get();
 
         spawn_blocking!(move || {
             let repo = repo.lock().unwrap();
 
-            repo.pull(
-                DEFAULT_REMOTE_NAME,
-                DEFAULT_AUTHOR_NAME,
-                DEFAULT_AUTHOR_EMAIL,
-            )
+            repo.pull(pull_strategy)
         })
         .await
     }
@@ -285,7 +823,10 @@ impl NoteRepository {
         spawn_blocking!(move || {
             let repo = repo.lock().unwrap();
 
-            repo.commit("Sync commit", DEFAULT_AUTHOR_NAME, DEFAULT_AUTHOR_EMAIL)
+            let message = commit_message::describe(&repo.staged_changes()?);
+            log::info!("Commit message: {}", message);
+
+            repo.commit(&message)
         })
         .await
     }
@@ -296,7 +837,7 @@ impl NoteRepository {
         spawn_blocking!(move || {
             let repo = repo.lock().unwrap();
 
-            repo.push(DEFAULT_REMOTE_NAME)
+            repo.push()
         })
         .await
     }
@@ -305,9 +846,14 @@ impl NoteRepository {
         Arc::clone(self.imp().repository.get().unwrap())
     }
 
+    fn monitor(&self) -> Arc<TransferMonitor> {
+        Arc::clone(self.imp().monitor.get().unwrap())
+    }
+
     fn set_repository(&self, repository: Repository) {
-        self.imp()
-            .repository
+        let imp = self.imp();
+        imp.monitor.set(repository.monitor()).unwrap();
+        imp.repository
             .set(Arc::new(Mutex::new(repository)))
             .unwrap();
     }
@@ -317,7 +863,77 @@ impl NoteRepository {
     }
 
     fn set_sync_state(&self, sync_state: SyncState) {
+        let was_syncing = self.sync_state().is_syncing();
+
         self.imp().sync_state.set(sync_state);
         self.notify("sync-state");
+
+        match (was_syncing, sync_state.is_syncing()) {
+            (false, true) => self.start_sync_progress_polling(),
+            (true, false) => self.stop_sync_progress_polling(),
+            _ => {}
+        }
+    }
+
+    /// Leave the syncing states, showing whether the last sync failed
+    fn finish_sync_state(&self) {
+        if self.imp().sync_error.borrow().is_some() {
+            self.set_sync_state(SyncState::Error);
+        } else {
+            self.set_sync_state(SyncState::Idle);
+        }
+    }
+
+    fn set_sync_error(&self, sync_error: Option<SyncError>) {
+        if *self.imp().sync_error.borrow() == sync_error {
+            return;
+        }
+
+        self.imp().sync_error.replace(sync_error);
+        self.notify("sync-error");
+    }
+
+    fn set_problems(&self, problems: Vec<Problem>) {
+        if *self.imp().problems.borrow() == problems {
+            return;
+        }
+
+        self.imp().problems.replace(problems);
+        self.emit_by_name::<()>("problems-changed", &[]);
+    }
+
+    fn set_sync_progress(&self, sync_progress: SyncProgress) {
+        if self.sync_progress() == sync_progress {
+            return;
+        }
+
+        self.imp().sync_progress.set(sync_progress);
+        self.notify("sync-progress");
+    }
+
+    /// The progress is reported from the thread doing the remote operations, so it is read
+    /// periodically instead of notifying on every object transferred
+    fn start_sync_progress_polling(&self) {
+        let monitor = self.monitor();
+        monitor.reset();
+
+        let source_id = glib::timeout_add_local(
+            SYNC_PROGRESS_POLL_INTERVAL,
+            clone!(@weak self as obj => @default-return Continue(false), move || {
+                obj.set_sync_progress(monitor.progress());
+                Continue(true)
+            }),
+        );
+        self.imp().sync_progress_source_id.replace(Some(source_id));
+    }
+
+    fn stop_sync_progress_polling(&self) {
+        if let Some(source_id) = self.imp().sync_progress_source_id.take() {
+            source_id.remove();
+        }
+
+        // Don't let the cancellation of this sync affect the next one
+        self.monitor().reset();
+        self.set_sync_progress(SyncProgress::default());
     }
 }
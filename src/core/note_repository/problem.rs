@@ -0,0 +1,95 @@
+/// Something wrong with the repository that keeps it from being synced, usually left behind by a
+/// crash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// `index.lock` was left behind by a process that is gone
+    StaleIndexLock,
+    /// A merge of a sync was interrupted. Aborting it loses nothing, as local changes are
+    /// committed before pulling.
+    InterruptedMerge,
+    /// Like [`Problem::InterruptedMerge`], but for a rebase
+    InterruptedRebase,
+    /// An operation that syncing never does, e.g., a cherry-pick, was left unfinished. Aborting it
+    /// throws away what it changed.
+    UnfinishedOperation(String),
+    /// HEAD points to a commit instead of a branch. It is reattached to `branch`, which is moved
+    /// to the commit. Commits only on `branch` are lost if it is not a fast-forward.
+    DetachedHead {
+        branch: Option<String>,
+        is_fast_forward: bool,
+    },
+    /// HEAD points to a branch without commits, while the local `branches` have some
+    UnbornBranch { branches: Vec<String> },
+}
+
+impl Problem {
+    /// Whether it can be repaired without losing anything, so without asking first
+    pub fn is_safe_to_repair(&self) -> bool {
+        match self {
+            Self::StaleIndexLock | Self::InterruptedMerge | Self::InterruptedRebase => true,
+            Self::UnfinishedOperation(_) => false,
+            Self::DetachedHead {
+                branch,
+                is_fast_forward,
+            } => branch.is_some() && *is_fast_forward,
+            Self::UnbornBranch { branches } => branches.len() == 1,
+        }
+    }
+
+    /// Whether it can be repaired at all. The others have to be fixed by hand.
+    pub fn can_repair(&self) -> bool {
+        match self {
+            Self::DetachedHead { branch, .. } => branch.is_some(),
+            Self::UnbornBranch { branches } => branches.len() == 1,
+            _ => true,
+        }
+    }
+}
+
+/// Name of the operation that left the repository in `state`, as in the Git commands
+pub fn operation_name(state: git2::RepositoryState) -> &'static str {
+    use git2::RepositoryState;
+
+    match state {
+        RepositoryState::Clean => "none",
+        RepositoryState::Merge => "merge",
+        RepositoryState::Revert | RepositoryState::RevertSequence => "revert",
+        RepositoryState::CherryPick | RepositoryState::CherryPickSequence => "cherry-pick",
+        RepositoryState::Bisect => "bisect",
+        RepositoryState::Rebase
+        | RepositoryState::RebaseInteractive
+        | RepositoryState::RebaseMerge => "rebase",
+        RepositoryState::ApplyMailbox | RepositoryState::ApplyMailboxOrRebase => "am",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn safe_to_repair() {
+        assert!(Problem::InterruptedMerge.is_safe_to_repair());
+        assert!(!Problem::UnfinishedOperation("cherry-pick".into()).is_safe_to_repair());
+        assert!(Problem::UnfinishedOperation("cherry-pick".into()).can_repair());
+
+        let detached_behind = Problem::DetachedHead {
+            branch: Some("main".into()),
+            is_fast_forward: true,
+        };
+        assert!(detached_behind.is_safe_to_repair());
+
+        let detached_diverged = Problem::DetachedHead {
+            branch: Some("main".into()),
+            is_fast_forward: false,
+        };
+        assert!(!detached_diverged.is_safe_to_repair());
+        assert!(detached_diverged.can_repair());
+
+        let unborn = Problem::UnbornBranch {
+            branches: vec!["main".into(), "notes".into()],
+        };
+        assert!(!unborn.is_safe_to_repair());
+        assert!(!unborn.can_repair());
+    }
+}
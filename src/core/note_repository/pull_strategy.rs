@@ -0,0 +1,30 @@
+/// How the remote changes are combined with the local ones once both have new commits
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PullStrategy {
+    /// Create a merge commit
+    #[default]
+    Merge,
+    /// Replay the local commits on top of the remote ones, so the history stays linear
+    Rebase,
+    /// Refuse to sync, so the user can choose what to do
+    FastForwardOnly,
+}
+
+impl PullStrategy {
+    pub fn from_setting(value: &str) -> Option<Self> {
+        match value {
+            "merge" => Some(Self::Merge),
+            "rebase" => Some(Self::Rebase),
+            "ff-only" => Some(Self::FastForwardOnly),
+            _ => None,
+        }
+    }
+
+    pub const fn as_setting(self) -> &'static str {
+        match self {
+            Self::Merge => "merge",
+            Self::Rebase => "rebase",
+            Self::FastForwardOnly => "ff-only",
+        }
+    }
+}
@@ -0,0 +1,354 @@
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum RemoteUrlError {
+    #[error("Remote url is empty")]
+    Empty,
+    #[error("Unsupported scheme `{0}`")]
+    UnsupportedScheme(String),
+    #[error("Remote url has no host")]
+    MissingHost,
+    #[error("Remote url has no repository path")]
+    MissingPath,
+    #[error("Invalid port `{0}`")]
+    InvalidPort(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemoteScheme {
+    /// `ssh://host/path` and the scp-like `user@host:path`
+    Ssh,
+    Https,
+    Http,
+    /// The unauthenticated `git://` protocol
+    Git,
+    /// `file:///path` and plain local paths
+    File,
+}
+
+impl RemoteScheme {
+    /// Whether a username and token is needed to authenticate
+    pub const fn needs_credentials(self) -> bool {
+        matches!(self, Self::Https | Self::Http)
+    }
+
+    pub const fn is_ssh(self) -> bool {
+        matches!(self, Self::Ssh)
+    }
+
+    fn from_name(name: &str) -> Result<Self, RemoteUrlError> {
+        match name {
+            "ssh" | "git+ssh" | "ssh+git" => Ok(Self::Ssh),
+            "https" => Ok(Self::Https),
+            "http" => Ok(Self::Http),
+            "git" => Ok(Self::Git),
+            "file" => Ok(Self::File),
+            other => Err(RemoteUrlError::UnsupportedScheme(other.to_string())),
+        }
+    }
+}
+
+/// A parsed git remote url
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteUrl {
+    scheme: RemoteScheme,
+    user: Option<String>,
+    host: Option<String>,
+    port: Option<u16>,
+    path: String,
+}
+
+impl RemoteUrl {
+    pub fn parse(remote_url: &str) -> Result<Self, RemoteUrlError> {
+        remote_url.parse()
+    }
+
+    pub const fn scheme(&self) -> RemoteScheme {
+        self.scheme
+    }
+
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    /// This is `None` only for local repositories
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+
+    pub const fn port(&self) -> Option<u16> {
+        self.port
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn parse_with_scheme(scheme_name: &str, rest: &str) -> Result<Self, RemoteUrlError> {
+        let scheme = RemoteScheme::from_name(&scheme_name.to_ascii_lowercase())?;
+
+        if scheme == RemoteScheme::File {
+            return Self::parse_local(rest);
+        }
+
+        let (authority, path) = rest
+            .find('/')
+            .map_or((rest, ""), |index| rest.split_at(index));
+
+        let (user, host_port) = match authority.rsplit_once('@') {
+            Some((user, host_port)) => (Some(user), host_port),
+            None => (None, authority),
+        };
+
+        let (host, port) = match host_port.rsplit_once(':') {
+            // Don't mistake the colon of IPv6 addresses for a port
+            Some((host, port)) if !port.contains(']') => {
+                let port = port
+                    .parse()
+                    .map_err(|_| RemoteUrlError::InvalidPort(port.to_string()))?;
+                (host, Some(port))
+            }
+            _ => (host_port, None),
+        };
+
+        Self::new(scheme, user, host, port, path)
+    }
+
+    /// Parse the scp-like syntax, `[user@]host:path`
+    fn parse_scp_like(remote_url: &str) -> Option<Result<Self, RemoteUrlError>> {
+        let (authority, path) = remote_url.split_once(':')?;
+
+        // Otherwise, it is a local path that happens to have a colon
+        if authority.contains('/') {
+            return None;
+        }
+
+        let (user, host) = match authority.rsplit_once('@') {
+            Some((user, host)) => (Some(user), host),
+            None => (None, authority),
+        };
+
+        Some(Self::new(RemoteScheme::Ssh, user, host, None, path))
+    }
+
+    fn parse_local(path: &str) -> Result<Self, RemoteUrlError> {
+        if path.is_empty() {
+            return Err(RemoteUrlError::MissingPath);
+        }
+
+        Ok(Self {
+            scheme: RemoteScheme::File,
+            user: None,
+            host: None,
+            port: None,
+            path: path.to_string(),
+        })
+    }
+
+    fn new(
+        scheme: RemoteScheme,
+        user: Option<&str>,
+        host: &str,
+        port: Option<u16>,
+        path: &str,
+    ) -> Result<Self, RemoteUrlError> {
+        if host.is_empty() {
+            return Err(RemoteUrlError::MissingHost);
+        }
+
+        if path.trim_matches('/').is_empty() {
+            return Err(RemoteUrlError::MissingPath);
+        }
+
+        Ok(Self {
+            scheme,
+            user: user.filter(|user| !user.is_empty()).map(str::to_string),
+            host: Some(host.to_string()),
+            port,
+            path: path.to_string(),
+        })
+    }
+}
+
+impl FromStr for RemoteUrl {
+    type Err = RemoteUrlError;
+
+    fn from_str(remote_url: &str) -> Result<Self, Self::Err> {
+        let remote_url = remote_url.trim();
+
+        if remote_url.is_empty() {
+            return Err(RemoteUrlError::Empty);
+        }
+
+        if let Some((scheme_name, rest)) = remote_url.split_once("://") {
+            return Self::parse_with_scheme(scheme_name, rest);
+        }
+
+        if remote_url.starts_with(['/', '.', '~']) {
+            return Self::parse_local(remote_url);
+        }
+
+        Self::parse_scp_like(remote_url).unwrap_or_else(|| Self::parse_local(remote_url))
+    }
+}
+
+impl fmt::Display for RemoteUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scheme_name = match self.scheme() {
+            RemoteScheme::Ssh => "ssh",
+            RemoteScheme::Https => "https",
+            RemoteScheme::Http => "http",
+            RemoteScheme::Git => "git",
+            RemoteScheme::File => "file",
+        };
+        let path = self.path();
+
+        if !path.starts_with('/') {
+            match self.scheme() {
+                // Relative paths can't be written as a `file://` url
+                RemoteScheme::File => return f.write_str(path),
+                // Paths relative to the home directory are only possible with the scp-like syntax
+                RemoteScheme::Ssh => {
+                    if let Some(user) = self.user() {
+                        write!(f, "{}@", user)?;
+                    }
+                    return write!(f, "{}:{}", self.host().unwrap_or_default(), path);
+                }
+                _ => (),
+            }
+        }
+
+        write!(f, "{}://", scheme_name)?;
+
+        if let Some(user) = self.user() {
+            write!(f, "{}@", user)?;
+        }
+
+        if let Some(host) = self.host() {
+            f.write_str(host)?;
+        }
+
+        if let Some(port) = self.port() {
+            write!(f, ":{}", port)?;
+        }
+
+        if !path.starts_with('/') {
+            f.write_str("/")?;
+        }
+
+        f.write_str(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Input, scheme, user, host, port, and path
+    type ParseCase<'a> = (
+        &'a str,
+        RemoteScheme,
+        Option<&'a str>,
+        Option<&'a str>,
+        Option<u16>,
+        &'a str,
+    );
+
+    #[test]
+    fn parse() {
+        use RemoteScheme::*;
+
+        #[rustfmt::skip]
+        let table: &[ParseCase<'_>] = &[
+            ("git@github.com:SeaDve/Noteworthy.git", Ssh, Some("git"), Some("github.com"), None, "SeaDve/Noteworthy.git"),
+            ("github.com:SeaDve/Noteworthy.git", Ssh, None, Some("github.com"), None, "SeaDve/Noteworthy.git"),
+            ("git@nas.local:/srv/notes.git", Ssh, Some("git"), Some("nas.local"), None, "/srv/notes.git"),
+            ("ssh://git@host:2222/repo", Ssh, Some("git"), Some("host"), Some(2222), "/repo"),
+            ("ssh://host/~/notes.git", Ssh, None, Some("host"), None, "/~/notes.git"),
+            ("git+ssh://git@host/repo.git", Ssh, Some("git"), Some("host"), None, "/repo.git"),
+            ("https://github.com/SeaDve/Noteworthy.git", Https, None, Some("github.com"), None, "/SeaDve/Noteworthy.git"),
+            ("https://user@gitlab.example.com:8443/group/sub/notes", Https, Some("user"), Some("gitlab.example.com"), Some(8443), "/group/sub/notes"),
+            ("HTTPS://github.com/SeaDve/Noteworthy", Https, None, Some("github.com"), None, "/SeaDve/Noteworthy"),
+            ("http://192.168.1.2/notes.git", Http, None, Some("192.168.1.2"), None, "/notes.git"),
+            ("git://host.xz/path/to/repo.git/", Git, None, Some("host.xz"), None, "/path/to/repo.git/"),
+            ("ssh://git@[::1]:22/repo.git", Ssh, Some("git"), Some("[::1]"), Some(22), "/repo.git"),
+            ("file:///srv/notes.git", File, None, None, None, "/srv/notes.git"),
+            ("/srv/notes.git", File, None, None, None, "/srv/notes.git"),
+            ("./notes", File, None, None, None, "./notes"),
+            ("../notes.git", File, None, None, None, "../notes.git"),
+            ("~/notes.git", File, None, None, None, "~/notes.git"),
+            ("mnt/nas:2/notes.git", File, None, None, None, "mnt/nas:2/notes.git"),
+            ("  git@github.com:SeaDve/Noteworthy.git\n", Ssh, Some("git"), Some("github.com"), None, "SeaDve/Noteworthy.git"),
+        ];
+
+        for (input, scheme, user, host, port, path) in table {
+            let remote_url = RemoteUrl::parse(input)
+                .unwrap_or_else(|err| panic!("Failed to parse `{}`: {}", input, err));
+            assert_eq!(remote_url.scheme(), *scheme, "scheme of `{}`", input);
+            assert_eq!(remote_url.user(), *user, "user of `{}`", input);
+            assert_eq!(remote_url.host(), *host, "host of `{}`", input);
+            assert_eq!(remote_url.port(), *port, "port of `{}`", input);
+            assert_eq!(remote_url.path(), *path, "path of `{}`", input);
+        }
+    }
+
+    #[test]
+    fn parse_error() {
+        #[rustfmt::skip]
+        let table: &[(&str, RemoteUrlError)] = &[
+            ("", RemoteUrlError::Empty),
+            ("   ", RemoteUrlError::Empty),
+            ("https://github.com", RemoteUrlError::MissingPath),
+            ("https://github.com/", RemoteUrlError::MissingPath),
+            ("https:///SeaDve/Noteworthy", RemoteUrlError::MissingHost),
+            ("ssh://host:port/repo", RemoteUrlError::InvalidPort("port".into())),
+            ("ssh://host:99999/repo", RemoteUrlError::InvalidPort("99999".into())),
+            ("git@github.com:", RemoteUrlError::MissingPath),
+            (":repo.git", RemoteUrlError::MissingHost),
+            ("ftp://host/repo", RemoteUrlError::UnsupportedScheme("ftp".into())),
+            ("file://", RemoteUrlError::MissingPath),
+        ];
+
+        for (input, expected) in table {
+            assert_eq!(
+                RemoteUrl::parse(input).as_ref(),
+                Err(expected),
+                "error of `{}`",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn display() {
+        let table = [
+            (
+                "git@github.com:SeaDve/Noteworthy.git",
+                "git@github.com:SeaDve/Noteworthy.git",
+            ),
+            ("git+ssh://git@host/repo.git", "ssh://git@host/repo.git"),
+            ("ssh://git@host:2222/repo", "ssh://git@host:2222/repo"),
+            (
+                "https://github.com/SeaDve/Noteworthy.git",
+                "https://github.com/SeaDve/Noteworthy.git",
+            ),
+            ("/srv/notes.git", "file:///srv/notes.git"),
+            ("./notes", "./notes"),
+        ];
+
+        for (input, expected) in table {
+            assert_eq!(RemoteUrl::parse(input).unwrap().to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn scheme() {
+        assert!(RemoteScheme::Https.needs_credentials());
+        assert!(RemoteScheme::Http.needs_credentials());
+        assert!(!RemoteScheme::Ssh.needs_credentials());
+        assert!(!RemoteScheme::File.needs_credentials());
+
+        assert!(RemoteScheme::Ssh.is_ssh());
+        assert!(!RemoteScheme::Git.is_ssh());
+    }
+}
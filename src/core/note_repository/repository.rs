@@ -1,12 +1,94 @@
 use std::{
-    fs::{self, File},
-    io::Write,
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    ffi::{OsStr, OsString},
+    fs,
     path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
 };
 
+use super::{
+    auth::{AuthOptions, CredentialsHandler},
+    commit_message::{self, Change},
+    identity::Identity,
+    ignore_file::{self, Section},
+    merge::{self, NoteConflict, RawNote},
+    problem::{self, Problem},
+    pull_strategy::PullStrategy,
+    remote_url::RemoteUrl,
+    signing::{Signer, SigningOptions},
+    sync_error::SyncError,
+    sync_progress::{Cancelled, TransferMonitor},
+    upstream::{Upstream, DEFAULT_REMOTE_NAME},
+};
+
+const DATA_FILE_NAME: &str = "data.nwty";
+const CONFLICTS_FILE_NAME: &str = "noteworthy-conflicts";
+const INDEX_LOCK_FILE_NAME: &str = "index.lock";
+const IGNORE_FILE_NAME: &str = ".gitignore";
+
+/// How old `index.lock` must be to be considered left behind. Writing the index of a notes
+/// repository never takes this long.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FileKind {
+    Note,
+    Data,
+    Other,
+}
+
+impl FileKind {
+    fn for_path(path: &Path) -> Self {
+        if path.file_name() == Some(OsStr::new(DATA_FILE_NAME)) {
+            Self::Data
+        } else if path.extension() == Some(OsStr::new("md")) {
+            Self::Note
+        } else {
+            Self::Other
+        }
+    }
+}
+
+/// A file that was changed by a pull or a merge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedFile {
+    pub path: PathBuf,
+    /// Where the file was before it was renamed or copied, otherwise the same as `path`
+    pub old_path: PathBuf,
+    pub delta: git2::Delta,
+}
+
+/// A commit that changed a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Revision {
+    pub id: String,
+    /// Seconds since the Unix epoch
+    pub time: i64,
+    pub summary: String,
+    /// Device where the commit was made, if it was recorded
+    pub device: Option<String>,
+    /// Content of the file after the commit. This is `None` when the commit deleted it.
+    pub content: Option<String>,
+}
+
+/// Result of [`Repository::last_edit_devices`] for a commit, which is updated from the commits
+/// made after it
+#[derive(Debug)]
+struct LastEditDevices {
+    commit_id: git2::Oid,
+    devices: HashMap<PathBuf, String>,
+}
+
 pub struct Repository {
     inner: git2::Repository,
     base_path: PathBuf,
+    auth_options: AuthOptions,
+    identity: Identity,
+    signing_options: SigningOptions,
+    monitor: Arc<TransferMonitor>,
+    last_edit_devices: RefCell<Option<LastEditDevices>>,
 }
 
 impl std::fmt::Debug for Repository {
@@ -27,27 +109,56 @@ impl Repository {
         Ok(Self {
             inner: repo,
             base_path: base_path.as_ref().to_owned(),
+            auth_options: AuthOptions::default(),
+            identity: Identity::default(),
+            signing_options: SigningOptions::default(),
+            monitor: Arc::default(),
+            last_edit_devices: RefCell::default(),
         })
     }
 
-    pub fn clone(base_path: impl AsRef<Path>, remote_url: &str) -> anyhow::Result<Self> {
-        let mut callbacks = git2::RemoteCallbacks::new();
-        callbacks.credentials(|_, username_from_url, _| Self::credentials_cb(username_from_url));
-        callbacks.transfer_progress(|ref progress| Self::transfer_progress_cb(progress));
-
-        let mut fetch_options = git2::FetchOptions::new();
-        fetch_options.remote_callbacks(callbacks);
-
-        let mut repo_builder = git2::build::RepoBuilder::new();
-        repo_builder.fetch_options(fetch_options);
-
+    /// Clone the remote at `remote_url` under `remote_name`, and check out `branch_name`, or the
+    /// default branch of the remote if it is `None`. The checked out branch tracks the remote one.
+    ///
+    /// The download is reported to and can be cancelled with `monitor`, which is kept for the
+    /// later remote operations.
+    pub fn clone(
+        base_path: impl AsRef<Path>,
+        remote_url: &str,
+        remote_name: &str,
+        branch_name: Option<&str>,
+        auth_options: AuthOptions,
+        monitor: Arc<TransferMonitor>,
+    ) -> anyhow::Result<Self> {
         log::info!("Cloning from `{}` ...", remote_url);
-        let repo = repo_builder.clone(remote_url, base_path.as_ref())?;
+        let repo = Self::with_remote_callbacks(&auth_options, &monitor, |callbacks| {
+            let mut fetch_options = git2::FetchOptions::new();
+            fetch_options.remote_callbacks(callbacks);
 
-        Ok(Self {
+            git2::build::RepoBuilder::new()
+                .fetch_options(fetch_options)
+                .remote_create(|repo, _, url| repo.remote(remote_name, url))
+                .clone(remote_url, base_path.as_ref())
+        })?;
+
+        let this = Self {
             inner: repo,
             base_path: base_path.as_ref().to_owned(),
-        })
+            auth_options,
+            identity: Identity::default(),
+            signing_options: SigningOptions::default(),
+            monitor,
+            last_edit_devices: RefCell::default(),
+        };
+
+        let local_branch = this.local_branch()?;
+        let upstream = Upstream::new(remote_name, branch_name.unwrap_or(&local_branch));
+        if upstream.branch_name != local_branch {
+            this.checkout_remote_branch(&upstream, &local_branch)?;
+        }
+        this.set_upstream(&upstream)?;
+
+        Ok(this)
     }
 
     pub fn open(base_path: &Path) -> anyhow::Result<Self> {
@@ -57,13 +168,50 @@ impl Repository {
         Ok(Self {
             inner: repo,
             base_path: base_path.to_owned(),
+            auth_options: AuthOptions::default(),
+            identity: Identity::default(),
+            signing_options: SigningOptions::default(),
+            monitor: Arc::default(),
+            last_edit_devices: RefCell::default(),
         })
     }
 
+    pub fn set_auth_options(&mut self, auth_options: AuthOptions) {
+        self.auth_options = auth_options;
+    }
+
+    pub fn set_ssh_key_path(&mut self, ssh_key_path: Option<PathBuf>) {
+        self.auth_options.ssh_key_path = ssh_key_path;
+    }
+
+    /// Use `passphrase` for the key at `key_path` for the rest of the session
+    pub fn set_ssh_key_passphrase(&mut self, key_path: PathBuf, passphrase: String) {
+        self.auth_options
+            .ssh_key_passphrases
+            .insert(key_path, passphrase);
+    }
+
+    pub fn set_identity(&mut self, identity: Identity) {
+        self.identity = identity;
+    }
+
+    pub fn set_signing_options(&mut self, signing_options: SigningOptions) {
+        self.signing_options = signing_options;
+    }
+
     pub fn base_path(&self) -> &Path {
         self.base_path.as_path()
     }
 
+    /// Progress of the remote operations, which can also be used to cancel them
+    pub fn monitor(&self) -> Arc<TransferMonitor> {
+        Arc::clone(&self.monitor)
+    }
+
+    pub fn set_monitor(&mut self, monitor: Arc<TransferMonitor>) {
+        self.monitor = monitor;
+    }
+
     pub fn remotes(&self) -> anyhow::Result<Vec<String>> {
         let repo = self.inner();
         let remotes = repo.remotes()?;
@@ -76,14 +224,251 @@ impl Repository {
             .collect())
     }
 
+    /// Create the first commit if there is none yet, e.g., after cloning an empty remote, so there
+    /// is a branch to push to and merge with. Returns whether a commit was created.
+    pub fn initialize_if_empty(&self) -> anyhow::Result<bool> {
+        // The checked out branch may not be the default one, which `is_empty` expects
+        match self.inner().head() {
+            Err(err) if err.code() == git2::ErrorCode::UnbornBranch => {}
+            Err(err) => return Err(err.into()),
+            Ok(_) => return Ok(false),
+        }
+
+        log::info!("Repository is empty; creating initial commit...");
+        self.add(&["."])?;
+        self.commit("Initialize notes")?;
+
+        Ok(true)
+    }
+
+    /// Name of the checked out branch, even if it has no commit yet
+    fn local_branch(&self) -> anyhow::Result<String> {
+        let head = self.inner().find_reference("HEAD")?;
+        let target = head
+            .symbolic_target()
+            .ok_or_else(|| anyhow::anyhow!("Head is not a symbolic reference"))?;
+
+        Ok(target
+            .strip_prefix("refs/heads/")
+            .unwrap_or(target)
+            .to_string())
+    }
+
+    /// Remote branch that the checked out branch is synced with
+    pub fn upstream(&self) -> anyhow::Result<Upstream> {
+        let local_branch = self.local_branch()?;
+        let config = self.inner().config()?.snapshot()?;
+
+        let remote_name = config.get_string(&format!("branch.{}.remote", local_branch));
+        let merge_ref = config.get_string(&format!("branch.{}.merge", local_branch));
+
+        match (remote_name, merge_ref) {
+            (Ok(remote_name), Ok(merge_ref)) => Ok(Upstream {
+                remote_name,
+                branch_name: merge_ref
+                    .strip_prefix("refs/heads/")
+                    .unwrap_or(&merge_ref)
+                    .to_string(),
+            }),
+            // Repositories set up before upstreams were tracked
+            _ => Ok(Upstream::new(DEFAULT_REMOTE_NAME, &local_branch)),
+        }
+    }
+
+    /// Make the checked out branch track `upstream`. The remote branch does not have to exist yet.
+    fn set_upstream(&self, upstream: &Upstream) -> anyhow::Result<()> {
+        let local_branch = self.local_branch()?;
+        log::info!("Setting upstream of `{}` to `{}`", local_branch, upstream);
+
+        let mut config = self.inner().config()?;
+        config.set_str(
+            &format!("branch.{}.remote", local_branch),
+            &upstream.remote_name,
+        )?;
+        config.set_str(
+            &format!("branch.{}.merge", local_branch),
+            &upstream.merge_ref(),
+        )?;
+
+        Ok(())
+    }
+
+    /// Check out the remote branch of `upstream` in place of `old_branch`, which is deleted. If
+    /// the remote is empty, the branch is created on the first commit instead.
+    fn checkout_remote_branch(&self, upstream: &Upstream, old_branch: &str) -> anyhow::Result<()> {
+        let repo = self.inner();
+        let local_ref = format!("refs/heads/{}", upstream.branch_name);
+
+        match repo.find_reference(&upstream.remote_ref()) {
+            Ok(remote_ref) => {
+                log::info!("Checking out `{}`", upstream);
+                let commit = remote_ref.peel_to_commit()?;
+                repo.branch(&upstream.branch_name, &commit, false)?;
+                repo.checkout_tree(
+                    commit.as_object(),
+                    Some(git2::build::CheckoutBuilder::new().force()),
+                )?;
+                repo.set_head(&local_ref)?;
+
+                repo.find_branch(old_branch, git2::BranchType::Local)?
+                    .delete()?;
+            }
+            Err(err) if err.code() == git2::ErrorCode::NotFound && repo.is_empty()? => {
+                repo.set_head(&local_ref)?;
+            }
+            Err(err) if err.code() == git2::ErrorCode::NotFound => {
+                anyhow::bail!("Remote has no branch named `{}`", upstream.branch_name);
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        Ok(())
+    }
+
+    /// URL of the remote the notes are synced with, if there is such remote
+    pub fn remote_url(&self) -> Option<String> {
+        let upstream = self.upstream().ok()?;
+        let remote = self.inner().find_remote(&upstream.remote_name).ok()?;
+        remote.url().map(str::to_string)
+    }
+
+    /// Add the remote at `remote_url` under `remote_name`, so notes that were only stored locally
+    /// can be synced with its branch `branch_name`.
+    ///
+    /// If the remote branch already has notes, they are merged with the local ones even if the
+    /// histories are unrelated. The local branch is renamed after the remote one and tracks it.
+    /// Without `branch_name`, the remote branch with the same name as the local one is used, or
+    /// else the only one of the remote. Returns the files that were changed by the merge. The
+    /// remote is removed again if any of this fails.
+    pub fn attach_remote(
+        &self,
+        remote_name: &str,
+        branch_name: Option<&str>,
+        remote_url: &str,
+    ) -> anyhow::Result<Vec<ChangedFile>> {
+        let repo = self.inner();
+
+        anyhow::ensure!(
+            repo.find_remote(remote_name).is_err(),
+            "Remote `{}` already exists",
+            remote_name
+        );
+
+        log::info!("Attaching remote `{}` at `{}`", remote_name, remote_url);
+        repo.remote(remote_name, remote_url)?;
+
+        let res = self
+            .fetch(remote_name)
+            .and_then(|_| self.ensure_not_cancelled())
+            .and_then(|_| {
+                self.with_local_only_protected(|| {
+                    self.merge_attached_remote(remote_name, branch_name)
+                })
+            });
+
+        if res.is_err() {
+            if let Err(err) = repo.remote_delete(remote_name) {
+                log::warn!("Failed to remove remote `{}`: {:?}", remote_name, err);
+            }
+        }
+
+        res
+    }
+
+    /// Remove the remote, so the notes are only stored locally. The history is kept.
+    pub fn detach_remote(&self) -> anyhow::Result<()> {
+        let remote_name = self.upstream()?.remote_name;
+
+        log::info!("Detaching remote `{}`", remote_name);
+        // This also removes the upstream of the branches that track it
+        self.inner().remote_delete(&remote_name)?;
+        Ok(())
+    }
+
+    fn merge_attached_remote(
+        &self,
+        remote_name: &str,
+        branch_name: Option<&str>,
+    ) -> anyhow::Result<Vec<ChangedFile>> {
+        let repo = self.inner();
+
+        let local_branch = self.local_branch()?;
+
+        let prefix = format!("{}/", remote_name);
+        let mut remote_branches = Vec::new();
+        for branch in repo.branches(Some(git2::BranchType::Remote))? {
+            let (branch, _) = branch?;
+            if let Some(name) = branch.name()? {
+                if name.starts_with(&prefix) && !name.ends_with("/HEAD") {
+                    remote_branches.push(name.to_string());
+                }
+            }
+        }
+
+        // Prefer the branch with the same name, otherwise only adopt the remote one if there is
+        // no other choice. A chosen branch is created on the first push if it does not exist yet.
+        let same_branch = format!("{}{}", prefix, branch_name.unwrap_or(&local_branch));
+        let source_branch = if remote_branches.contains(&same_branch) {
+            Some(same_branch)
+        } else if branch_name.is_some() || remote_branches.is_empty() {
+            None
+        } else if let [remote_branch] = remote_branches.as_slice() {
+            Some(remote_branch.clone())
+        } else {
+            anyhow::bail!(
+                "Remote `{}` has no branch named `{}` and more than one other branch",
+                remote_name,
+                local_branch
+            );
+        };
+
+        let branch_name = source_branch.as_ref().map_or_else(
+            || branch_name.unwrap_or(&local_branch),
+            |source_branch| &source_branch[prefix.len()..],
+        );
+        if branch_name != local_branch {
+            log::info!("Renaming branch `{}` to `{}`", local_branch, branch_name);
+            repo.find_branch(&local_branch, git2::BranchType::Local)?
+                .rename(branch_name, false)?;
+        }
+
+        self.set_upstream(&Upstream::new(remote_name, branch_name))?;
+
+        let source_branch = match source_branch {
+            Some(source_branch) => source_branch,
+            None => {
+                log::info!(
+                    "Remote `{}` has no branch named `{}`; nothing to merge",
+                    remote_name,
+                    branch_name
+                );
+                return Ok(Vec::new());
+            }
+        };
+
+        let old_tree = repo.head()?.peel_to_tree()?;
+
+        let message = commit_message::describe_merge(&self.remote_description(remote_name));
+        self.merge(&source_branch, None, &message)?;
+
+        let new_tree = repo.head()?.peel_to_tree()?;
+
+        self.diff_tree_to_tree(&old_tree, &new_tree)
+    }
+
     pub fn diff_tree_to_tree(
         &self,
         old_tree: &git2::Tree,
         new_tree: &git2::Tree,
-    ) -> anyhow::Result<Vec<(PathBuf, git2::Delta)>> {
+    ) -> anyhow::Result<Vec<ChangedFile>> {
         let repo = self.inner();
 
-        let diff = repo.diff_tree_to_tree(Some(old_tree), Some(new_tree), None)?;
+        let mut diff = repo.diff_tree_to_tree(Some(old_tree), Some(new_tree), None)?;
+
+        // Otherwise renamed and copied files are reported as added, and renamed ones as deleted too
+        let mut find_options = git2::DiffFindOptions::new();
+        find_options.renames(true).copies(true);
+        diff.find_similar(Some(&mut find_options))?;
 
         let files = diff
             .deltas()
@@ -96,10 +481,11 @@ impl Repository {
                     new_file_path.display()
                 );
 
-                let file_path = self.base_path().join(new_file_path);
-                let status = delta.status();
-
-                (file_path, status)
+                ChangedFile {
+                    path: self.base_path().join(new_file_path),
+                    old_path: self.base_path().join(old_file_path),
+                    delta: delta.status(),
+                }
             })
             .collect();
 
@@ -113,40 +499,320 @@ impl Repository {
         diff_options.include_untracked(true);
 
         let diff = repo.diff_index_to_workdir(None, Some(&mut diff_options))?;
-        let diff_stats = diff.stats()?;
-        Ok(diff_stats.files_changed() > 0)
+
+        // Notes kept on this device only are still in the index, but their changes are never
+        // staged
+        let local_only_paths = self.local_only_paths()?;
+        let is_changed = diff.deltas().any(|delta| {
+            !matches!(delta.new_file().path(), Some(path) if local_only_paths.iter().any(|p| p == path))
+        });
+
+        // Files that became ignored still have to be removed from the index
+        Ok(is_changed || !self.tracked_ignored_paths()?.is_empty())
     }
 
-    pub fn is_same(&self, spec_a: &str, spec_b: &str) -> anyhow::Result<bool> {
+    /// Changes in the index compared to HEAD, used to describe the next commit
+    pub fn staged_changes(&self) -> anyhow::Result<Vec<Change>> {
         let repo = self.inner();
 
-        let object_a_id = repo.revparse_single(spec_a)?.id();
-        let object_b_id = repo.revparse_single(spec_b)?.id();
+        // HEAD is unborn before the first commit
+        let head_tree = repo
+            .head()
+            .ok()
+            .map(|head| head.peel_to_tree())
+            .transpose()?;
+        let diff = repo.diff_tree_to_index(head_tree.as_ref(), None, None)?;
+
+        let mut changes = Vec::new();
+
+        for delta in diff.deltas() {
+            let path = delta
+                .new_file()
+                .path()
+                .or_else(|| delta.old_file().path())
+                .ok_or_else(|| anyhow::anyhow!("Delta has no path"))?;
+
+            let change = match (FileKind::for_path(path), delta.status()) {
+                (FileKind::Note, git2::Delta::Added) => {
+                    Change::NoteAdded(self.note_title(path, delta.new_file().id()))
+                }
+                (FileKind::Note, git2::Delta::Deleted) => {
+                    Change::NoteDeleted(self.note_title(path, delta.old_file().id()))
+                }
+                (FileKind::Note, _) => {
+                    let old_note = self.read_note(delta.old_file().id()).ok();
+                    let new_note = self.read_note(delta.new_file().id()).ok();
+                    let title = self.note_title(path, delta.new_file().id());
+
+                    match (old_note, new_note) {
+                        (Some(old), Some(new))
+                            if !old.metadata.is_trashed && new.metadata.is_trashed =>
+                        {
+                            Change::NoteTrashed(title)
+                        }
+                        (Some(old), Some(new))
+                            if old.metadata.is_trashed && !new.metadata.is_trashed =>
+                        {
+                            Change::NoteRestored(title)
+                        }
+                        _ => Change::NoteEdited(title),
+                    }
+                }
+                (FileKind::Data, _) => Change::TagsEdited,
+                (FileKind::Other, git2::Delta::Added) => Change::AttachmentAdded,
+                (FileKind::Other, git2::Delta::Deleted) => Change::AttachmentDeleted,
+                (FileKind::Other, _) => Change::AttachmentEdited,
+            };
 
-        log::info!("Revparse spec_a: `{}` <-> `{}`", spec_a, object_a_id);
-        log::info!("Revparse spec_a: `{}` <-> `{}`", spec_b, object_b_id);
+            changes.push(change);
+        }
 
-        Ok(object_a_id == object_b_id)
+        Ok(changes)
     }
 
-    pub fn fetch(&self, remote_name: &str) -> anyhow::Result<()> {
+    /// Name of the device where each file in HEAD was last changed, keyed by the path relative
+    /// to the repository. Files last changed in commits without a device are left out.
+    ///
+    /// Only the commits made since the last call are walked, as long as HEAD moved forward.
+    pub fn last_edit_devices(&self) -> anyhow::Result<HashMap<PathBuf, String>> {
         let repo = self.inner();
 
-        let mut remote = repo.find_remote(remote_name)?;
+        // HEAD is unborn before the first commit
+        let head_commit = match repo.head() {
+            Ok(head) => head.peel_to_commit()?,
+            Err(_) => return Ok(HashMap::new()),
+        };
+        let head_tree = head_commit.tree()?;
+
+        let mut cache = self.last_edit_devices.borrow_mut();
+        // Rewritten history, e.g., after a repair, is walked again from the start
+        let mut cached = cache.take().filter(|cached| {
+            cached.commit_id == head_commit.id()
+                || repo
+                    .graph_descendant_of(head_commit.id(), cached.commit_id)
+                    .unwrap_or(false)
+        });
+
+        let mut remaining = HashSet::new();
+        head_tree.walk(git2::TreeWalkMode::PreOrder, |root, entry| {
+            if entry.kind() == Some(git2::ObjectType::Blob) {
+                if let Some(name) = entry.name() {
+                    remaining.insert(Path::new(root).join(name));
+                }
+            }
+            git2::TreeWalkResult::Ok
+        })?;
+
+        let mut revwalk = repo.revwalk()?;
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+        revwalk.push(head_commit.id())?;
+        if let Some(ref cached) = cached {
+            revwalk.hide(cached.commit_id)?;
+        }
 
-        let mut callbacks = git2::RemoteCallbacks::new();
-        callbacks.credentials(|_, username_from_url, _| Self::credentials_cb(username_from_url));
-        callbacks.transfer_progress(|ref progress| Self::transfer_progress_cb(progress));
+        let mut devices = HashMap::new();
+
+        for commit_id in revwalk {
+            if remaining.is_empty() {
+                break;
+            }
+
+            let commit = repo.find_commit(commit_id?)?;
+            let device = commit.message().and_then(commit_message::device);
+
+            for path in self.changed_paths(&commit, None)? {
+                if remaining.remove(&path) {
+                    if let Some(device) = device {
+                        devices.insert(path, device.to_string());
+                    }
+                }
+            }
+        }
+
+        // The files that were not changed since keep their device
+        if let Some(ref mut cached) = cached {
+            for path in remaining {
+                if let Some(device) = cached.devices.remove(&path) {
+                    devices.insert(path, device);
+                }
+            }
+        }
+
+        cache.replace(LastEditDevices {
+            commit_id: head_commit.id(),
+            devices: devices.clone(),
+        });
+
+        Ok(devices)
+    }
+
+    /// Commits that changed the file at `path`, relative to the repository, newest first
+    pub fn file_history(&self, path: &Path) -> anyhow::Result<Vec<Revision>> {
+        let repo = self.inner();
+
+        // HEAD is unborn before the first commit
+        if repo.head().is_err() {
+            return Ok(Vec::new());
+        }
+
+        let mut revwalk = repo.revwalk()?;
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+        revwalk.push_head()?;
+
+        let mut revisions = Vec::new();
+
+        for commit_id in revwalk {
+            let commit = repo.find_commit(commit_id?)?;
+
+            if self.changed_paths(&commit, Some(path))?.is_empty() {
+                continue;
+            }
+
+            let content = match commit.tree()?.get_path(path) {
+                Ok(entry) => {
+                    let blob = repo.find_blob(entry.id())?;
+                    Some(String::from_utf8_lossy(blob.content()).into_owned())
+                }
+                Err(err) if err.code() == git2::ErrorCode::NotFound => None,
+                Err(err) => return Err(err.into()),
+            };
+
+            let message = commit.message().unwrap_or_default();
+
+            revisions.push(Revision {
+                id: commit.id().to_string(),
+                time: commit.time().seconds(),
+                summary: commit.summary().unwrap_or_default().to_string(),
+                device: commit_message::device(message).map(str::to_string),
+                content,
+            });
+        }
+
+        Ok(revisions)
+    }
+
+    /// Paths that were changed by `commit` itself. For merges, these are the paths that differ
+    /// from all of the parents, as the others are changes that were merged in.
+    ///
+    /// When `pathspec` is given, only that path is considered.
+    fn changed_paths(
+        &self,
+        commit: &git2::Commit<'_>,
+        pathspec: Option<&Path>,
+    ) -> anyhow::Result<HashSet<PathBuf>> {
+        let repo = self.inner();
+        let tree = commit.tree()?;
+
+        let diff_paths =
+            |parent_tree: Option<&git2::Tree<'_>>| -> anyhow::Result<HashSet<PathBuf>> {
+                let mut diff_options = git2::DiffOptions::new();
+                if let Some(pathspec) = pathspec {
+                    diff_options.pathspec(pathspec).disable_pathspec_match(true);
+                }
+
+                let diff =
+                    repo.diff_tree_to_tree(parent_tree, Some(&tree), Some(&mut diff_options))?;
+                Ok(diff
+                    .deltas()
+                    .filter_map(|delta| delta.new_file().path().or_else(|| delta.old_file().path()))
+                    .map(Path::to_path_buf)
+                    .collect())
+            };
+
+        let mut changed_paths: Option<HashSet<PathBuf>> = None;
+
+        for parent in commit.parents() {
+            let paths = diff_paths(Some(&parent.tree()?))?;
+            changed_paths = Some(match changed_paths {
+                Some(changed_paths) => changed_paths.intersection(&paths).cloned().collect(),
+                None => paths,
+            });
+        }
+
+        match changed_paths {
+            Some(changed_paths) => Ok(changed_paths),
+            // The root commit added everything
+            None => diff_paths(None),
+        }
+    }
+
+    fn read_note(&self, blob_id: git2::Oid) -> anyhow::Result<RawNote> {
+        let blob = self.inner().find_blob(blob_id)?;
+        RawNote::parse(std::str::from_utf8(blob.content())?)
+    }
+
+    /// Title of the note in the blob, falling back to the file name when it has none
+    fn note_title(&self, path: &Path, blob_id: git2::Oid) -> String {
+        self.read_note(blob_id)
+            .ok()
+            .map(|note| note.metadata.title)
+            .filter(|title| !title.trim().is_empty())
+            .unwrap_or_else(|| {
+                path.file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+    }
 
-        let mut fetch_options = git2::FetchOptions::new();
-        fetch_options.remote_callbacks(callbacks);
+    /// Name of the remote with its host, used to tell where merged changes came from
+    fn remote_description(&self, remote_name: &str) -> String {
+        let host = self
+            .inner()
+            .find_remote(remote_name)
+            .ok()
+            .and_then(|remote| remote.url().and_then(|url| RemoteUrl::parse(url).ok()))
+            .and_then(|remote_url| remote_url.host().map(str::to_string));
+
+        match host {
+            Some(host) => format!("{} ({})", remote_name, host),
+            None => remote_name.to_string(),
+        }
+    }
+
+    /// Fetch the remote branch and check whether it has changes that are not in HEAD
+    pub fn is_upstream_changed(&self) -> anyhow::Result<bool> {
+        let remote_id = self.fetch_upstream(&self.upstream()?)?;
+        let head_id = self.inner().refname_to_id("HEAD")?;
+
+        Ok(matches!(remote_id, Some(remote_id) if remote_id != head_id))
+    }
+
+    /// Fetch all branches of the remote
+    pub fn fetch(&self, remote_name: &str) -> anyhow::Result<()> {
+        self.fetch_refspecs(remote_name, &[])
+    }
+
+    /// Fetch only the remote branch of `upstream`. Returns the commit it points to, or `None` if
+    /// the remote has no such branch yet.
+    fn fetch_upstream(&self, upstream: &Upstream) -> anyhow::Result<Option<git2::Oid>> {
+        self.fetch_refspecs(&upstream.remote_name, &[upstream.fetch_refspec()])?;
+
+        match self.inner().refname_to_id(&upstream.remote_ref()) {
+            Ok(id) => Ok(Some(id)),
+            Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn fetch_refspecs(&self, remote_name: &str, refspecs: &[String]) -> anyhow::Result<()> {
+        let repo = self.inner();
+
+        let mut remote = repo.find_remote(remote_name)?;
 
         log::info!("Fetching from `{}`...", remote_name);
-        remote.fetch::<&str>(&[], Some(&mut fetch_options), None)?;
+        Self::with_remote_callbacks(&self.auth_options, &self.monitor, |callbacks| {
+            let mut fetch_options = git2::FetchOptions::new();
+            fetch_options.remote_callbacks(callbacks);
+
+            remote.fetch(refspecs, Some(&mut fetch_options), None)
+        })?;
 
         Ok(())
     }
 
+    /// Add the files at `paths` to the index. Ignored files are left out, and removed from it if
+    /// they were added before, while being kept in the working directory.
     pub fn add(&self, paths: &[impl AsRef<Path>]) -> anyhow::Result<()> {
         let repo = self.inner();
 
@@ -156,10 +822,21 @@ impl Repository {
             paths.iter().map(|p| p.as_ref()),
             git2::IndexAddOption::DEFAULT,
             Some(&mut |path: &Path, _: &[u8]| {
+                // Only untracked files are skipped by default
+                if repo.is_path_ignored(path).unwrap_or(false) {
+                    return 1;
+                }
+
                 log::info!("Added match `{}`", path.display());
                 0
             }),
         )?;
+
+        for path in self.tracked_ignored_paths()? {
+            log::info!("Removing ignored file `{}` from index", path.display());
+            index.remove_path(&path)?;
+        }
+
         index.write()?;
 
         Ok(())
@@ -191,110 +868,553 @@ impl Repository {
         Ok(())
     }
 
-    // From https://github.com/GitJournal/git_bindings/blob/master/gj_common/gitjournal.c
-    pub fn merge(
-        &self,
-        source_branch: &str,
-        fetch_commit: Option<git2::AnnotatedCommit<'_>>,
-        author_name: &str,
-        author_email: &str,
-    ) -> anyhow::Result<()> {
-        let repo = self.inner();
+    /// Write the default patterns to `.gitignore`, which is committed, so they apply on every
+    /// device, and `patterns` to `info/exclude`, so they only apply on this device. Returns
+    /// whether `.gitignore` changed.
+    pub fn set_ignored_patterns(&self, patterns: &[String]) -> anyhow::Result<bool> {
+        let ignore_file_path = self.base_path().join(IGNORE_FILE_NAME);
+
+        // Patterns added by other devices are kept, so that devices with different defaults
+        // don't keep rewriting each other's file
+        let mut default_patterns = ignore_file::managed_patterns(
+            &Self::read_ignore_file(&ignore_file_path)?,
+            Section::DefaultPatterns,
+        );
+        for pattern in ignore_file::DEFAULT_PATTERNS {
+            if !default_patterns.iter().any(|p| p == pattern) {
+                default_patterns.push(pattern.to_string());
+            }
+        }
 
-        let annotated_commit = if let Some(commit) = fetch_commit {
-            commit
-        } else {
-            let origin_head_ref = repo.find_branch(source_branch, git2::BranchType::Remote)?;
-            repo.reference_to_annotated_commit(origin_head_ref.get())?
-        };
+        Self::write_managed_patterns(&self.exclude_file_path(), Section::UserPatterns, patterns)?;
+        Self::write_managed_patterns(
+            &ignore_file_path,
+            Section::DefaultPatterns,
+            &default_patterns,
+        )
+    }
 
-        let (merge_analysis, _) = repo.merge_analysis(&[&annotated_commit])?;
-        log::info!("Merge analysis: {:?}", merge_analysis);
+    /// Files, relative to the repository, that are kept out of sync on this device
+    pub fn local_only_paths(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let content = Self::read_ignore_file(&self.exclude_file_path())?;
 
-        if merge_analysis.contains(git2::MergeAnalysis::ANALYSIS_UP_TO_DATE) {
-            log::info!("Merge analysis: Up to date");
-        } else if merge_analysis.contains(git2::MergeAnalysis::ANALYSIS_UNBORN) {
-            anyhow::bail!("Merge analysis: Unborn");
-        } else if merge_analysis.contains(git2::MergeAnalysis::ANALYSIS_FASTFORWARD) {
-            log::info!("Merge analysis: Fastforwarding...");
-            let target_oid = annotated_commit.id();
-            self.perform_fastforward(target_oid)?;
-        } else if merge_analysis.contains(git2::MergeAnalysis::ANALYSIS_NORMAL) {
-            log::info!("Merge analysis: Performing normal merge...");
+        Ok(
+            ignore_file::managed_patterns(&content, Section::LocalOnlyNotes)
+                .iter()
+                .filter_map(|pattern| ignore_file::literal_path(pattern))
+                .collect(),
+        )
+    }
 
-            repo.merge(&[&annotated_commit], None, None)?;
-            let mut index = repo.index()?;
-            let conflicts = index.conflicts()?;
+    /// Keep the file at `path`, relative to the repository, out of sync on this device. Its changes
+    /// are not committed anymore, so the remote keeps the last synced version, and the changes
+    /// pulled from the remote do not overwrite it.
+    pub fn set_local_only(&self, path: &Path, is_local_only: bool) -> anyhow::Result<()> {
+        let mut paths = self.local_only_paths()?;
+        paths.retain(|local_only_path| local_only_path != path);
+        if is_local_only {
+            paths.push(path.to_owned());
+        }
+        paths.sort();
 
-            for conflict in conflicts.flatten() {
-                let our = conflict.our.unwrap();
-                let their = conflict.their.unwrap();
+        let patterns = paths
+            .iter()
+            .map(|path| ignore_file::literal_pattern(path))
+            .collect::<Vec<_>>();
+        Self::write_managed_patterns(
+            &self.exclude_file_path(),
+            Section::LocalOnlyNotes,
+            &patterns,
+        )?;
 
-                let current_conflict_path = std::str::from_utf8(&their.path).unwrap();
-                log::info!("Pull: Conflict on file `{}`", current_conflict_path);
-                self.resolve_conflict(&our)?;
-                log::info!("Resolved conflict on file `{}`", current_conflict_path);
+        Ok(())
+    }
 
-                let path = std::str::from_utf8(&our.path).unwrap();
-                let path = Path::new(&path);
+    /// Stored inside the git directory, so the files excluded by it are only excluded on this
+    /// device
+    fn exclude_file_path(&self) -> PathBuf {
+        self.inner().path().join("info").join("exclude")
+    }
 
-                let mut index = repo.index()?;
-                index.remove_path(path)?;
-                index.add_all([our.path], git2::IndexAddOption::DEFAULT, None)?;
-                index.write()?;
-            }
+    /// Content of the ignore file at `path`, which is empty if there is none
+    fn read_ignore_file(path: &Path) -> anyhow::Result<String> {
+        match fs::read_to_string(path) {
+            Ok(content) => Ok(content),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
 
-            let tree_id = index.write_tree()?;
-            let tree = repo.find_tree(tree_id)?;
-            let signature = git2::Signature::now(author_name, author_email)?;
-            let head_id = repo.refname_to_id("HEAD")?;
-            let head_commit = repo.find_commit(head_id)?;
-            let origin_head_commit = repo.find_commit(annotated_commit.id())?;
+    /// Returns whether the file at `path` changed
+    fn write_managed_patterns(
+        path: &Path,
+        section: Section,
+        patterns: &[String],
+    ) -> anyhow::Result<bool> {
+        let content = Self::read_ignore_file(path)?;
+
+        let new_content = ignore_file::with_managed_patterns(&content, section, patterns);
+        if new_content == content {
+            return Ok(false);
+        }
 
-            let parents = [&head_commit, &origin_head_commit];
-            let message = "Custom merge commit";
-            repo.commit(
-                Some("HEAD"),
-                &signature,
-                &signature,
-                message,
-                &tree,
-                &parents,
-            )?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
         }
+        fs::write(path, new_content)?;
 
-        Ok(())
+        log::info!("Updated ignored patterns in `{}`", path.display());
+
+        Ok(true)
     }
 
-    pub fn commit(
-        &self,
-        message: &str,
-        author_name: &str,
-        author_email: &str,
-    ) -> anyhow::Result<()> {
+    /// Files in the index that became ignored after they were added, besides the ones kept on this
+    /// device only, whose deletion would be synced to the other devices
+    fn tracked_ignored_paths(&self) -> anyhow::Result<Vec<PathBuf>> {
         let repo = self.inner();
+        let local_only_paths = self.local_only_paths()?;
 
-        let mut index = repo.index()?;
-        let tree_id = index.write_tree()?;
-        let tree = repo.find_tree(tree_id)?;
+        let mut paths = Vec::new();
+        for entry in repo.index()?.iter() {
+            let path = PathBuf::from(std::str::from_utf8(&entry.path)?);
 
-        let signature = git2::Signature::now(author_name, author_email)?;
+            if path != Path::new(IGNORE_FILE_NAME)
+                && !local_only_paths.contains(&path)
+                && repo.is_path_ignored(&path)?
+            {
+                paths.push(path);
+            }
+        }
 
-        log::info!("Creating commit...");
+        Ok(paths)
+    }
+
+    /// Run `operation`, which moves HEAD and returns the files it changed, without touching the
+    /// notes kept on this device only. Their content is put back afterwards and they are left out
+    /// of the changed files. If the remote renamed one, the content follows it to the new path,
+    /// which is kept on this device only instead.
+    fn with_local_only_protected(
+        &self,
+        operation: impl FnOnce() -> anyhow::Result<Vec<ChangedFile>>,
+    ) -> anyhow::Result<Vec<ChangedFile>> {
+        let local_only_paths = self.local_only_paths()?;
+        if local_only_paths.is_empty() {
+            return operation();
+        }
+
+        let mut contents = Vec::new();
+        for path in &local_only_paths {
+            match fs::read(self.base_path().join(path)) {
+                Ok(content) => contents.push((path.clone(), Some(content))),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    contents.push((path.clone(), None));
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        // Otherwise their local changes keep the checkouts from updating them
+        self.checkout_index_paths(&local_only_paths)?;
+
+        let res = operation();
+        let changed_files = res.as_deref().unwrap_or_default();
+
+        for (path, content) in contents {
+            let new_path = changed_files
+                .iter()
+                .find(|file| {
+                    file.delta == git2::Delta::Renamed
+                        && file.old_path == self.base_path().join(&path)
+                })
+                .map(|file| file.path.clone());
+
+            let full_path = new_path
+                .clone()
+                .unwrap_or_else(|| self.base_path().join(&path));
+            match content {
+                Some(content) => {
+                    if let Some(parent) = full_path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::write(&full_path, content)?;
+                }
+                // It was deleted on this device, but may have been checked out again
+                None => match fs::remove_file(&full_path) {
+                    Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                        return Err(err.into());
+                    }
+                    _ => {}
+                },
+            }
+
+            if let Some(new_path) = new_path {
+                let new_path = new_path.strip_prefix(self.base_path())?;
+                log::info!(
+                    "Keeping `{}` on this device only in place of `{}`",
+                    new_path.display(),
+                    path.display()
+                );
+                self.set_local_only(&path, false)?;
+                self.set_local_only(new_path, true)?;
+            }
+        }
+
+        let mut changed_files = res?;
+        changed_files.retain(|file| {
+            file.delta == git2::Delta::Renamed
+                || !local_only_paths
+                    .iter()
+                    .any(|path| file.path == self.base_path().join(path))
+        });
+        Ok(changed_files)
+    }
+
+    /// Overwrite the files at `paths`, relative to the repository, with their version in the index,
+    /// if they have one. The index is left as is, as it may be locked or have conflicts while
+    /// repairing.
+    fn checkout_index_paths(&self, paths: &[PathBuf]) -> anyhow::Result<()> {
+        let repo = self.inner();
+        let index = repo.index()?;
+
+        for path in paths {
+            if let Some(entry) = index.get_path(path, 0) {
+                let blob = repo.find_blob(entry.id)?;
+                fs::write(self.base_path().join(path), blob.content())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Notes that were merged with conflicts and are not yet resolved by the user
+    pub fn conflicts(&self) -> anyhow::Result<Vec<NoteConflict>> {
+        let conflicts_file_path = self.conflicts_file_path();
+
+        if !conflicts_file_path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let bytes = fs::read(conflicts_file_path)?;
+        Ok(serde_yaml::from_slice(&bytes)?)
+    }
+
+    /// Forget the conflict on the note at `path`, relative to the repository
+    pub fn remove_conflict(&self, path: &Path) -> anyhow::Result<()> {
+        let mut conflicts = self.conflicts()?;
+        conflicts.retain(|conflict| conflict.path != path);
+        self.set_conflicts(&conflicts)
+    }
+
+    fn add_conflicts(&self, new_conflicts: Vec<NoteConflict>) -> anyhow::Result<()> {
+        let mut conflicts = self.conflicts()?;
+
+        for new_conflict in new_conflicts {
+            // Keep the original base and ours if the note was still unresolved
+            if let Some(conflict) = conflicts.iter_mut().find(|c| c.path == new_conflict.path) {
+                conflict.theirs = new_conflict.theirs;
+            } else {
+                conflicts.push(new_conflict);
+            }
+        }
+
+        self.set_conflicts(&conflicts)
+    }
+
+    fn set_conflicts(&self, conflicts: &[NoteConflict]) -> anyhow::Result<()> {
+        let conflicts_file_path = self.conflicts_file_path();
+
+        if conflicts.is_empty() {
+            if conflicts_file_path.exists() {
+                fs::remove_file(conflicts_file_path)?;
+            }
+        } else {
+            fs::write(conflicts_file_path, serde_yaml::to_string(conflicts)?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Stored inside the git directory, so it is never committed
+    fn conflicts_file_path(&self) -> PathBuf {
+        self.inner().path().join(CONFLICTS_FILE_NAME)
+    }
+
+    /// Find what keeps the repository from being synced, e.g., after a crash in the middle of a
+    /// sync
+    pub fn diagnose(&self) -> anyhow::Result<Vec<Problem>> {
+        let repo = self.inner();
+        let mut problems = Vec::new();
+
+        let lock_path = repo.path().join(INDEX_LOCK_FILE_NAME);
+        if let Ok(metadata) = fs::metadata(&lock_path) {
+            let age = SystemTime::now().duration_since(metadata.modified()?);
+            if matches!(age, Ok(age) if age >= STALE_LOCK_AGE) {
+                problems.push(Problem::StaleIndexLock);
+            }
+        }
+
+        match repo.state() {
+            git2::RepositoryState::Clean => {}
+            git2::RepositoryState::Merge => problems.push(Problem::InterruptedMerge),
+            git2::RepositoryState::Rebase
+            | git2::RepositoryState::RebaseInteractive
+            | git2::RepositoryState::RebaseMerge => problems.push(Problem::InterruptedRebase),
+            state => problems.push(Problem::UnfinishedOperation(
+                problem::operation_name(state).to_string(),
+            )),
+        }
+
+        // Operations like rebases detach HEAD while they run
+        if repo.state() != git2::RepositoryState::Clean {
+            return Ok(problems);
+        }
+
+        let head = repo.find_reference("HEAD")?;
+        let local_branches = repo
+            .branches(Some(git2::BranchType::Local))?
+            .map(|branch| {
+                let (branch, _) = branch?;
+                let name = branch.name()?.unwrap_or_default().to_string();
+                Ok((name, branch.get().target()))
+            })
+            .collect::<Result<Vec<_>, git2::Error>>()?;
+
+        if let Some(head_id) = head.target() {
+            // Prefer the branch that is behind HEAD, as moving it loses nothing
+            let behind = local_branches
+                .iter()
+                .filter(|(_, tip)| {
+                    matches!(tip, Some(tip) if *tip == head_id
+                        || repo.graph_descendant_of(head_id, *tip).unwrap_or(false))
+                })
+                .collect::<Vec<_>>();
+
+            let (branch, is_fast_forward) = match (behind.as_slice(), local_branches.as_slice()) {
+                ([(name, _)], _) => (Some(name.clone()), true),
+                (_, [(name, _)]) => (Some(name.clone()), false),
+                _ => (None, false),
+            };
+
+            problems.push(Problem::DetachedHead {
+                branch,
+                is_fast_forward,
+            });
+        } else if repo.head().is_err() && !local_branches.is_empty() {
+            problems.push(Problem::UnbornBranch {
+                branches: local_branches.into_iter().map(|(name, _)| name).collect(),
+            });
+        }
+
+        Ok(problems)
+    }
+
+    /// Repair `problem`, even if it loses something. See [`Problem::can_repair`]. Returns the
+    /// files that were changed by moving HEAD.
+    pub fn repair(&self, problem: &Problem) -> anyhow::Result<Vec<ChangedFile>> {
+        let repo = self.inner();
+        log::info!("Repairing problem: {:?}", problem);
+
+        // Resetting and checking out would throw away the notes kept on this device only
+        self.with_local_only_protected(|| {
+            let old_tree = self.head_tree()?;
+
+            match problem {
+                Problem::StaleIndexLock => {
+                    fs::remove_file(repo.path().join(INDEX_LOCK_FILE_NAME))?;
+                }
+                // The conflicts of a rebase are only recorded once it is finished
+                Problem::InterruptedRebase => match repo.open_rebase(None) {
+                    Ok(mut rebase) => rebase.abort()?,
+                    Err(err) => {
+                        log::warn!("Failed to open rebase; resetting instead: {:?}", err);
+                        self.reset_to_head()?;
+                    }
+                },
+                Problem::InterruptedMerge => {
+                    // The merge only staged the notes it merged, so the conflicts on the other
+                    // ones are still unresolved
+                    let merged_paths = self.staged_paths()?;
+                    self.reset_to_head()?;
+
+                    let mut conflicts = self.conflicts()?;
+                    conflicts.retain(|conflict| !merged_paths.contains(&conflict.path));
+                    self.set_conflicts(&conflicts)?;
+                }
+                Problem::UnfinishedOperation(_) => self.reset_to_head()?,
+                Problem::DetachedHead { branch, .. } => {
+                    let branch = branch
+                        .as_ref()
+                        .ok_or_else(|| anyhow::anyhow!("No branch to reattach HEAD to"))?;
+                    let branch_ref = format!("refs/heads/{}", branch);
+                    let head_id = repo.refname_to_id("HEAD")?;
+
+                    repo.reference(&branch_ref, head_id, true, "Reattach HEAD")?;
+                    repo.set_head(&branch_ref)?;
+                }
+                Problem::UnbornBranch { branches } => {
+                    let branch = match branches.as_slice() {
+                        [branch] => branch,
+                        _ => anyhow::bail!("No single branch to check out"),
+                    };
+
+                    repo.set_head(&format!("refs/heads/{}", branch))?;
+                    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().safe()))?;
+                }
+            }
+
+            let new_tree = self.head_tree()?;
+            self.diff_tree_to_tree(&old_tree, &new_tree)
+        })
+    }
+
+    /// Repair what can be without losing anything. Returns the problems that are left and the
+    /// files that were changed by the repairs.
+    pub fn repair_safe_problems(&self) -> anyhow::Result<(Vec<Problem>, Vec<ChangedFile>)> {
+        let mut repaired = Vec::new();
+        let mut changed_files = Vec::new();
+
+        // Repair one at a time, as it may fix or reveal others, e.g., aborting a rebase
+        // reattaches HEAD
+        loop {
+            let problems = self.diagnose()?;
+
+            let problem = match problems.iter().find(|problem| problem.is_safe_to_repair()) {
+                Some(problem) => problem.clone(),
+                None => {
+                    if !problems.is_empty() {
+                        log::warn!("Problems left after repairing: {:?}", problems);
+                    }
+                    return Ok((problems, changed_files));
+                }
+            };
+
+            anyhow::ensure!(
+                !repaired.contains(&problem),
+                "Failed to repair problem: {:?}",
+                problem
+            );
+            changed_files.extend(self.repair(&problem)?);
+            repaired.push(problem);
+        }
+    }
+
+    /// Tree of HEAD, or an empty one if the branch has no commit yet
+    fn head_tree(&self) -> anyhow::Result<git2::Tree<'_>> {
+        let repo = self.inner();
+
+        match repo.head() {
+            Ok(head) => Ok(head.peel_to_tree()?),
+            Err(err) if err.code() == git2::ErrorCode::UnbornBranch => {
+                let tree_id = repo.treebuilder(None)?.write()?;
+                Ok(repo.find_tree(tree_id)?)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Paths of the changes in the index compared to HEAD, relative to the repository
+    fn staged_paths(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let head_tree = self.head_tree()?;
+        let diff = self
+            .inner()
+            .diff_tree_to_index(Some(&head_tree), None, None)?;
+
+        Ok(diff
+            .deltas()
+            .filter_map(|delta| delta.new_file().path().or_else(|| delta.old_file().path()))
+            .map(Path::to_path_buf)
+            .collect())
+    }
+
+    /// Throw away the changes of an unfinished operation
+    fn reset_to_head(&self) -> anyhow::Result<()> {
+        let repo = self.inner();
+
+        let head_commit = repo.head()?.peel_to_commit()?;
+        repo.reset(head_commit.as_object(), git2::ResetType::Hard, None)?;
+        repo.cleanup_state()?;
+
+        Ok(())
+    }
+
+    // From https://github.com/GitJournal/git_bindings/blob/master/gj_common/gitjournal.c
+    pub fn merge(
+        &self,
+        source_branch: &str,
+        fetch_commit: Option<git2::AnnotatedCommit<'_>>,
+        message: &str,
+    ) -> anyhow::Result<()> {
+        let repo = self.inner();
+
+        let annotated_commit = if let Some(commit) = fetch_commit {
+            commit
+        } else {
+            let origin_head_ref = repo.find_branch(source_branch, git2::BranchType::Remote)?;
+            repo.reference_to_annotated_commit(origin_head_ref.get())?
+        };
+
+        let (merge_analysis, _) = repo.merge_analysis(&[&annotated_commit])?;
+        log::info!("Merge analysis: {:?}", merge_analysis);
+
+        if merge_analysis.contains(git2::MergeAnalysis::ANALYSIS_UP_TO_DATE) {
+            log::info!("Merge analysis: Up to date");
+        } else if merge_analysis.contains(git2::MergeAnalysis::ANALYSIS_UNBORN) {
+            anyhow::bail!("Merge analysis: Unborn");
+        } else if merge_analysis.contains(git2::MergeAnalysis::ANALYSIS_FASTFORWARD) {
+            log::info!("Merge analysis: Fastforwarding...");
+            let target_oid = annotated_commit.id();
+            self.perform_fastforward(target_oid)?;
+        } else if merge_analysis.contains(git2::MergeAnalysis::ANALYSIS_NORMAL) {
+            log::info!("Merge analysis: Performing normal merge...");
+
+            repo.merge(&[&annotated_commit], None, None)?;
+            let mut index = repo.index()?;
+
+            let mut note_conflicts = Vec::new();
+
+            let conflicts = index.conflicts()?.collect::<Result<Vec<_>, _>>()?;
+            for conflict in conflicts {
+                if let Some(note_conflict) = self.resolve_conflict(&mut index, &conflict)? {
+                    note_conflicts.push(note_conflict);
+                }
+            }
+            index.write()?;
+
+            if !note_conflicts.is_empty() {
+                self.add_conflicts(note_conflicts)?;
+            }
+
+            let tree_id = index.write_tree()?;
+            let tree = repo.find_tree(tree_id)?;
+            let message = self.identity.sign_message(message);
+            let head_id = repo.refname_to_id("HEAD")?;
+            let head_commit = repo.find_commit(head_id)?;
+            let origin_head_commit = repo.find_commit(annotated_commit.id())?;
+
+            self.create_commit(&message, &tree, &[&head_commit, &origin_head_commit])?;
+            repo.cleanup_state()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn commit(&self, message: &str) -> anyhow::Result<()> {
+        let repo = self.inner();
+
+        let mut index = repo.index()?;
+        let tree_id = index.write_tree()?;
+        let tree = repo.find_tree(tree_id)?;
+
+        let message = self.identity.sign_message(message);
+
+        log::info!("Creating commit...");
         match repo.refname_to_id("HEAD") {
             Ok(parent_id) => {
                 let parent_commit = repo.find_commit(parent_id)?;
-                repo.commit(
-                    Some("HEAD"),
-                    &signature,
-                    &signature,
-                    message,
-                    &tree,
-                    &[&parent_commit],
-                )?;
+                self.create_commit(&message, &tree, &[&parent_commit])?;
             }
             Err(err) => {
-                repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &[])?;
+                self.create_commit(&message, &tree, &[])?;
                 log::warn!("Failed to refname_to_id: {:?}", err);
             }
         };
@@ -302,10 +1422,73 @@ impl Repository {
         Ok(())
     }
 
-    pub fn push(&self, remote_name: &str) -> anyhow::Result<()> {
+    /// Create a commit made by the app and move HEAD to it. It is signed if signing is enabled in
+    /// the signing options or in the git config.
+    fn create_commit(
+        &self,
+        message: &str,
+        tree: &git2::Tree<'_>,
+        parents: &[&git2::Commit<'_>],
+    ) -> anyhow::Result<git2::Oid> {
         let repo = self.inner();
+        let signature = self.signature()?;
+
+        let signer = self
+            .signing_options
+            .signer(&repo.config()?.snapshot()?, &signature)?;
+        let signer = match signer {
+            Some(signer) => signer,
+            None => {
+                let commit_id =
+                    repo.commit(Some("HEAD"), &signature, &signature, message, tree, parents)?;
+                return Ok(commit_id);
+            }
+        };
 
-        let mut remote = repo.find_remote(remote_name)?;
+        let commit_id =
+            self.create_signed_commit(&signer, &signature, &signature, message, tree, parents)?;
+
+        // Unlike `commit`, `commit_signed` does not move any reference
+        let head = repo.find_reference("HEAD")?;
+        let log_message = format!("commit: {}", message.lines().next().unwrap_or_default());
+        match head.symbolic_target() {
+            Some(branch_ref) => {
+                repo.reference(branch_ref, commit_id, true, &log_message)?;
+            }
+            None => repo.set_head_detached(commit_id)?,
+        }
+
+        Ok(commit_id)
+    }
+
+    fn create_signed_commit(
+        &self,
+        signer: &Signer,
+        author: &git2::Signature<'_>,
+        committer: &git2::Signature<'_>,
+        message: &str,
+        tree: &git2::Tree<'_>,
+        parents: &[&git2::Commit<'_>],
+    ) -> anyhow::Result<git2::Oid> {
+        let repo = self.inner();
+
+        let buffer = repo.commit_create_buffer(author, committer, message, tree, parents)?;
+        let content = buffer
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Commit is not valid UTF-8"))?;
+        let commit_signature = signer.sign(content)?;
+
+        log::info!("Signed commit");
+
+        Ok(repo.commit_signed(content, &commit_signature, None)?)
+    }
+
+    /// Push the checked out branch to its remote branch, which is created if needed
+    pub fn push(&self) -> anyhow::Result<()> {
+        let repo = self.inner();
+
+        let upstream = self.upstream()?;
+        let mut remote = repo.find_remote(&upstream.remote_name)?;
         let ref_head = repo.head()?;
         let ref_head_name = ref_head
             .name()
@@ -318,53 +1501,178 @@ impl Repository {
             "Head is not a direct reference"
         );
 
-        let mut callbacks = git2::RemoteCallbacks::new();
-        callbacks.credentials(|_, username_from_url, _| Self::credentials_cb(username_from_url));
-        callbacks.transfer_progress(|ref progress| Self::transfer_progress_cb(progress));
+        let refspec = format!("{}:{}", ref_head_name, upstream.merge_ref());
 
-        let mut push_options = git2::PushOptions::new();
-        push_options.remote_callbacks(callbacks);
+        log::info!("Pushing to `{}` ...", upstream);
+        Self::with_remote_callbacks(&self.auth_options, &self.monitor, |callbacks| {
+            let mut push_options = git2::PushOptions::new();
+            push_options.remote_callbacks(callbacks);
 
-        log::info!("Pushing to `{}` ...", remote_name);
-        remote.push(&[ref_head_name], Some(&mut push_options))?;
+            remote.push(&[&refspec], Some(&mut push_options))
+        })?;
 
         Ok(())
     }
 
-    pub fn pull(
-        &self,
-        remote_name: &str,
-        author_name: &str,
-        author_email: &str,
-    ) -> anyhow::Result<Vec<(PathBuf, git2::Delta)>> {
+    /// Fetch the remote branch of the checked out branch and combine it with the local one using
+    /// `strategy`
+    pub fn pull(&self, strategy: PullStrategy) -> anyhow::Result<Vec<ChangedFile>> {
         let repo = self.inner();
 
-        self.fetch(remote_name)?;
+        let upstream = self.upstream()?;
+        let fetch_id = self.fetch_upstream(&upstream)?;
+        // The fetch may have finished right before being cancelled
+        self.ensure_not_cancelled()?;
 
-        let head = repo.find_reference("HEAD")?;
-        let old_tree = head.peel_to_tree()?;
+        let fetch_id = match fetch_id {
+            Some(fetch_id) => fetch_id,
+            None => {
+                log::info!("Remote has no `{}` yet; nothing to pull", upstream);
+                return Ok(Vec::new());
+            }
+        };
 
-        let fetch_head = repo.find_reference("FETCH_HEAD")?;
-        let new_tree = fetch_head.peel_to_tree()?;
+        self.with_local_only_protected(|| {
+            let old_tree = repo.head()?.peel_to_tree()?;
 
-        let fetch_commit = repo.reference_to_annotated_commit(&fetch_head)?;
+            let fetch_commit = repo.find_annotated_commit(fetch_id)?;
 
-        // TODO better way to get this?
-        let head = repo.head()?;
-        let branch_name = head
-            .name()
-            .ok_or_else(|| anyhow::anyhow!("Ref head name not found"))?;
-        let source_branch = format!("{}/{}", remote_name, branch_name);
+            let (merge_analysis, _) = repo.merge_analysis(&[&fetch_commit])?;
+            let is_diverged = merge_analysis.contains(git2::MergeAnalysis::ANALYSIS_NORMAL);
+
+            match strategy {
+                PullStrategy::Rebase if is_diverged => self.rebase(&fetch_commit)?,
+                PullStrategy::FastForwardOnly if is_diverged => {
+                    return Err(SyncError::Diverged.into());
+                }
+                _ => {
+                    let message = commit_message::describe_merge(
+                        &self.remote_description(&upstream.remote_name),
+                    );
+                    self.merge(&upstream.to_string(), Some(fetch_commit), &message)?;
+                }
+            }
+
+            // Compare against the new HEAD instead of FETCH_HEAD, so files that are only changed
+            // locally are not reported and merged files are
+            let new_tree = repo.head()?.peel_to_tree()?;
+
+            self.diff_tree_to_tree(&old_tree, &new_tree)
+        })
+    }
+
+    /// Replay the local commits on top of `upstream`. Conflicts are resolved like on merges, with
+    /// the local side as ours. Nothing is changed if it fails.
+    fn rebase(&self, upstream: &git2::AnnotatedCommit<'_>) -> anyhow::Result<()> {
+        let repo = self.inner();
+
+        log::info!("Rebase: Rebasing onto `{}`...", upstream.id());
+
+        let mut rebase = repo.rebase(None, Some(upstream), None, None)?;
+
+        let res = self.apply_rebase(&mut rebase);
+
+        if res.is_err() {
+            if let Err(err) = rebase.abort() {
+                log::warn!("Failed to abort rebase: {:?}", err);
+            }
+        }
+
+        res
+    }
+
+    fn apply_rebase(&self, rebase: &mut git2::Rebase<'_>) -> anyhow::Result<()> {
+        let repo = self.inner();
+        let signature = self.signature()?;
+        let signer = self
+            .signing_options
+            .signer(&repo.config()?.snapshot()?, &signature)?;
+
+        let mut note_conflicts = Vec::new();
+
+        while let Some(operation) = rebase.next() {
+            let operation = operation?;
+            log::info!("Rebase: Applying `{}`", operation.id());
+
+            let mut index = repo.index()?;
+
+            let conflicts = index.conflicts()?.collect::<Result<Vec<_>, _>>()?;
+            for conflict in conflicts {
+                // While rebasing, ours is the upstream and theirs is the local commit
+                let conflict = git2::IndexConflict {
+                    ancestor: conflict.ancestor,
+                    our: conflict.their,
+                    their: conflict.our,
+                };
+
+                if let Some(note_conflict) = self.resolve_conflict(&mut index, &conflict)? {
+                    note_conflicts.push(note_conflict);
+                }
+            }
+            index.write()?;
+
+            // libgit2 cannot sign the commits it makes while rebasing
+            if let Some(ref signer) = signer {
+                if !self.commit_rebased_signed(signer, &signature, operation.id())? {
+                    log::info!("Rebase: `{}` is already applied; skipped", operation.id());
+                }
+                continue;
+            }
+
+            match rebase.commit(None, &signature, None) {
+                Ok(_) => {}
+                // The upstream already has the same changes
+                Err(err) if err.code() == git2::ErrorCode::Applied => {
+                    log::info!("Rebase: `{}` is already applied; skipped", operation.id());
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        rebase.finish(Some(&signature))?;
+
+        if !note_conflicts.is_empty() {
+            self.add_conflicts(note_conflicts)?;
+        }
+
+        log::info!("Rebase: Finished");
+
+        Ok(())
+    }
 
-        self.merge(
-            &source_branch,
-            Some(fetch_commit),
-            author_name,
-            author_email,
+    /// Commit the index in place of the commit `original_id` that is being rebased, and move HEAD
+    /// to it. Returns `false` if there is nothing to commit, as the upstream already has the same
+    /// changes.
+    fn commit_rebased_signed(
+        &self,
+        signer: &Signer,
+        committer: &git2::Signature<'_>,
+        original_id: git2::Oid,
+    ) -> anyhow::Result<bool> {
+        let repo = self.inner();
+
+        let original = repo.find_commit(original_id)?;
+        let head_commit = repo.head()?.peel_to_commit()?;
+        let tree = repo.find_tree(repo.index()?.write_tree()?)?;
+
+        if tree.id() == head_commit.tree_id() {
+            return Ok(false);
+        }
+
+        let message = original
+            .message_raw()
+            .ok_or_else(|| anyhow::anyhow!("Commit message is not valid UTF-8"))?;
+        let commit_id = self.create_signed_commit(
+            signer,
+            &original.author(),
+            committer,
+            message,
+            &tree,
+            &[&head_commit],
         )?;
+        repo.set_head_detached(commit_id)?;
 
-        let changed_files = self.diff_tree_to_tree(&old_tree, &new_tree)?;
-        Ok(changed_files)
+        Ok(true)
     }
 
     fn perform_fastforward(&self, target_oid: git2::Oid) -> anyhow::Result<()> {
@@ -379,52 +1687,1610 @@ impl Repository {
         Ok(())
     }
 
-    fn resolve_conflict(&self, our: &git2::IndexEntry) -> anyhow::Result<()> {
+    /// Merge both sides of a conflicting file, write the result to the workdir, and stage it.
+    ///
+    /// Returns a [`NoteConflict`] when the file is a note that still needs the user to resolve.
+    fn resolve_conflict(
+        &self,
+        index: &mut git2::Index,
+        conflict: &git2::IndexConflict,
+    ) -> anyhow::Result<Option<NoteConflict>> {
         let repo = self.inner();
 
-        let odb = repo.odb()?;
-        let odb_object = odb.read(our.id)?;
-        let file_data = odb_object.data();
+        let path_bytes = conflict
+            .our
+            .as_ref()
+            .or(conflict.their.as_ref())
+            .map(|entry| entry.path.clone())
+            .ok_or_else(|| anyhow::anyhow!("Conflict has neither our nor their side"))?;
+        let path = PathBuf::from(std::str::from_utf8(&path_bytes)?);
+        let full_path = self.base_path().join(&path);
+
+        log::info!("Merge: Conflict on file `{}`", path.display());
+
+        let read_blob = |entry: &Option<git2::IndexEntry>| -> anyhow::Result<Option<Vec<u8>>> {
+            entry
+                .as_ref()
+                .map(|entry| Ok(repo.find_blob(entry.id)?.content().to_vec()))
+                .transpose()
+        };
+        let ancestor = read_blob(&conflict.ancestor)?;
+        let ours = read_blob(&conflict.our)?;
+        let theirs = read_blob(&conflict.their)?;
 
-        let file_path = &our.path;
-        let file_full_path = self
-            .base_path()
-            .join(std::str::from_utf8(file_path).unwrap());
+        let (merged, note_conflict) = match (ours, theirs) {
+            (Some(ours), Some(theirs)) => {
+                self.merge_contents(index, &path, ancestor.as_deref(), &ours, &theirs)?
+            }
+            // Modified on one side and deleted on the other, keep the modified one so nothing is lost
+            (Some(remaining), None) | (None, Some(remaining)) => {
+                log::info!(
+                    "Merge: `{}` was deleted on one side only; keeping it",
+                    path.display()
+                );
+                (remaining, None)
+            }
+            (None, None) => unreachable!("conflict has either our or their side"),
+        };
 
-        let mut file = File::open(file_full_path)?;
-        file.write_all(file_data)?;
+        fs::write(&full_path, merged)?;
 
-        Ok(())
-    }
+        index.remove_path(&path)?;
+        index.add_path(&path)?;
 
-    const fn inner(&self) -> &git2::Repository {
-        &self.inner
+        log::info!("Merge: Resolved conflict on file `{}`", path.display());
+
+        Ok(note_conflict)
     }
 
-    fn credentials_cb(username_from_url: Option<&str>) -> Result<git2::Cred, git2::Error> {
-        log::info!(
-            "Credential callback with username `{}`",
-            username_from_url.unwrap()
+    /// Merge the contents of the file at `path`. Files that are made besides it are staged in
+    /// `index`.
+    fn merge_contents(
+        &self,
+        index: &mut git2::Index,
+        path: &Path,
+        ancestor: Option<&[u8]>,
+        ours: &[u8],
+        theirs: &[u8],
+    ) -> anyhow::Result<(Vec<u8>, Option<NoteConflict>)> {
+        let texts = (
+            ancestor.map(std::str::from_utf8).transpose(),
+            std::str::from_utf8(ours),
+            std::str::from_utf8(theirs),
         );
-        git2::Cred::ssh_key_from_agent(username_from_url.unwrap())
-    }
 
-    fn transfer_progress_cb(progress: &git2::Progress) -> bool {
-        if progress.received_objects() == progress.total_objects() {
-            log::info!(
-                "Resolving deltas {}/{}",
-                progress.indexed_deltas(),
-                progress.total_deltas()
-            );
-        } else if progress.total_objects() > 0 {
-            log::info!(
-                "Received {}/{} objects ({}) in {} bytes",
-                progress.received_objects(),
-                progress.total_objects(),
-                progress.indexed_objects(),
-                progress.received_bytes()
-            );
+        match (FileKind::for_path(path), texts) {
+            (FileKind::Note, (Ok(ancestor), Ok(ours), Ok(theirs))) => {
+                let (merged, is_conflicted) = match merge::merge_note(ancestor, ours, theirs) {
+                    Ok(outcome) => {
+                        if outcome.is_conflicted() {
+                            log::warn!(
+                                "Merge: `{}` has conflicts; body conflict `{}`; conflicting fields {:?}",
+                                path.display(),
+                                outcome.has_body_conflict(),
+                                outcome.conflicting_fields,
+                            );
+                        }
+
+                        (outcome.note.to_text()?, outcome.is_conflicted())
+                    }
+                    Err(err) => {
+                        // A note broken on either side must not keep the others from being pulled
+                        log::warn!(
+                            "Merge: Failed to merge note `{}`; merging it as text instead: {:?}",
+                            path.display(),
+                            err
+                        );
+                        let chunks = merge::merge_text(ancestor.unwrap_or_default(), ours, theirs);
+                        let is_conflicted = chunks.iter().any(merge::TextChunk::is_conflict);
+                        (merge::render_with_markers(&chunks), is_conflicted)
+                    }
+                };
+
+                if !is_conflicted {
+                    return Ok((merged.into_bytes(), None));
+                }
+
+                let note_conflict = NoteConflict {
+                    path: path.to_owned(),
+                    base: ancestor.map(str::to_string),
+                    ours: ours.to_string(),
+                    theirs: theirs.to_string(),
+                };
+
+                Ok((merged.into_bytes(), Some(note_conflict)))
+            }
+            (FileKind::Data, (Ok(ancestor), Ok(ours_text), Ok(theirs_text))) => {
+                match merge::merge_data_file(ancestor, ours_text, theirs_text) {
+                    Ok(merged) => Ok((merged.into_bytes(), None)),
+                    // Like for the notes, a broken data file must not keep the pull from finishing
+                    Err(err) => {
+                        log::warn!(
+                            "Merge: Failed to merge data file `{}`: {:?}",
+                            path.display(),
+                            err
+                        );
+                        self.store_conflicted_copy(index, path, theirs)?;
+                        Ok((ours.to_vec(), None))
+                    }
+                }
+            }
+            _ => {
+                // There is no sensible way to merge binary files, so store theirs next to ours
+                self.store_conflicted_copy(index, path, theirs)?;
+                Ok((ours.to_vec(), None))
+            }
+        }
+    }
+
+    /// Write `theirs` beside the file at `path`, relative to the repository, and stage it
+    fn store_conflicted_copy(
+        &self,
+        index: &mut git2::Index,
+        path: &Path,
+        theirs: &[u8],
+    ) -> anyhow::Result<()> {
+        let copy_path = conflicted_copy_path(path);
+        log::warn!(
+            "Merge: Cannot merge `{}`; storing their version at `{}`",
+            path.display(),
+            copy_path.display()
+        );
+        fs::write(self.base_path().join(&copy_path), theirs)?;
+        index.add_path(&copy_path)?;
+
+        Ok(())
+    }
+
+    fn signature(&self) -> anyhow::Result<git2::Signature<'static>> {
+        self.identity.signature(&self.inner().config()?)
+    }
+
+    const fn inner(&self) -> &git2::Repository {
+        &self.inner
+    }
+
+    fn ensure_not_cancelled(&self) -> anyhow::Result<()> {
+        if self.monitor.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+
+        Ok(())
+    }
+
+    /// Run a remote operation, reporting its progress to `monitor` and turning authentication
+    /// failures into [`super::AuthError`]
+    ///
+    /// Fetches are aborted as soon as `monitor` is cancelled. Pushes can only be aborted before
+    /// uploading, as libgit2 has no way to stop them after.
+    fn with_remote_callbacks<T>(
+        auth_options: &AuthOptions,
+        monitor: &TransferMonitor,
+        f: impl FnOnce(git2::RemoteCallbacks<'_>) -> Result<T, git2::Error>,
+    ) -> anyhow::Result<T> {
+        if monitor.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+        monitor.reset_progress();
+
+        let credentials_handler = CredentialsHandler::new(auth_options);
+
+        let mut callbacks = git2::RemoteCallbacks::new();
+        callbacks.credentials(|url, username_from_url, allowed_types| {
+            credentials_handler.credentials(url, username_from_url, allowed_types)
+        });
+        callbacks.transfer_progress(|progress| {
+            monitor.update_transfer(&progress);
+            !monitor.is_cancelled()
+        });
+        callbacks.sideband_progress(|_| !monitor.is_cancelled());
+        callbacks.pack_progress(|stage, current, total| {
+            monitor.update_pack(stage, current, total);
+        });
+        callbacks.push_transfer_progress(|current, total, bytes| {
+            monitor.update_push(current, total, bytes);
+        });
+        callbacks.push_negotiation(|_| {
+            if monitor.is_cancelled() {
+                Err(git2::Error::from_str("Push was cancelled"))
+            } else {
+                Ok(())
+            }
+        });
+
+        let res = f(callbacks);
+
+        let progress = monitor.progress();
+        log::info!(
+            "Transferred {}/{} objects in {} bytes; pushed {}/{} objects in {} bytes",
+            progress.received_objects,
+            progress.total_objects,
+            progress.received_bytes,
+            progress.pushed_objects,
+            progress.total_pushed_objects,
+            progress.pushed_bytes
+        );
+
+        res.map_err(|err| {
+            if monitor.is_cancelled() {
+                Cancelled.into()
+            } else {
+                credentials_handler.convert_error(err)
+            }
+        })
+    }
+}
+
+/// Returns a path beside `path` with ` (conflicted copy)` appended to its file stem
+fn conflicted_copy_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_stem().map(OsString::from).unwrap_or_default();
+    file_name.push(" (conflicted copy)");
+
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+
+    path.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use temp_dir::TempDir;
+
+    const AUTHOR_NAME: &str = "Test";
+    const AUTHOR_EMAIL: &str = "test@example.com";
+
+    const BASE_NOTE: &str = "title: Title\ntag_list: []\nattachment_list: []\nis_pinned: false\nis_trashed: false\n---\nLine 1\nLine 2\nLine 3\n";
+
+    /// Returns a repository standing for the remote and a clone of it
+    fn setup_cloned(files: &[(&str, &str)]) -> (TempDir, Repository, TempDir, Repository) {
+        let remote_dir = TempDir::new().unwrap();
+        let mut remote = Repository::init(remote_dir.path()).unwrap();
+        remote.set_identity(identity("Remote"));
+        for (file_name, content) in files {
+            write_file(&remote, file_name, content);
+        }
+        commit_all(&remote, "Initial commit");
+
+        let local_dir = TempDir::new().unwrap();
+        let mut local = Repository::clone(
+            local_dir.path(),
+            remote_dir.path().to_str().unwrap(),
+            "origin",
+            None,
+            AuthOptions::default(),
+            Arc::default(),
+        )
+        .unwrap();
+        local.set_identity(identity("Local"));
+
+        (remote_dir, remote, local_dir, local)
+    }
+
+    fn identity(device_name: &str) -> Identity {
+        Identity {
+            author_name: Some(AUTHOR_NAME.into()),
+            author_email: Some(AUTHOR_EMAIL.into()),
+            device_name: Some(device_name.into()),
+        }
+    }
+
+    fn write_file(repo: &Repository, file_name: &str, content: &str) {
+        fs::write(repo.base_path().join(file_name), content).unwrap();
+    }
+
+    fn read_file(repo: &Repository, file_name: &str) -> String {
+        fs::read_to_string(repo.base_path().join(file_name)).unwrap()
+    }
+
+    fn commit_all(repo: &Repository, message: &str) {
+        repo.add(&["."]).unwrap();
+        repo.commit(message).unwrap();
+    }
+
+    fn pull(repo: &Repository) -> Vec<(PathBuf, git2::Delta)> {
+        pull_with(repo, PullStrategy::Merge)
+    }
+
+    fn pull_with(repo: &Repository, strategy: PullStrategy) -> Vec<(PathBuf, git2::Delta)> {
+        repo.pull(strategy)
+            .unwrap()
+            .into_iter()
+            .map(|file| (file.path, file.delta))
+            .collect()
+    }
+
+    fn head_message(repo: &Repository) -> String {
+        repo.inner()
+            .head()
+            .unwrap()
+            .peel_to_commit()
+            .unwrap()
+            .message()
+            .unwrap()
+            .to_string()
+    }
+
+    fn head_parent_count(repo: &Repository) -> usize {
+        repo.inner()
+            .head()
+            .unwrap()
+            .peel_to_commit()
+            .unwrap()
+            .parent_count()
+    }
+
+    #[test]
+    fn pull_fast_forward() {
+        let (_remote_dir, remote, _local_dir, local) = setup_cloned(&[("note.md", BASE_NOTE)]);
+
+        let edited_note = BASE_NOTE.replace("Line 2", "Line 2 edited");
+        write_file(&remote, "note.md", &edited_note);
+        commit_all(&remote, "Edit note");
+
+        let changed_files = pull(&local);
+        assert_eq!(
+            changed_files,
+            vec![(local.base_path().join("note.md"), git2::Delta::Modified)]
+        );
+        assert_eq!(read_file(&local, "note.md"), edited_note);
+        assert_eq!(head_parent_count(&local), 1);
+    }
+
+    #[test]
+    fn pull_detects_renamed_note() {
+        let (_remote_dir, remote, _local_dir, local) = setup_cloned(&[("note.md", BASE_NOTE)]);
+
+        fs::rename(
+            remote.base_path().join("note.md"),
+            remote.base_path().join("renamed.md"),
+        )
+        .unwrap();
+        let mut index = remote.inner().index().unwrap();
+        index.remove_path(Path::new("note.md")).unwrap();
+        index.write().unwrap();
+        commit_all(&remote, "Rename note");
+
+        let changed_files = local.pull(PullStrategy::Merge).unwrap();
+        assert_eq!(
+            changed_files,
+            vec![ChangedFile {
+                path: local.base_path().join("renamed.md"),
+                old_path: local.base_path().join("note.md"),
+                delta: git2::Delta::Renamed,
+            }]
+        );
+        assert_eq!(read_file(&local, "renamed.md"), BASE_NOTE);
+        assert!(!local.base_path().join("note.md").exists());
+    }
+
+    #[test]
+    fn pull_cancelled() {
+        let (_remote_dir, remote, _local_dir, local) = setup_cloned(&[("note.md", BASE_NOTE)]);
+
+        let edited_note = BASE_NOTE.replace("Line 2", "Line 2 edited");
+        write_file(&remote, "note.md", &edited_note);
+        commit_all(&remote, "Edit note");
+
+        local.monitor().cancel();
+        let err = local.pull(PullStrategy::Merge).unwrap_err();
+        assert!(err.is::<Cancelled>());
+        assert_eq!(read_file(&local, "note.md"), BASE_NOTE);
+
+        local.monitor().reset();
+        pull(&local);
+        assert_eq!(read_file(&local, "note.md"), edited_note);
+        assert!(local.monitor().progress().total_objects > 0);
+    }
+
+    #[test]
+    fn pull_merges_divergent_note_edits() {
+        let (_remote_dir, remote, _local_dir, local) = setup_cloned(&[("note.md", BASE_NOTE)]);
+
+        write_file(
+            &remote,
+            "note.md",
+            &BASE_NOTE
+                .replace("Line 3", "Line 3 from remote")
+                .replace("is_pinned: false", "is_pinned: true"),
+        );
+        commit_all(&remote, "Edit note on remote");
+
+        write_file(
+            &local,
+            "note.md",
+            &BASE_NOTE
+                .replace("Line 1", "Line 1 from local")
+                .replace("tag_list: []", "tag_list:\n- A"),
+        );
+        commit_all(&local, "Edit note on local");
+
+        let changed_files = pull(&local);
+        assert_eq!(
+            changed_files,
+            vec![(local.base_path().join("note.md"), git2::Delta::Modified)]
+        );
+
+        let merged = merge::RawNote::parse(&read_file(&local, "note.md")).unwrap();
+        assert_eq!(
+            merged.body,
+            "Line 1 from local\nLine 2\nLine 3 from remote\n"
+        );
+        assert_eq!(merged.metadata.tag_list, vec!["A"]);
+        assert!(merged.metadata.is_pinned);
+
+        assert_eq!(head_parent_count(&local), 2);
+        assert_eq!(
+            head_message(&local),
+            commit_message::with_device("Merge changes from origin", "Local")
+        );
+        assert_eq!(local.inner().state(), git2::RepositoryState::Clean);
+        assert!(!local.is_file_changed_in_workdir().unwrap());
+        assert!(local.conflicts().unwrap().is_empty());
+    }
+
+    #[test]
+    fn pull_overlapping_note_edits_adds_conflict_markers() {
+        let (_remote_dir, remote, _local_dir, local) = setup_cloned(&[("note.md", BASE_NOTE)]);
+
+        write_file(&remote, "note.md", &BASE_NOTE.replace("Line 2", "Remote"));
+        commit_all(&remote, "Edit note on remote");
+
+        write_file(&local, "note.md", &BASE_NOTE.replace("Line 2", "Local"));
+        commit_all(&local, "Edit note on local");
+
+        pull(&local);
+
+        let merged = merge::RawNote::parse(&read_file(&local, "note.md")).unwrap();
+        assert_eq!(
+            merged.body,
+            "Line 1\n<<<<<<< ours\nLocal\n=======\nRemote\n>>>>>>> theirs\nLine 3\n"
+        );
+        assert_eq!(head_parent_count(&local), 2);
+        assert_eq!(local.inner().state(), git2::RepositoryState::Clean);
+
+        let conflicts = local.conflicts().unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, Path::new("note.md"));
+        assert_eq!(conflicts[0].base.as_deref(), Some(BASE_NOTE));
+        assert!(conflicts[0].ours.contains("Local"));
+        assert!(conflicts[0].theirs.contains("Remote"));
+
+        local.remove_conflict(Path::new("note.md")).unwrap();
+        assert!(local.conflicts().unwrap().is_empty());
+    }
+
+    #[test]
+    fn pull_rebase_keeps_history_linear() {
+        let (_remote_dir, remote, _local_dir, local) =
+            setup_cloned(&[("note.md", BASE_NOTE), ("other.md", BASE_NOTE)]);
+
+        write_file(&remote, "note.md", &BASE_NOTE.replace("Line 3", "Remote"));
+        commit_all(&remote, "Edit note on remote");
+
+        write_file(&local, "other.md", &BASE_NOTE.replace("Line 1", "Local"));
+        commit_all(&local, "Edit other note on local");
+
+        let changed_files = pull_with(&local, PullStrategy::Rebase);
+        assert_eq!(
+            changed_files,
+            vec![(local.base_path().join("note.md"), git2::Delta::Modified)]
+        );
+        assert_eq!(
+            read_file(&local, "note.md"),
+            BASE_NOTE.replace("Line 3", "Remote")
+        );
+        assert_eq!(
+            read_file(&local, "other.md"),
+            BASE_NOTE.replace("Line 1", "Local")
+        );
+
+        assert_eq!(head_parent_count(&local), 1);
+        assert_eq!(
+            head_message(&local),
+            commit_message::with_device("Edit other note on local", "Local")
+        );
+        assert_eq!(local.inner().state(), git2::RepositoryState::Clean);
+        assert!(!local.is_file_changed_in_workdir().unwrap());
+    }
+
+    /// Make `repo` sign its commits with a new SSH key, through the git config
+    fn enable_ssh_signing(repo: &Repository, key_dir: &TempDir) {
+        let key_path = key_dir.path().join("id_ed25519");
+        let status = std::process::Command::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-f"])
+            .arg(&key_path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let mut config = repo.inner().config().unwrap();
+        config.set_bool("commit.gpgsign", true).unwrap();
+        config.set_str("gpg.format", "ssh").unwrap();
+        config
+            .set_str("user.signingkey", key_path.to_str().unwrap())
+            .unwrap();
+    }
+
+    fn is_signed(repo: &Repository, commit_id: git2::Oid) -> bool {
+        match repo.inner().extract_signature(&commit_id, None) {
+            Ok((signature, _)) => signature
+                .as_str()
+                .unwrap()
+                .starts_with("-----BEGIN SSH SIGNATURE-----"),
+            Err(_) => false,
+        }
+    }
+
+    fn head_id(repo: &Repository) -> git2::Oid {
+        repo.inner().refname_to_id("HEAD").unwrap()
+    }
+
+    #[test]
+    fn commit_signed() {
+        let (_dir, repo) = setup_offline(&[("note.md", BASE_NOTE)]);
+        assert!(!is_signed(&repo, head_id(&repo)));
+        let parent_id = head_id(&repo);
+
+        let key_dir = TempDir::new().unwrap();
+        enable_ssh_signing(&repo, &key_dir);
+
+        write_file(&repo, "note.md", &BASE_NOTE.replace("Line 1", "Signed"));
+        commit_all(&repo, "Edit note");
+
+        let head = repo.inner().head().unwrap();
+        assert_eq!(
+            head.shorthand(),
+            Some(repo.local_branch().unwrap().as_str())
+        );
+        assert!(is_signed(&repo, head_id(&repo)));
+        let head_commit = head.peel_to_commit().unwrap();
+        assert_eq!(
+            head_commit.parent_ids().collect::<Vec<_>>(),
+            vec![parent_id]
+        );
+        assert_eq!(
+            head_message(&repo),
+            commit_message::with_device("Edit note", "Local")
+        );
+        assert!(!repo.is_file_changed_in_workdir().unwrap());
+    }
+
+    #[test]
+    fn commit_signing_failure() {
+        let (_dir, repo) = setup_offline(&[("note.md", BASE_NOTE)]);
+        let head_id_before = head_id(&repo);
+
+        let mut config = repo.inner().config().unwrap();
+        config.set_bool("commit.gpgsign", true).unwrap();
+        config.set_str("gpg.format", "ssh").unwrap();
+        config
+            .set_str("user.signingkey", "/nonexistent/key")
+            .unwrap();
+
+        write_file(&repo, "note.md", &BASE_NOTE.replace("Line 1", "Unsigned"));
+        repo.add(&["."]).unwrap();
+        let err = repo.commit("Edit note").unwrap_err();
+        assert!(matches!(SyncError::from(err), SyncError::Signing(_)));
+        assert_eq!(head_id(&repo), head_id_before);
+    }
+
+    #[test]
+    fn pull_merge_signed() {
+        let (_remote_dir, remote, _local_dir, local) =
+            setup_cloned(&[("note.md", BASE_NOTE), ("other.md", BASE_NOTE)]);
+        let key_dir = TempDir::new().unwrap();
+        enable_ssh_signing(&local, &key_dir);
+
+        write_file(&remote, "note.md", &BASE_NOTE.replace("Line 3", "Remote"));
+        commit_all(&remote, "Edit note on remote");
+
+        write_file(&local, "other.md", &BASE_NOTE.replace("Line 1", "Local"));
+        commit_all(&local, "Edit other note on local");
+
+        pull(&local);
+        assert_eq!(head_parent_count(&local), 2);
+        assert!(is_signed(&local, head_id(&local)));
+    }
+
+    #[test]
+    fn pull_rebase_signed() {
+        let (_remote_dir, remote, _local_dir, local) =
+            setup_cloned(&[("note.md", BASE_NOTE), ("other.md", BASE_NOTE)]);
+        let key_dir = TempDir::new().unwrap();
+        enable_ssh_signing(&local, &key_dir);
+
+        write_file(&remote, "note.md", &BASE_NOTE.replace("Line 3", "Remote"));
+        commit_all(&remote, "Edit note on remote");
+
+        write_file(&local, "other.md", &BASE_NOTE.replace("Line 1", "Local"));
+        commit_all(&local, "Edit other note on local");
+        write_file(&local, "note.md", &BASE_NOTE.replace("Line 3", "Remote"));
+        commit_all(&local, "Make the same edit on local");
+
+        pull_with(&local, PullStrategy::Rebase);
+        assert_eq!(head_parent_count(&local), 1);
+        assert_eq!(
+            head_message(&local),
+            commit_message::with_device("Edit other note on local", "Local")
+        );
+        assert!(is_signed(&local, head_id(&local)));
+        assert_eq!(
+            read_file(&local, "other.md"),
+            BASE_NOTE.replace("Line 1", "Local")
+        );
+
+        // The remote commit is not rewritten
+        let parent_id = local
+            .inner()
+            .head()
+            .unwrap()
+            .peel_to_commit()
+            .unwrap()
+            .parent_id(0)
+            .unwrap();
+        assert_eq!(parent_id, head_id(&remote));
+        assert_eq!(local.inner().state(), git2::RepositoryState::Clean);
+        assert_eq!(
+            local.upstream().unwrap(),
+            Upstream::new("origin", &local.local_branch().unwrap())
+        );
+        assert_eq!(
+            local.inner().head().unwrap().shorthand(),
+            Some(local.local_branch().unwrap().as_str())
+        );
+    }
+
+    #[test]
+    fn pull_rebase_overlapping_note_edits_adds_conflict_markers() {
+        let (_remote_dir, remote, _local_dir, local) = setup_cloned(&[("note.md", BASE_NOTE)]);
+
+        write_file(&remote, "note.md", &BASE_NOTE.replace("Line 2", "Remote"));
+        commit_all(&remote, "Edit note on remote");
+
+        write_file(&local, "note.md", &BASE_NOTE.replace("Line 2", "Local"));
+        commit_all(&local, "Edit note on local");
+
+        pull_with(&local, PullStrategy::Rebase);
+
+        let merged = merge::RawNote::parse(&read_file(&local, "note.md")).unwrap();
+        assert_eq!(
+            merged.body,
+            "Line 1\n<<<<<<< ours\nLocal\n=======\nRemote\n>>>>>>> theirs\nLine 3\n"
+        );
+        assert_eq!(head_parent_count(&local), 1);
+        assert_eq!(local.inner().state(), git2::RepositoryState::Clean);
+
+        let conflicts = local.conflicts().unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert!(conflicts[0].ours.contains("Local"));
+        assert!(conflicts[0].theirs.contains("Remote"));
+    }
+
+    #[test]
+    fn pull_fast_forward_only_refuses_diverged() {
+        let (_remote_dir, remote, _local_dir, local) = setup_cloned(&[("note.md", BASE_NOTE)]);
+
+        write_file(&remote, "note.md", &BASE_NOTE.replace("Line 3", "Remote"));
+        commit_all(&remote, "Edit note on remote");
+
+        let local_note = BASE_NOTE.replace("Line 1", "Local");
+        write_file(&local, "note.md", &local_note);
+        commit_all(&local, "Edit note on local");
+
+        let err = local.pull(PullStrategy::FastForwardOnly).unwrap_err();
+        assert_eq!(err.downcast::<SyncError>().unwrap(), SyncError::Diverged);
+        assert_eq!(read_file(&local, "note.md"), local_note);
+        assert_eq!(
+            head_message(&local),
+            commit_message::with_device("Edit note on local", "Local")
+        );
+    }
+
+    #[test]
+    fn pull_keeps_note_modified_locally_but_deleted_on_remote() {
+        let (_remote_dir, remote, _local_dir, local) =
+            setup_cloned(&[("note.md", BASE_NOTE), ("other.md", BASE_NOTE)]);
+
+        remote.remove(&["note.md"]).unwrap();
+        commit_all(&remote, "Delete note on remote");
+
+        let edited_note = BASE_NOTE.replace("Line 1", "Line 1 edited");
+        write_file(&local, "note.md", &edited_note);
+        commit_all(&local, "Edit note on local");
+
+        pull(&local);
+
+        assert_eq!(read_file(&local, "note.md"), edited_note);
+        assert_eq!(head_parent_count(&local), 2);
+    }
+
+    #[test]
+    fn pull_merges_data_file_tag_list() {
+        let (_remote_dir, remote, _local_dir, local) =
+            setup_cloned(&[(DATA_FILE_NAME, "tag_list:\n- A\n")]);
+
+        write_file(&remote, DATA_FILE_NAME, "tag_list:\n- A\n- Remote\n");
+        commit_all(&remote, "Add tag on remote");
+
+        write_file(&local, DATA_FILE_NAME, "tag_list:\n- A\n- Local\n");
+        commit_all(&local, "Add tag on local");
+
+        pull(&local);
+
+        assert_eq!(
+            read_file(&local, DATA_FILE_NAME),
+            "tag_list:\n- A\n- Local\n- Remote\n"
+        );
+    }
+
+    #[test]
+    fn pull_stores_conflicted_copy_of_broken_data_file() {
+        let (_remote_dir, remote, _local_dir, local) =
+            setup_cloned(&[(DATA_FILE_NAME, "tag_list:\n- A\n")]);
+
+        write_file(&remote, DATA_FILE_NAME, "tag_list:\n- A\n- Remote\n");
+        commit_all(&remote, "Add tag on remote");
+
+        write_file(&local, DATA_FILE_NAME, "tag_list: [A\n");
+        commit_all(&local, "Break data file on local");
+
+        pull(&local);
+
+        assert_eq!(read_file(&local, DATA_FILE_NAME), "tag_list: [A\n");
+        assert_eq!(
+            read_file(&local, "data (conflicted copy).nwty"),
+            "tag_list:\n- A\n- Remote\n"
+        );
+        assert!(is_committed(&local, "data (conflicted copy).nwty"));
+    }
+
+    #[test]
+    fn pull_stores_conflicted_copy_of_binary_file() {
+        let (_remote_dir, remote, _local_dir, local) = setup_cloned(&[("picture.png", "base")]);
+
+        write_file(&remote, "picture.png", "remote");
+        commit_all(&remote, "Edit picture on remote");
+
+        write_file(&local, "picture.png", "local");
+        commit_all(&local, "Edit picture on local");
+
+        pull(&local);
+
+        assert_eq!(read_file(&local, "picture.png"), "local");
+        assert_eq!(read_file(&local, "picture (conflicted copy).png"), "remote");
+        assert!(is_committed(&local, "picture (conflicted copy).png"));
+        assert!(!local.is_file_changed_in_workdir().unwrap());
+    }
+
+    #[test]
+    fn pull_merges_broken_note_as_text() {
+        let (_remote_dir, remote, _local_dir, local) =
+            setup_cloned(&[("note.md", BASE_NOTE), ("other.md", BASE_NOTE)]);
+
+        write_file(
+            &remote,
+            "note.md",
+            &BASE_NOTE.replace("Line 1", "Line 1 remote"),
+        );
+        write_file(
+            &remote,
+            "other.md",
+            &BASE_NOTE.replace("Line 1", "Line 1 remote"),
+        );
+        commit_all(&remote, "Edit notes on remote");
+
+        let broken_note = BASE_NOTE
+            .replace("title: Title", "title: [Title")
+            .replace("Line 3", "Line 3 local");
+        write_file(&local, "note.md", &broken_note);
+        write_file(
+            &local,
+            "other.md",
+            &BASE_NOTE.replace("Line 3", "Line 3 local"),
+        );
+        commit_all(&local, "Edit notes on local");
+
+        pull(&local);
+
+        assert_eq!(
+            read_file(&local, "note.md"),
+            broken_note.replace("Line 1", "Line 1 remote")
+        );
+        assert_eq!(
+            read_file(&local, "other.md"),
+            BASE_NOTE
+                .replace("Line 1", "Line 1 remote")
+                .replace("Line 3", "Line 3 local")
+        );
+        assert!(local.conflicts().unwrap().is_empty());
+        assert_eq!(head_parent_count(&local), 2);
+    }
+
+    #[test]
+    fn pull_flags_conflict_in_broken_note() {
+        let (_remote_dir, remote, _local_dir, local) = setup_cloned(&[("note.md", BASE_NOTE)]);
+
+        write_file(
+            &remote,
+            "note.md",
+            &BASE_NOTE.replace("Line 2", "Line 2 remote"),
+        );
+        commit_all(&remote, "Edit note on remote");
+
+        let broken_note = BASE_NOTE
+            .replace("title: Title", "title: [Title")
+            .replace("Line 2", "Line 2 local");
+        write_file(&local, "note.md", &broken_note);
+        commit_all(&local, "Edit note on local");
+
+        pull(&local);
+
+        assert!(read_file(&local, "note.md").contains(merge::OURS_MARKER));
+        let conflicts = local.conflicts().unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, Path::new("note.md"));
+        assert_eq!(conflicts[0].ours, broken_note);
+    }
+
+    fn setup_offline(files: &[(&str, &str)]) -> (TempDir, Repository) {
+        let dir = TempDir::new().unwrap();
+        let mut repo = Repository::init(dir.path()).unwrap();
+        repo.set_identity(identity("Local"));
+        for (file_name, content) in files {
+            write_file(&repo, file_name, content);
+        }
+        commit_all(&repo, "Initial commit");
+
+        (dir, repo)
+    }
+
+    #[test]
+    fn attach_empty_remote() {
+        let (_local_dir, local) = setup_offline(&[("note.md", BASE_NOTE)]);
+        let remote_dir = TempDir::new().unwrap();
+        Repository::init(remote_dir.path()).unwrap();
+
+        let remote_url = remote_dir.path().to_str().unwrap();
+        let changed_files = local.attach_remote("origin", None, remote_url).unwrap();
+        assert!(changed_files.is_empty());
+        assert_eq!(local.remotes().unwrap(), vec!["origin".to_string()]);
+        assert_eq!(local.remote_url().as_deref(), Some(remote_url));
+
+        local.detach_remote().unwrap();
+        assert!(local.remotes().unwrap().is_empty());
+        assert_eq!(local.remote_url(), None);
+    }
+
+    #[test]
+    fn attach_remote_with_chosen_branch() {
+        let (_local_dir, local) = setup_offline(&[("note.md", BASE_NOTE)]);
+        let remote_dir = TempDir::new().unwrap();
+        Repository::init(remote_dir.path()).unwrap();
+
+        let remote_url = remote_dir.path().to_str().unwrap();
+        let changed_files = local
+            .attach_remote("upstream", Some("notes"), remote_url)
+            .unwrap();
+        assert!(changed_files.is_empty());
+        assert_eq!(local.local_branch().unwrap(), "notes");
+        assert_eq!(
+            local.upstream().unwrap(),
+            Upstream::new("upstream", "notes")
+        );
+        assert_eq!(local.remote_url().as_deref(), Some(remote_url));
+
+        // The remote branch is only created on the first push
+        assert!(local.pull(PullStrategy::Merge).unwrap().is_empty());
+        assert!(!local.is_upstream_changed().unwrap());
+    }
+
+    #[test]
+    fn clone_chosen_branch() {
+        let remote_dir = TempDir::new().unwrap();
+        let mut remote = Repository::init(remote_dir.path()).unwrap();
+        remote.set_identity(identity("Remote"));
+        write_file(&remote, "code.rs", "fn main() {}\n");
+        commit_all(&remote, "Add code");
+        let default_branch = remote.local_branch().unwrap();
+
+        // Keep the notes on their own branch, next to the default one
+        let checkout_notes_branch = |remote: &Repository| {
+            remote.inner().set_head("refs/heads/notes").unwrap();
+            remote
+                .inner()
+                .checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+                .unwrap();
+        };
+        let head_commit = remote.inner().head().unwrap().peel_to_commit().unwrap();
+        remote.inner().branch("notes", &head_commit, false).unwrap();
+        checkout_notes_branch(&remote);
+        write_file(&remote, "note.md", BASE_NOTE);
+        commit_all(&remote, "Add note");
+        remote
+            .inner()
+            .set_head(&format!("refs/heads/{}", default_branch))
+            .unwrap();
+
+        let local_dir = TempDir::new().unwrap();
+        let mut local = Repository::clone(
+            local_dir.path(),
+            remote_dir.path().to_str().unwrap(),
+            "upstream",
+            Some("notes"),
+            AuthOptions::default(),
+            Arc::default(),
+        )
+        .unwrap();
+        local.set_identity(identity("Local"));
+
+        assert_eq!(local.local_branch().unwrap(), "notes");
+        assert_eq!(
+            local.upstream().unwrap(),
+            Upstream::new("upstream", "notes")
+        );
+        assert_eq!(read_file(&local, "note.md"), BASE_NOTE);
+
+        // Pulls from the chosen branch, not the default one of the remote
+        checkout_notes_branch(&remote);
+        let edited_note = BASE_NOTE.replace("Line 2", "Line 2 edited");
+        write_file(&remote, "note.md", &edited_note);
+        commit_all(&remote, "Edit note");
+
+        assert!(local.is_upstream_changed().unwrap());
+        assert_eq!(
+            pull(&local),
+            vec![(local.base_path().join("note.md"), git2::Delta::Modified)]
+        );
+        assert_eq!(read_file(&local, "note.md"), edited_note);
+        assert!(!local.is_upstream_changed().unwrap());
+    }
+
+    #[test]
+    fn clone_missing_branch() {
+        let (remote_dir, _remote, _local_dir, _local) = setup_cloned(&[("note.md", BASE_NOTE)]);
+
+        let local_dir = TempDir::new().unwrap();
+        assert!(Repository::clone(
+            local_dir.path(),
+            remote_dir.path().to_str().unwrap(),
+            "origin",
+            Some("missing"),
+            AuthOptions::default(),
+            Arc::default(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn clone_cancelled() {
+        let (remote_dir, _remote, _local_dir, _local) = setup_cloned(&[("note.md", BASE_NOTE)]);
+
+        let monitor = Arc::<TransferMonitor>::default();
+        monitor.cancel();
+
+        let local_dir = TempDir::new().unwrap();
+        let err = Repository::clone(
+            local_dir.path(),
+            remote_dir.path().to_str().unwrap(),
+            "origin",
+            None,
+            AuthOptions::default(),
+            monitor,
+        )
+        .unwrap_err();
+        assert!(err.is::<Cancelled>());
+    }
+
+    #[test]
+    fn upstream_without_tracking() {
+        let (_remote_dir, _remote, _local_dir, local) = setup_cloned(&[("note.md", BASE_NOTE)]);
+        let local_branch = local.local_branch().unwrap();
+        assert_eq!(
+            local.upstream().unwrap(),
+            Upstream::new("origin", &local_branch)
+        );
+
+        let mut config = local.inner().config().unwrap();
+        config
+            .remove(&format!("branch.{}.remote", local_branch))
+            .unwrap();
+        config
+            .remove(&format!("branch.{}.merge", local_branch))
+            .unwrap();
+
+        assert_eq!(
+            local.upstream().unwrap(),
+            Upstream::new(DEFAULT_REMOTE_NAME, &local_branch)
+        );
+    }
+
+    #[test]
+    fn initialize_cloned_empty_remote() {
+        let remote_dir = TempDir::new().unwrap();
+        Repository::init(remote_dir.path()).unwrap();
+
+        let local_dir = TempDir::new().unwrap();
+        let mut local = Repository::clone(
+            local_dir.path(),
+            remote_dir.path().to_str().unwrap(),
+            "origin",
+            Some("notes"),
+            AuthOptions::default(),
+            Arc::default(),
+        )
+        .unwrap();
+        local.set_identity(identity("Local"));
+
+        assert!(local.initialize_if_empty().unwrap());
+        assert_eq!(local.local_branch().unwrap(), "notes");
+        assert_eq!(head_parent_count(&local), 0);
+
+        assert!(!local.initialize_if_empty().unwrap());
+    }
+
+    #[test]
+    fn attach_remote_with_unrelated_history() {
+        let (_local_dir, local) = setup_offline(&[("local.md", BASE_NOTE)]);
+
+        let remote_dir = TempDir::new().unwrap();
+        let mut remote = Repository::init(remote_dir.path()).unwrap();
+        remote.set_identity(identity("Remote"));
+        write_file(&remote, "remote.md", BASE_NOTE);
+        commit_all(&remote, "Initial commit");
+
+        let changed_files = local
+            .attach_remote("origin", None, remote_dir.path().to_str().unwrap())
+            .unwrap();
+        assert_eq!(
+            changed_files,
+            vec![ChangedFile {
+                path: local.base_path().join("remote.md"),
+                old_path: local.base_path().join("remote.md"),
+                delta: git2::Delta::Added,
+            }]
+        );
+        assert_eq!(read_file(&local, "local.md"), BASE_NOTE);
+        assert_eq!(read_file(&local, "remote.md"), BASE_NOTE);
+        assert_eq!(head_parent_count(&local), 2);
+    }
+
+    #[test]
+    fn attach_unreachable_remote() {
+        let (_local_dir, local) = setup_offline(&[("note.md", BASE_NOTE)]);
+        let missing_dir = TempDir::new().unwrap();
+
+        assert!(local
+            .attach_remote(
+                "origin",
+                None,
+                &missing_dir.path().join("missing").display().to_string()
+            )
+            .is_err());
+        assert!(local.remotes().unwrap().is_empty());
+    }
+
+    #[test]
+    fn repair_interrupted_merge() {
+        let (_remote_dir, remote, _local_dir, local) =
+            setup_cloned(&[("note.md", BASE_NOTE), ("other.md", BASE_NOTE)]);
+
+        // Left unresolved by an earlier pull
+        let other_conflict = NoteConflict {
+            path: PathBuf::from("other.md"),
+            base: Some(BASE_NOTE.to_string()),
+            ours: BASE_NOTE.replace("Line 1", "Line 1 ours"),
+            theirs: BASE_NOTE.replace("Line 1", "Line 1 theirs"),
+        };
+        local.add_conflicts(vec![other_conflict.clone()]).unwrap();
+
+        write_file(
+            &remote,
+            "note.md",
+            &BASE_NOTE.replace("Line 1", "Line 1 remote"),
+        );
+        commit_all(&remote, "Edit note on remote");
+        write_file(
+            &local,
+            "note.md",
+            &BASE_NOTE.replace("Line 1", "Line 1 local"),
+        );
+        commit_all(&local, "Edit note locally");
+
+        // Leave the merge unfinished, like a crash in the middle of a pull
+        local.fetch("origin").unwrap();
+        let fetch_head = local.inner().find_reference("FETCH_HEAD").unwrap();
+        let fetch_commit = local
+            .inner()
+            .reference_to_annotated_commit(&fetch_head)
+            .unwrap();
+        local.inner().merge(&[&fetch_commit], None, None).unwrap();
+        local
+            .add_conflicts(vec![NoteConflict {
+                path: PathBuf::from("note.md"),
+                base: Some(BASE_NOTE.to_string()),
+                ours: BASE_NOTE.replace("Line 1", "Line 1 local"),
+                theirs: BASE_NOTE.replace("Line 1", "Line 1 remote"),
+            }])
+            .unwrap();
+
+        assert_eq!(local.diagnose().unwrap(), vec![Problem::InterruptedMerge]);
+        assert_eq!(
+            local.repair_safe_problems().unwrap(),
+            (Vec::new(), Vec::new())
+        );
+        assert_eq!(local.inner().state(), git2::RepositoryState::Clean);
+        assert_eq!(
+            read_file(&local, "note.md"),
+            BASE_NOTE.replace("Line 1", "Line 1 local")
+        );
+        assert_eq!(local.conflicts().unwrap(), vec![other_conflict]);
+    }
+
+    #[test]
+    fn repair_stale_index_lock() {
+        let (_dir, repo) = setup_offline(&[("note.md", BASE_NOTE)]);
+
+        let lock_path = repo.inner().path().join(INDEX_LOCK_FILE_NAME);
+        fs::File::create(&lock_path).unwrap();
+        assert!(repo.diagnose().unwrap().is_empty());
+
+        let stale_time =
+            filetime::FileTime::from_system_time(SystemTime::now() - STALE_LOCK_AGE * 2);
+        filetime::set_file_mtime(&lock_path, stale_time).unwrap();
+        assert_eq!(repo.diagnose().unwrap(), vec![Problem::StaleIndexLock]);
+        assert_eq!(
+            repo.repair_safe_problems().unwrap(),
+            (Vec::new(), Vec::new())
+        );
+        assert!(!lock_path.exists());
+    }
+
+    #[test]
+    fn repair_detached_head() {
+        let (_dir, repo) = setup_offline(&[("note.md", BASE_NOTE)]);
+        let branch = repo.local_branch().unwrap();
+
+        let head_id = repo.inner().refname_to_id("HEAD").unwrap();
+        repo.inner().set_head_detached(head_id).unwrap();
+        write_file(
+            &repo,
+            "note.md",
+            &BASE_NOTE.replace("Line 1", "Line 1 edited"),
+        );
+        commit_all(&repo, "Edit note while detached");
+
+        assert_eq!(
+            repo.diagnose().unwrap(),
+            vec![Problem::DetachedHead {
+                branch: Some(branch.clone()),
+                is_fast_forward: true,
+            }]
+        );
+        assert_eq!(
+            repo.repair_safe_problems().unwrap(),
+            (Vec::new(), Vec::new())
+        );
+        assert_eq!(repo.local_branch().unwrap(), branch);
+        assert_eq!(
+            head_message(&repo),
+            commit_message::with_device("Edit note while detached", "Local")
+        );
+    }
+
+    #[test]
+    fn repair_diverged_detached_head_only_when_asked() {
+        let (_dir, repo) = setup_offline(&[("note.md", BASE_NOTE)]);
+        let branch = repo.local_branch().unwrap();
+
+        let first_id = repo.inner().refname_to_id("HEAD").unwrap();
+        write_file(
+            &repo,
+            "note.md",
+            &BASE_NOTE.replace("Line 1", "Line 1 on branch"),
+        );
+        commit_all(&repo, "Edit note on branch");
+        repo.inner().set_head_detached(first_id).unwrap();
+        repo.inner()
+            .checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+        write_file(
+            &repo,
+            "note.md",
+            &BASE_NOTE.replace("Line 1", "Line 1 detached"),
+        );
+        commit_all(&repo, "Edit note while detached");
+
+        let problem = Problem::DetachedHead {
+            branch: Some(branch.clone()),
+            is_fast_forward: false,
+        };
+        assert_eq!(
+            repo.repair_safe_problems().unwrap(),
+            (vec![problem.clone()], Vec::new())
+        );
+
+        assert!(repo.repair(&problem).unwrap().is_empty());
+        assert!(repo.diagnose().unwrap().is_empty());
+        assert_eq!(repo.local_branch().unwrap(), branch);
+        assert_eq!(
+            read_file(&repo, "note.md"),
+            BASE_NOTE.replace("Line 1", "Line 1 detached")
+        );
+    }
+
+    #[test]
+    fn repair_unborn_branch() {
+        let (_dir, repo) = setup_offline(&[("note.md", BASE_NOTE)]);
+        let branch = repo.local_branch().unwrap();
+
+        repo.inner().set_head("refs/heads/missing").unwrap();
+        assert_eq!(
+            repo.diagnose().unwrap(),
+            vec![Problem::UnbornBranch {
+                branches: vec![branch.clone()]
+            }]
+        );
+        let (problems, changed_files) = repo.repair_safe_problems().unwrap();
+        assert!(problems.is_empty());
+        assert_eq!(
+            changed_files,
+            vec![ChangedFile {
+                path: repo.base_path().join("note.md"),
+                old_path: repo.base_path().join("note.md"),
+                delta: git2::Delta::Added,
+            }]
+        );
+        assert_eq!(repo.local_branch().unwrap(), branch);
+    }
+
+    #[test]
+    fn diagnose_new_repository() {
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        assert!(repo.diagnose().unwrap().is_empty());
+    }
+
+    fn is_committed(repo: &Repository, file_name: &str) -> bool {
+        repo.head_tree()
+            .unwrap()
+            .get_path(Path::new(file_name))
+            .is_ok()
+    }
+
+    #[test]
+    fn add_leaves_out_ignored_files() {
+        let (_dir, repo) = setup_offline(&[("note.md", BASE_NOTE)]);
+        assert!(repo.set_ignored_patterns(&["*.bak".into()]).unwrap());
+        assert!(!repo.set_ignored_patterns(&["*.bak".into()]).unwrap());
+
+        write_file(&repo, ".DS_Store", "");
+        write_file(&repo, "note.md.swp", "");
+        write_file(&repo, "note.md.bak", BASE_NOTE);
+        assert!(repo.is_file_changed_in_workdir().unwrap());
+        commit_all(&repo, "Ignore files");
+
+        assert!(is_committed(&repo, ".gitignore"));
+        assert!(is_committed(&repo, "note.md"));
+        assert!(!is_committed(&repo, ".DS_Store"));
+        assert!(!is_committed(&repo, "note.md.swp"));
+        assert!(!is_committed(&repo, "note.md.bak"));
+        assert!(!repo.is_file_changed_in_workdir().unwrap());
+
+        // Patterns of the user only apply on this device
+        assert!(!read_file(&repo, ".gitignore").contains("*.bak"));
+    }
+
+    #[test]
+    fn ignored_patterns_are_per_device() {
+        let (_remote_dir, remote, _local_dir, local) = setup_cloned(&[("note.md", BASE_NOTE)]);
+        remote.set_ignored_patterns(&["*.bak".into()]).unwrap();
+        commit_all(&remote, "Ignore backups");
+        pull(&local);
+
+        // The other device sees the same `.gitignore`, so it is not rewritten
+        assert!(!local.set_ignored_patterns(&["*.orig".into()]).unwrap());
+        assert!(!local.is_file_changed_in_workdir().unwrap());
+
+        write_file(&local, "note.md.bak", BASE_NOTE);
+        write_file(&local, "note.md.orig", BASE_NOTE);
+        assert!(local.is_file_changed_in_workdir().unwrap());
+        commit_all(&local, "Add files");
+        assert!(is_committed(&local, "note.md.bak"));
+        assert!(!is_committed(&local, "note.md.orig"));
+    }
+
+    #[test]
+    fn add_removes_files_that_became_ignored() {
+        let (_dir, repo) = setup_offline(&[("note.md", BASE_NOTE), ("note.md.bak", BASE_NOTE)]);
+        assert!(is_committed(&repo, "note.md.bak"));
+
+        repo.set_ignored_patterns(&["*.bak".into()]).unwrap();
+        commit_all(&repo, "Ignore backups");
+
+        assert!(!is_committed(&repo, "note.md.bak"));
+        assert!(repo.base_path().join("note.md.bak").exists());
+        assert!(!repo.is_file_changed_in_workdir().unwrap());
+    }
+
+    #[test]
+    fn local_only_note() {
+        let (_dir, repo) = setup_offline(&[("note.md", BASE_NOTE), ("Other Note.md", BASE_NOTE)]);
+        let path = Path::new("Other Note.md");
+
+        repo.set_local_only(path, true).unwrap();
+        assert_eq!(repo.local_only_paths().unwrap(), vec![path.to_owned()]);
+        // It is not deleted from the other devices
+        assert!(!repo.is_file_changed_in_workdir().unwrap());
+
+        // Editing it changes nothing to sync
+        write_file(&repo, "Other Note.md", "---\nEdited\n");
+        assert!(!repo.is_file_changed_in_workdir().unwrap());
+        write_file(&repo, "note.md", "---\nEdited\n");
+        commit_all(&repo, "Edit note");
+        assert_eq!(
+            repo.head_tree()
+                .unwrap()
+                .get_path(path)
+                .unwrap()
+                .to_object(repo.inner())
+                .unwrap()
+                .peel_to_blob()
+                .unwrap()
+                .content(),
+            BASE_NOTE.as_bytes()
+        );
+
+        repo.set_local_only(path, false).unwrap();
+        assert!(repo.local_only_paths().unwrap().is_empty());
+        assert!(repo.is_file_changed_in_workdir().unwrap());
+        commit_all(&repo, "Sync note again");
+        assert!(!repo.is_file_changed_in_workdir().unwrap());
+    }
+
+    #[test]
+    fn pull_keeps_local_only_note() {
+        let (_remote_dir, remote, _local_dir, local) =
+            setup_cloned(&[("note.md", BASE_NOTE), ("Other Note.md", BASE_NOTE)]);
+        local
+            .set_local_only(Path::new("Other Note.md"), true)
+            .unwrap();
+        write_file(&local, "Other Note.md", "---\nLocal\n");
+
+        write_file(&remote, "Other Note.md", "---\nRemote\n");
+        write_file(&remote, "note.md", "---\nRemote\n");
+        commit_all(&remote, "Edit notes");
+
+        let changed_files = pull(&local);
+        assert_eq!(
+            changed_files,
+            vec![(local.base_path().join("note.md"), git2::Delta::Modified)]
+        );
+        assert_eq!(read_file(&local, "Other Note.md"), "---\nLocal\n");
+        assert_eq!(read_file(&local, "note.md"), "---\nRemote\n");
+        assert!(!local.is_file_changed_in_workdir().unwrap());
+
+        // Also when both sides have new commits
+        write_file(&local, "note.md", "---\nLocal\n");
+        commit_all(&local, "Edit note");
+        write_file(&remote, "Other Note.md", "---\nRemote again\n");
+        commit_all(&remote, "Edit note again");
+
+        for strategy in [PullStrategy::Rebase, PullStrategy::Merge] {
+            pull_with(&local, strategy);
+            assert_eq!(read_file(&local, "Other Note.md"), "---\nLocal\n");
         }
-        true
+        assert!(local.conflicts().unwrap().is_empty());
+    }
+
+    #[test]
+    fn pull_keeps_local_only_note_deleted_on_remote() {
+        let (_remote_dir, remote, _local_dir, local) =
+            setup_cloned(&[("note.md", BASE_NOTE), ("Other Note.md", BASE_NOTE)]);
+        local
+            .set_local_only(Path::new("Other Note.md"), true)
+            .unwrap();
+        write_file(&local, "Other Note.md", "---\nLocal\n");
+
+        remote.remove(&["Other Note.md"]).unwrap();
+        remote.commit("Delete note").unwrap();
+
+        assert!(pull(&local).is_empty());
+        assert_eq!(read_file(&local, "Other Note.md"), "---\nLocal\n");
+        assert!(!local.is_file_changed_in_workdir().unwrap());
+    }
+
+    #[test]
+    fn pull_moves_local_only_note_renamed_on_remote() {
+        let (_remote_dir, remote, _local_dir, local) =
+            setup_cloned(&[("note.md", BASE_NOTE), ("Other Note.md", BASE_NOTE)]);
+        local
+            .set_local_only(Path::new("Other Note.md"), true)
+            .unwrap();
+        write_file(
+            &local,
+            "Other Note.md",
+            &BASE_NOTE.replace("Line 1", "Local"),
+        );
+
+        fs::rename(
+            remote.base_path().join("Other Note.md"),
+            remote.base_path().join("Renamed Note.md"),
+        )
+        .unwrap();
+        commit_all(&remote, "Rename note");
+
+        let changed_files = local.pull(PullStrategy::Merge).unwrap();
+        assert_eq!(changed_files.len(), 1);
+        assert_eq!(changed_files[0].delta, git2::Delta::Renamed);
+        assert_eq!(
+            changed_files[0].path,
+            local.base_path().join("Renamed Note.md")
+        );
+
+        assert!(!local.base_path().join("Other Note.md").exists());
+        assert_eq!(
+            read_file(&local, "Renamed Note.md"),
+            BASE_NOTE.replace("Line 1", "Local")
+        );
+        assert_eq!(
+            local.local_only_paths().unwrap(),
+            vec![PathBuf::from("Renamed Note.md")]
+        );
+        assert!(!local.is_file_changed_in_workdir().unwrap());
+    }
+
+    #[test]
+    fn staged_changes() {
+        let (_remote_dir, _remote, _local_dir, local) = setup_cloned(&[
+            ("edited.md", BASE_NOTE),
+            ("trashed.md", BASE_NOTE),
+            ("deleted.md", &BASE_NOTE.replace("Title", "Old draft")),
+            ("data.nwty", "tag_list: []\n"),
+        ]);
+
+        write_file(&local, "edited.md", &BASE_NOTE.replace("Line 1", "Edited"));
+        write_file(
+            &local,
+            "trashed.md",
+            &BASE_NOTE.replace("is_trashed: false", "is_trashed: true"),
+        );
+        fs::remove_file(local.base_path().join("deleted.md")).unwrap();
+        write_file(&local, "Note.md", "---\nBody\n");
+        write_file(&local, "Camera.png", "png");
+        write_file(&local, "data.nwty", "tag_list:\n- A\n");
+        local.add(&["."]).unwrap();
+        local
+            .inner()
+            .index()
+            .unwrap()
+            .remove_path(Path::new("deleted.md"))
+            .unwrap();
+
+        let mut changes = local.staged_changes().unwrap();
+        changes.sort_by_key(|change| format!("{:?}", change));
+        assert_eq!(
+            changes,
+            vec![
+                Change::AttachmentAdded,
+                Change::NoteAdded("Note".into()),
+                Change::NoteDeleted("Old draft".into()),
+                Change::NoteEdited("Title".into()),
+                Change::NoteTrashed("Title".into()),
+                Change::TagsEdited,
+            ]
+        );
+    }
+
+    #[test]
+    fn commit_author() {
+        let (_remote_dir, _remote, _local_dir, local) = setup_cloned(&[("note.md", BASE_NOTE)]);
+
+        let commit = local.inner().head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(commit.author().name(), Some(AUTHOR_NAME));
+        assert_eq!(commit.author().email(), Some(AUTHOR_EMAIL));
+    }
+
+    #[test]
+    fn last_edit_devices() {
+        let (_remote_dir, remote, _local_dir, local) = setup_cloned(&[
+            ("a.md", BASE_NOTE),
+            ("b.md", BASE_NOTE),
+            ("c.md", BASE_NOTE),
+        ]);
+
+        write_file(&remote, "a.md", &BASE_NOTE.replace("Line 1", "Remote"));
+        commit_all(&remote, "Edit a on remote");
+
+        write_file(&local, "b.md", &BASE_NOTE.replace("Line 1", "Local"));
+        commit_all(&local, "Edit b on local");
+
+        // The merge commit is made on local, but `a.md` was changed on remote
+        pull(&local);
+
+        let devices = local.last_edit_devices().unwrap();
+        assert_eq!(
+            devices,
+            HashMap::from([
+                (PathBuf::from("a.md"), "Remote".to_string()),
+                (PathBuf::from("b.md"), "Local".to_string()),
+                (PathBuf::from("c.md"), "Remote".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn last_edit_devices_are_updated() {
+        let (_remote_dir, remote, local_dir, local) =
+            setup_cloned(&[("a.md", BASE_NOTE), ("b.md", BASE_NOTE)]);
+        assert_eq!(
+            local.last_edit_devices().unwrap(),
+            HashMap::from([
+                (PathBuf::from("a.md"), "Remote".to_string()),
+                (PathBuf::from("b.md"), "Remote".to_string()),
+            ])
+        );
+
+        write_file(&local, "a.md", &BASE_NOTE.replace("Line 1", "Local"));
+        write_file(&local, "c.md", BASE_NOTE);
+        commit_all(&local, "Edit a on local");
+        remote.remove(&["b.md"]).unwrap();
+        commit_all(&remote, "Delete b on remote");
+        pull(&local);
+
+        let expected = HashMap::from([
+            (PathBuf::from("a.md"), "Local".to_string()),
+            (PathBuf::from("c.md"), "Local".to_string()),
+        ]);
+        assert_eq!(local.last_edit_devices().unwrap(), expected);
+
+        // Same as walking the whole history
+        let reopened = Repository::open(local_dir.path()).unwrap();
+        assert_eq!(reopened.last_edit_devices().unwrap(), expected);
+    }
+
+    #[test]
+    fn file_history() {
+        let (_remote_dir, remote, _local_dir, local) =
+            setup_cloned(&[("note.md", BASE_NOTE), ("other.md", BASE_NOTE)]);
+
+        let edited_note = BASE_NOTE.replace("Line 1", "Remote");
+        write_file(&remote, "note.md", &edited_note);
+        commit_all(&remote, "Edit note on remote");
+
+        write_file(&local, "other.md", &BASE_NOTE.replace("Line 1", "Local"));
+        commit_all(&local, "Edit other on local");
+
+        pull(&local);
+
+        let history = local.file_history(Path::new("note.md")).unwrap();
+        let summaries = history
+            .iter()
+            .map(|revision| revision.summary.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(summaries, ["Edit note on remote", "Initial commit"]);
+
+        assert_eq!(history[0].device.as_deref(), Some("Remote"));
+        assert_eq!(history[0].content.as_deref(), Some(edited_note.as_str()));
+        assert_eq!(history[1].content.as_deref(), Some(BASE_NOTE));
+
+        assert!(local
+            .file_history(Path::new("missing.md"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn conflicted_copy() {
+        assert_eq!(
+            conflicted_copy_path(Path::new("/notes/a.png")),
+            Path::new("/notes/a (conflicted copy).png")
+        );
+        assert_eq!(
+            conflicted_copy_path(Path::new("/notes/a")),
+            Path::new("/notes/a (conflicted copy)")
+        );
     }
 }
@@ -6,21 +6,58 @@ use gtk::{
 };
 use once_cell::unsync::OnceCell;
 
-use std::{thread, time::Duration};
+use std::{
+    cell::{Cell, RefCell},
+    future::Future,
+    sync::{mpsc, Arc},
+    thread,
+    time::Duration,
+};
+
+use super::{auth::AuthOptions, sync_progress::TransferMonitor, Repository};
+use crate::{spawn, spawn_blocking};
 
-use super::Repository;
+const DEFAULT_POLL_INTERVAL_SECS: u32 = 30;
+const MAX_BACKOFF_DELAY: Duration = Duration::from_secs(15 * 60);
 
-const DEFAULT_SLEEP_TIME_SECS: u64 = 3;
+/// Messages sent from the main thread to the watcher thread
+#[derive(Debug)]
+enum Command {
+    SetPollInterval(Duration),
+    SetPaused(bool),
+    SetAuthOptions(AuthOptions),
+    Stop,
+}
 
 mod imp {
     use super::*;
     use glib::subclass::Signal;
     use once_cell::sync::Lazy;
 
-    #[derive(Default, Debug)]
+    #[derive(Debug)]
     pub struct RepositoryWatcher {
         pub base_path: OnceCell<gio::File>,
-        pub remote_name: OnceCell<String>,
+        pub poll_interval: Cell<u32>,
+        pub is_paused: Cell<bool>,
+
+        pub command_sender: RefCell<Option<mpsc::Sender<Command>>>,
+        pub thread_handle: RefCell<Option<thread::JoinHandle<()>>>,
+        pub monitor: Arc<TransferMonitor>,
+        pub network_monitor_handler_ids: RefCell<Vec<glib::SignalHandlerId>>,
+    }
+
+    impl Default for RepositoryWatcher {
+        fn default() -> Self {
+            Self {
+                base_path: OnceCell::default(),
+                poll_interval: Cell::new(DEFAULT_POLL_INTERVAL_SECS),
+                is_paused: Cell::default(),
+                command_sender: RefCell::default(),
+                thread_handle: RefCell::default(),
+                monitor: Arc::default(),
+                network_monitor_handler_ids: RefCell::default(),
+            }
+        }
     }
 
     #[glib::object_subclass]
@@ -47,12 +84,21 @@ mod imp {
                         gio::File::static_type(),
                         glib::ParamFlags::READWRITE | glib::ParamFlags::CONSTRUCT_ONLY,
                     ),
-                    glib::ParamSpecString::new(
-                        "remote-name",
-                        "Remote Name",
-                        "Remote name where the repo will be stored (e.g. origin)",
-                        None,
-                        glib::ParamFlags::READWRITE | glib::ParamFlags::CONSTRUCT_ONLY,
+                    glib::ParamSpecUInt::new(
+                        "poll-interval",
+                        "Poll Interval",
+                        "Seconds between checks for remote changes",
+                        1,
+                        u32::MAX,
+                        DEFAULT_POLL_INTERVAL_SECS,
+                        glib::ParamFlags::READWRITE | glib::ParamFlags::EXPLICIT_NOTIFY,
+                    ),
+                    glib::ParamSpecBoolean::new(
+                        "is-paused",
+                        "Is Paused",
+                        "Whether checking is paused because of no or metered connection",
+                        false,
+                        glib::ParamFlags::READABLE,
                     ),
                 ]
             });
@@ -61,7 +107,7 @@ mod imp {
 
         fn set_property(
             &self,
-            _obj: &Self::Type,
+            obj: &Self::Type,
             _id: usize,
             value: &glib::Value,
             pspec: &glib::ParamSpec,
@@ -71,18 +117,19 @@ mod imp {
                     let base_path = value.get().unwrap();
                     self.base_path.set(base_path).unwrap();
                 }
-                "remote-name" => {
-                    let remote_name = value.get().unwrap();
-                    self.remote_name.set(remote_name).unwrap();
+                "poll-interval" => {
+                    let poll_interval = value.get().unwrap();
+                    obj.set_poll_interval(poll_interval);
                 }
                 _ => unimplemented!(),
             }
         }
 
-        fn property(&self, _obj: &Self::Type, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        fn property(&self, obj: &Self::Type, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
             match pspec.name() {
                 "base-path" => self.base_path.get().to_value(),
-                "remote-name" => self.remote_name.get().to_value(),
+                "poll-interval" => obj.poll_interval().to_value(),
+                "is-paused" => obj.is_paused().to_value(),
                 _ => unimplemented!(),
             }
         }
@@ -90,8 +137,18 @@ mod imp {
         fn constructed(&self, obj: &Self::Type) {
             self.parent_constructed(obj);
 
+            obj.setup_network_monitor();
             obj.setup();
         }
+
+        fn dispose(&self, obj: &Self::Type) {
+            let network_monitor = gio::NetworkMonitor::default();
+            for handler_id in self.network_monitor_handler_ids.take() {
+                network_monitor.disconnect(handler_id);
+            }
+
+            spawn!(obj.stop());
+        }
     }
 }
 
@@ -100,8 +157,9 @@ glib::wrapper! {
 }
 
 impl RepositoryWatcher {
-    pub fn new(base_path: &gio::File, remote_name: &str) -> Self {
-        glib::Object::new(&[("base-path", &base_path), ("remote-name", &remote_name)])
+    /// Check the remote branch that the checked out branch of the repository at `base_path` tracks
+    pub fn new(base_path: &gio::File) -> Self {
+        glib::Object::new(&[("base-path", &base_path)])
             .expect("Failed to create RepositoryWatcher.")
     }
 
@@ -116,38 +174,130 @@ impl RepositoryWatcher {
         })
     }
 
+    pub fn poll_interval(&self) -> u32 {
+        self.imp().poll_interval.get()
+    }
+
+    /// Set the seconds to wait between checks. Note that this is the minimum, as the delay grows
+    /// after failed checks.
+    pub fn set_poll_interval(&self, poll_interval: u32) {
+        if poll_interval == self.poll_interval() {
+            return;
+        }
+
+        self.imp().poll_interval.set(poll_interval);
+        self.send_command(Command::SetPollInterval(Duration::from_secs(
+            poll_interval.into(),
+        )));
+        self.notify("poll-interval");
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.imp().is_paused.get()
+    }
+
+    /// Authenticate with the remote using `auth_options`, like the syncs of the session
+    pub fn set_auth_options(&self, auth_options: AuthOptions) {
+        self.send_command(Command::SetAuthOptions(auth_options));
+    }
+
+    /// Stop checking for remote changes. The running check is cancelled and the watcher thread is
+    /// joined in the thread pool, as that blocks until the fetch gives up. The returned future
+    /// resolves once the thread has ended.
+    pub fn stop(&self) -> impl Future<Output = ()> {
+        let imp = self.imp();
+
+        imp.monitor.cancel();
+        self.send_command(Command::Stop);
+        imp.command_sender.replace(None);
+
+        log::info!("Stopping watcher thread");
+
+        let join = imp.thread_handle.take().map(|thread_handle| {
+            spawn_blocking!(move || {
+                if thread_handle.join().is_err() {
+                    log::error!("Watcher thread panicked");
+                }
+            })
+        });
+
+        async move {
+            if let Some(join) = join {
+                join.await;
+            }
+        }
+    }
+
     fn base_path(&self) -> gio::File {
         self.property("base-path")
     }
 
-    fn remote_name(&self) -> String {
-        self.property("remote-name")
+    fn send_command(&self, command: Command) {
+        if let Some(ref sender) = *self.imp().command_sender.borrow() {
+            // This only fails when the thread already ended, which it logs itself
+            let _ = sender.send(command);
+        }
+    }
+
+    fn update_is_paused(&self) {
+        let network_monitor = gio::NetworkMonitor::default();
+        let is_paused =
+            !network_monitor.is_network_available() || network_monitor.is_network_metered();
+
+        if is_paused == self.is_paused() {
+            return;
+        }
+
+        if is_paused {
+            log::info!("No connection or connection is metered; pausing watcher");
+        } else {
+            log::info!("Connection is available; resuming watcher");
+        }
+
+        self.imp().is_paused.set(is_paused);
+        self.send_command(Command::SetPaused(is_paused));
+        self.notify("is-paused");
+    }
+
+    fn setup_network_monitor(&self) {
+        let network_monitor = gio::NetworkMonitor::default();
+
+        let handler_ids = vec![
+            network_monitor.connect_network_changed(clone!(@weak self as obj => move |_, _| {
+                obj.update_is_paused();
+            })),
+            network_monitor.connect_network_metered_notify(clone!(@weak self as obj => move |_| {
+                obj.update_is_paused();
+            })),
+        ];
+        self.imp().network_monitor_handler_ids.replace(handler_ids);
+
+        self.update_is_paused();
     }
 
     fn setup(&self) {
         let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT_IDLE);
+        let (command_sender, command_receiver) = mpsc::channel();
 
         let base_path = self.base_path().path().unwrap();
-        let remote_name = self.remote_name();
+        let poll_interval = Duration::from_secs(self.poll_interval().into());
+        let is_paused = self.is_paused();
+        let monitor = Arc::clone(&self.imp().monitor);
 
-        // FIXME join and end the thread properly when `self` is dropped
-        thread::spawn(move || match Repository::open(&base_path) {
-            Ok(repo) => {
+        let thread_handle = thread::spawn(move || match Repository::open(&base_path) {
+            Ok(mut repo) => {
                 log::info!("Starting watcher thread...");
+                repo.set_monitor(monitor);
 
-                loop {
-                    repo.fetch(&remote_name).unwrap_or_else(|err| {
-                        log::error!("Failed to fetch to origin: {:?}", err);
-                    });
-                    if let Ok(is_same) = repo.is_same("HEAD", "FETCH_HEAD") {
-                        sender.send(is_same).unwrap_or_else(|err| {
-                            log::error!("Failed to send message to channel: {:?}", err);
-                        });
-                    } else {
-                        log::error!("Failed to compare HEAD from FETCH_HEAD");
-                    }
-                    thread::sleep(Duration::from_secs(DEFAULT_SLEEP_TIME_SECS));
-                }
+                let mut watcher = Watcher {
+                    repo,
+                    poll_interval,
+                    is_paused,
+                    n_failures: 0,
+                };
+                watcher.run(&command_receiver, &sender);
+
+                log::info!("Watcher thread ended");
             }
             Err(err) => {
                 log::error!(
@@ -158,14 +308,127 @@ impl RepositoryWatcher {
             }
         });
 
+        let imp = self.imp();
+        imp.command_sender.replace(Some(command_sender));
+        imp.thread_handle.replace(Some(thread_handle));
+
         receiver.attach(
             None,
-            clone!(@weak self as obj => @default-return Continue(false), move |is_same| {
-                if !is_same {
-                    obj.emit_by_name::<()>("remote-changed", &[]);
-                }
+            clone!(@weak self as obj => @default-return Continue(false), move |_| {
+                obj.emit_by_name::<()>("remote-changed", &[]);
                 Continue(true)
             }),
         );
     }
 }
+
+/// State of the watcher thread
+struct Watcher {
+    repo: Repository,
+    poll_interval: Duration,
+    is_paused: bool,
+    n_failures: u32,
+}
+
+impl Watcher {
+    fn run(&mut self, command_receiver: &mpsc::Receiver<Command>, sender: &glib::Sender<()>) {
+        loop {
+            let res = if self.is_paused {
+                command_receiver
+                    .recv()
+                    .map_err(|_| mpsc::RecvTimeoutError::Disconnected)
+            } else {
+                command_receiver.recv_timeout(self.delay())
+            };
+
+            match res {
+                Ok(Command::SetPollInterval(poll_interval)) => {
+                    self.poll_interval = poll_interval;
+                }
+                Ok(Command::SetPaused(is_paused)) => {
+                    self.is_paused = is_paused;
+                    // Failures while the connection was down are not worth backing off for
+                    self.n_failures = 0;
+                }
+                Ok(Command::SetAuthOptions(auth_options)) => {
+                    self.repo.set_auth_options(auth_options);
+                    // The failures may have been caused by the previous credentials
+                    self.n_failures = 0;
+                }
+                Ok(Command::Stop) | Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                Err(mpsc::RecvTimeoutError::Timeout) => match self.check() {
+                    Ok(is_changed) => {
+                        self.n_failures = 0;
+
+                        if is_changed {
+                            sender.send(()).unwrap_or_else(|err| {
+                                log::error!("Failed to send message to channel: {:?}", err);
+                            });
+                        }
+                    }
+                    // Only cancelled when stopped
+                    Err(_) if self.repo.monitor().is_cancelled() => break,
+                    Err(err) => {
+                        self.n_failures = self.n_failures.saturating_add(1);
+                        log::error!(
+                            "Failed to check for remote changes; retrying in {:?}: {:?}",
+                            self.delay(),
+                            err
+                        );
+                    }
+                },
+            }
+        }
+    }
+
+    /// Fetch from the remote and check whether it has changes that are not in HEAD
+    fn check(&self) -> anyhow::Result<bool> {
+        // The upstream is read every time, as it changes when attaching a remote
+        self.repo.is_upstream_changed()
+    }
+
+    fn delay(&self) -> Duration {
+        backoff_delay(self.poll_interval, self.n_failures)
+    }
+}
+
+/// Double the delay for every consecutive failure, up to `MAX_BACKOFF_DELAY`. It never goes below
+/// `poll_interval` though.
+fn backoff_delay(poll_interval: Duration, n_failures: u32) -> Duration {
+    let factor = 2_u32.saturating_pow(n_failures);
+    poll_interval
+        .saturating_mul(factor)
+        .min(MAX_BACKOFF_DELAY)
+        .max(poll_interval)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn backoff_delay_without_failures() {
+        let poll_interval = Duration::from_secs(30);
+        assert_eq!(backoff_delay(poll_interval, 0), poll_interval);
+    }
+
+    #[test]
+    fn backoff_delay_doubles() {
+        let poll_interval = Duration::from_secs(30);
+        assert_eq!(backoff_delay(poll_interval, 1), Duration::from_secs(60));
+        assert_eq!(backoff_delay(poll_interval, 3), Duration::from_secs(240));
+    }
+
+    #[test]
+    fn backoff_delay_is_capped() {
+        let poll_interval = Duration::from_secs(30);
+        assert_eq!(backoff_delay(poll_interval, 10), MAX_BACKOFF_DELAY);
+        assert_eq!(backoff_delay(poll_interval, u32::MAX), MAX_BACKOFF_DELAY);
+    }
+
+    #[test]
+    fn backoff_delay_never_below_poll_interval() {
+        let poll_interval = Duration::from_secs(60 * 60);
+        assert_eq!(backoff_delay(poll_interval, 5), poll_interval);
+    }
+}
@@ -0,0 +1,169 @@
+use gtk::glib;
+
+use super::{auth::AuthError, signing::SigningError, sync_progress::Cancelled};
+
+/// Why a sync failed
+#[derive(Debug, Clone, PartialEq, thiserror::Error, glib::Boxed)]
+#[boxed_type(name = "NwtySyncError")]
+pub enum SyncError {
+    #[error(transparent)]
+    Auth(#[from] AuthError),
+    #[error("Failed to reach the remote: {0}")]
+    Network(String),
+    #[error("The remote has changes that conflict with the local ones: {0}")]
+    Conflict(String),
+    #[error("The notes repository is damaged: {0}")]
+    Corrupt(String),
+    #[error("The local and remote notes both have changes, and only fast-forwarding is allowed")]
+    Diverged,
+    #[error("Failed to sign the commit: {0}")]
+    Signing(#[from] SigningError),
+    #[error("Sync was cancelled")]
+    Cancelled,
+    #[error("{0}")]
+    Other(String),
+}
+
+impl SyncError {
+    /// Whether syncing again may succeed without the user fixing the repository by hand
+    pub const fn is_retryable(&self) -> bool {
+        match self {
+            Self::Corrupt(_) | Self::Diverged => false,
+            Self::Signing(signing_error) => signing_error.is_retryable(),
+            _ => true,
+        }
+    }
+
+    /// Whether only reaching the remote again can show that the failure is fixed
+    pub const fn is_remote(&self) -> bool {
+        matches!(
+            self,
+            Self::Auth(_) | Self::Network(_) | Self::Conflict(_) | Self::Diverged
+        )
+    }
+
+    fn from_git(err: &git2::Error) -> Option<Self> {
+        use git2::{ErrorClass, ErrorCode};
+
+        let message = err.message().to_string();
+
+        match err.code() {
+            ErrorCode::NotFastForward
+            | ErrorCode::Conflict
+            | ErrorCode::MergeConflict
+            | ErrorCode::Modified => return Some(Self::Conflict(message)),
+            ErrorCode::Certificate => return Some(Self::Network(message)),
+            _ => {}
+        }
+
+        match err.class() {
+            ErrorClass::Net | ErrorClass::Http | ErrorClass::Ssl | ErrorClass::Ssh => {
+                Some(Self::Network(message))
+            }
+            ErrorClass::Odb
+            | ErrorClass::Zlib
+            | ErrorClass::Object
+            | ErrorClass::Index
+            | ErrorClass::Tree
+            | ErrorClass::Repository => Some(Self::Corrupt(message)),
+            _ => None,
+        }
+    }
+}
+
+impl From<anyhow::Error> for SyncError {
+    fn from(err: anyhow::Error) -> Self {
+        if err.is::<Cancelled>() {
+            return Self::Cancelled;
+        }
+
+        let err = match err.downcast::<Self>() {
+            Ok(sync_error) => return sync_error,
+            Err(err) => err,
+        };
+
+        let err = match err.downcast::<AuthError>() {
+            Ok(auth_error) => return Self::Auth(auth_error),
+            Err(err) => err,
+        };
+
+        let err = match err.downcast::<SigningError>() {
+            Ok(signing_error) => return Self::Signing(signing_error),
+            Err(err) => err,
+        };
+
+        err.chain()
+            .find_map(|cause| cause.downcast_ref::<git2::Error>())
+            .and_then(Self::from_git)
+            .unwrap_or_else(|| Self::Other(format!("{:#}", err)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn git_error(code: git2::ErrorCode, class: git2::ErrorClass) -> anyhow::Error {
+        git2::Error::new(code, class, "message").into()
+    }
+
+    #[test]
+    fn from_anyhow() {
+        assert_eq!(
+            SyncError::from(anyhow::Error::new(Cancelled)),
+            SyncError::Cancelled
+        );
+        assert_eq!(
+            SyncError::from(anyhow::Error::new(AuthError::Rejected)),
+            SyncError::Auth(AuthError::Rejected)
+        );
+        assert_eq!(
+            SyncError::from(git_error(
+                git2::ErrorCode::GenericError,
+                git2::ErrorClass::Net
+            )),
+            SyncError::Network("message".into())
+        );
+        assert_eq!(
+            SyncError::from(git_error(
+                git2::ErrorCode::NotFastForward,
+                git2::ErrorClass::Reference
+            )),
+            SyncError::Conflict("message".into())
+        );
+        assert_eq!(
+            SyncError::from(
+                git_error(git2::ErrorCode::GenericError, git2::ErrorClass::Odb)
+                    .context("Failed to pull")
+            ),
+            SyncError::Corrupt("message".into())
+        );
+        assert_eq!(
+            SyncError::from(anyhow::anyhow!("Head is not a direct reference")),
+            SyncError::Other("Head is not a direct reference".into())
+        );
+    }
+
+    #[test]
+    fn retryable() {
+        assert!(SyncError::Network("message".into()).is_retryable());
+        assert!(SyncError::Auth(AuthError::NoSshKey).is_retryable());
+        assert!(!SyncError::Corrupt("message".into()).is_retryable());
+        // The passphrase prompt may have been dismissed
+        assert!(SyncError::Signing(SigningError::Failed {
+            program: "gpg".into(),
+            message: "message".into()
+        })
+        .is_retryable());
+        assert!(!SyncError::Signing(SigningError::NoSshKey).is_retryable());
+        assert!(!SyncError::Signing(SigningError::UnsupportedFormat("x509".into())).is_retryable());
+    }
+
+    #[test]
+    fn remote() {
+        assert!(SyncError::Network("message".into()).is_remote());
+        assert!(SyncError::Diverged.is_remote());
+        assert!(!SyncError::Signing(SigningError::NoSshKey).is_remote());
+        assert!(!SyncError::Corrupt("message".into()).is_remote());
+    }
+}
@@ -0,0 +1,175 @@
+use gtk::glib;
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+
+/// What is currently being transferred with the remote
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPhase {
+    /// Nothing was transferred yet, e.g., while authenticating
+    #[default]
+    Connecting,
+    /// Downloading the objects from the remote
+    Receiving,
+    /// Indexing the downloaded deltas
+    Resolving,
+    /// Compressing the objects to push
+    Packing,
+    /// Uploading the objects to the remote
+    Pushing,
+}
+
+/// Progress of the current fetch or push
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, glib::Boxed)]
+#[boxed_type(name = "NwtySyncProgress")]
+pub struct SyncProgress {
+    pub phase: SyncPhase,
+    pub received_objects: usize,
+    pub total_objects: usize,
+    pub indexed_deltas: usize,
+    pub total_deltas: usize,
+    pub received_bytes: usize,
+    pub packed_objects: usize,
+    pub total_packed_objects: usize,
+    pub pushed_objects: usize,
+    pub total_pushed_objects: usize,
+    pub pushed_bytes: usize,
+}
+
+impl SyncProgress {
+    /// How much of the current phase is done, from 0 to 1. This is `None` when the total is not
+    /// known yet.
+    pub fn fraction(&self) -> Option<f64> {
+        let (current, total) = match self.phase {
+            SyncPhase::Connecting => return None,
+            SyncPhase::Receiving => (self.received_objects, self.total_objects),
+            SyncPhase::Resolving => (self.indexed_deltas, self.total_deltas),
+            SyncPhase::Packing => (self.packed_objects, self.total_packed_objects),
+            SyncPhase::Pushing => (self.pushed_objects, self.total_pushed_objects),
+        };
+
+        if total == 0 {
+            return None;
+        }
+
+        Some((current as f64 / total as f64).clamp(0.0, 1.0))
+    }
+
+    fn update_transfer(&mut self, progress: &git2::Progress<'_>) {
+        self.received_objects = progress.received_objects();
+        self.total_objects = progress.total_objects();
+        self.indexed_deltas = progress.indexed_deltas();
+        self.total_deltas = progress.total_deltas();
+        self.received_bytes = progress.received_bytes();
+
+        self.phase = if self.total_objects > 0 && self.received_objects == self.total_objects {
+            SyncPhase::Resolving
+        } else if self.total_objects > 0 {
+            SyncPhase::Receiving
+        } else {
+            SyncPhase::Connecting
+        };
+    }
+
+    fn update_pack(&mut self, current: usize, total: usize) {
+        self.phase = SyncPhase::Packing;
+        self.packed_objects = current;
+        self.total_packed_objects = total;
+    }
+
+    fn update_push(&mut self, current: usize, total: usize, bytes: usize) {
+        self.phase = SyncPhase::Pushing;
+        self.pushed_objects = current;
+        self.total_pushed_objects = total;
+        self.pushed_bytes = bytes;
+    }
+}
+
+/// Returned when a fetch or push was stopped by [`TransferMonitor::cancel`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("Sync was cancelled")]
+pub struct Cancelled;
+
+/// Shared between the thread doing the remote operations and the main thread, which reads the
+/// progress and can ask to stop
+#[derive(Debug, Default)]
+pub struct TransferMonitor {
+    progress: Mutex<SyncProgress>,
+    is_cancelled: AtomicBool,
+}
+
+impl TransferMonitor {
+    pub fn progress(&self) -> SyncProgress {
+        *self.progress.lock().unwrap()
+    }
+
+    /// Make the current and next remote operations fail with [`Cancelled`], until
+    /// [`TransferMonitor::reset`] is called
+    pub fn cancel(&self) {
+        self.is_cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.is_cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn reset(&self) {
+        self.is_cancelled.store(false, Ordering::Relaxed);
+        self.reset_progress();
+    }
+
+    pub fn reset_progress(&self) {
+        *self.progress.lock().unwrap() = SyncProgress::default();
+    }
+
+    pub fn update_transfer(&self, progress: &git2::Progress<'_>) {
+        self.progress.lock().unwrap().update_transfer(progress);
+    }
+
+    pub fn update_pack(&self, stage: git2::PackBuilderStage, current: usize, total: usize) {
+        // Only deltafication reports a meaningful total
+        if stage == git2::PackBuilderStage::Deltafication {
+            self.progress.lock().unwrap().update_pack(current, total);
+        }
+    }
+
+    pub fn update_push(&self, current: usize, total: usize, bytes: usize) {
+        self.progress
+            .lock()
+            .unwrap()
+            .update_push(current, total, bytes);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fraction() {
+        let mut progress = SyncProgress::default();
+        assert_eq!(progress.fraction(), None);
+
+        progress.update_pack(0, 0);
+        assert_eq!(progress.fraction(), None);
+
+        progress.update_push(1, 4, 100);
+        assert_eq!(progress.phase, SyncPhase::Pushing);
+        assert_eq!(progress.fraction(), Some(0.25));
+    }
+
+    #[test]
+    fn cancel_and_reset() {
+        let monitor = TransferMonitor::default();
+        monitor.update_push(2, 2, 10);
+
+        monitor.cancel();
+        assert!(monitor.is_cancelled());
+
+        monitor.reset();
+        assert!(!monitor.is_cancelled());
+        assert_eq!(monitor.progress(), SyncProgress::default());
+    }
+}
@@ -7,6 +7,15 @@ pub enum SyncState {
     Pulling,
     Pushing,
     Idle,
+    /// The last sync failed. The reason is in the `sync-error` property of the repository.
+    Error,
+}
+
+impl SyncState {
+    /// Whether a sync is running
+    pub const fn is_syncing(self) -> bool {
+        matches!(self, Self::Syncing | Self::Pulling | Self::Pushing)
+    }
 }
 
 impl Default for SyncState {
@@ -0,0 +1,60 @@
+use std::fmt;
+
+/// Used when no remote name is chosen, and for repositories set up before upstreams were tracked
+pub const DEFAULT_REMOTE_NAME: &str = "origin";
+
+/// Branch on the remote that the checked out branch is synced with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upstream {
+    pub remote_name: String,
+    /// Name of the branch on the remote, without `refs/heads/`
+    pub branch_name: String,
+}
+
+impl Upstream {
+    pub fn new(remote_name: &str, branch_name: &str) -> Self {
+        Self {
+            remote_name: remote_name.to_string(),
+            branch_name: branch_name.to_string(),
+        }
+    }
+
+    /// Full name of the branch on the remote, e.g., `refs/heads/notes`
+    pub fn merge_ref(&self) -> String {
+        format!("refs/heads/{}", self.branch_name)
+    }
+
+    /// Full name of the local copy of the remote branch, e.g., `refs/remotes/origin/notes`
+    pub fn remote_ref(&self) -> String {
+        format!("refs/remotes/{}/{}", self.remote_name, self.branch_name)
+    }
+
+    /// Fetches only the remote branch, so other branches of larger repositories are left alone
+    pub fn fetch_refspec(&self) -> String {
+        format!("+{}:{}", self.merge_ref(), self.remote_ref())
+    }
+}
+
+impl fmt::Display for Upstream {
+    /// Short name of the remote branch, e.g., `origin/notes`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.remote_name, self.branch_name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn refs() {
+        let upstream = Upstream::new("origin", "notes");
+        assert_eq!(upstream.to_string(), "origin/notes");
+        assert_eq!(upstream.merge_ref(), "refs/heads/notes");
+        assert_eq!(upstream.remote_ref(), "refs/remotes/origin/notes");
+        assert_eq!(
+            upstream.fetch_refspec(),
+            "+refs/heads/notes:refs/remotes/origin/notes"
+        );
+    }
+}
@@ -20,6 +20,7 @@ mod application;
 mod config;
 mod core;
 mod model;
+mod preferences_window;
 mod session;
 mod setup;
 mod utils;
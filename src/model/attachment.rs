@@ -8,6 +8,7 @@ use crate::core::{DateTime, FileType};
 
 mod imp {
     use super::*;
+    use glib::subclass::Signal;
     use once_cell::sync::Lazy;
 
     #[derive(Debug, Serialize, Deserialize)]
@@ -43,6 +44,13 @@ mod imp {
     }
 
     impl ObjectImpl for Attachment {
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: Lazy<Vec<Signal>> = Lazy::new(|| {
+                vec![Signal::builder("file-changed", &[], <()>::static_type().into()).build()]
+            });
+            SIGNALS.as_ref()
+        }
+
         fn properties() -> &'static [glib::ParamSpec] {
             static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
                 vec![
@@ -150,6 +158,22 @@ impl Attachment {
         self.connect_notify_local(Some("title"), move |obj, _| f(obj))
     }
 
+    /// Tell that the content of the file was replaced, e.g., by a sync
+    pub fn emit_file_changed(&self) {
+        self.emit_by_name::<()>("file-changed", &[]);
+    }
+
+    pub fn connect_file_changed<F>(&self, f: F) -> glib::SignalHandlerId
+    where
+        F: Fn(&Self) + 'static,
+    {
+        self.connect_local("file-changed", true, move |values| {
+            let obj = values[0].get::<Self>().unwrap();
+            f(&obj);
+            None
+        })
+    }
+
     pub async fn delete(&self) {
         let file = self.file();
 
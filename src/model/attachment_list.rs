@@ -7,7 +7,7 @@ use gtk::{
 use indexmap::IndexSet;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use std::cell::RefCell;
+use std::{cell::RefCell, path::Path};
 
 use super::Attachment;
 
@@ -89,6 +89,16 @@ impl AttachmentList {
         self.imp().list.borrow().is_empty()
     }
 
+    /// The attachment stored at `path`, if any
+    pub fn get_by_path(&self, path: &Path) -> Option<Attachment> {
+        self.imp()
+            .list
+            .borrow()
+            .iter()
+            .find(|attachment| attachment.file().path().as_deref() == Some(path))
+            .cloned()
+    }
+
     fn get_index_of(&self, attachment: &Attachment) -> Option<usize> {
         self.imp().list.borrow().get_index_of(attachment)
     }
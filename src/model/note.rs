@@ -7,10 +7,13 @@ use gtk::{
 };
 use once_cell::unsync::OnceCell;
 
-use std::{cell::Cell, path::Path};
+use std::{
+    cell::{Cell, RefCell},
+    path::Path,
+};
 
 use super::{NoteId, NoteMetadata};
-use crate::utils;
+use crate::{core::NoteConflict, utils};
 
 mod imp {
     use super::*;
@@ -24,6 +27,9 @@ mod imp {
         pub buffer: OnceCell<gtk_source::Buffer>,
         pub is_saved: Cell<bool>,
         pub id: OnceCell<NoteId>,
+        pub conflict: RefCell<Option<NoteConflict>>,
+        pub last_edit_device: RefCell<Option<String>>,
+        pub is_local_only: Cell<bool>,
     }
 
     #[glib::object_subclass]
@@ -71,6 +77,27 @@ mod imp {
                         false,
                         glib::ParamFlags::READABLE,
                     ),
+                    glib::ParamSpecBoolean::new(
+                        "is-conflicted",
+                        "Is Conflicted",
+                        "Whether the note has merge conflicts that are not resolved",
+                        false,
+                        glib::ParamFlags::READABLE,
+                    ),
+                    glib::ParamSpecString::new(
+                        "last-edit-device",
+                        "Last Edit Device",
+                        "Name of the device where the note was last edited",
+                        None,
+                        glib::ParamFlags::READABLE,
+                    ),
+                    glib::ParamSpecBoolean::new(
+                        "is-local-only",
+                        "Is Local Only",
+                        "Whether the note is kept out of sync on this device",
+                        false,
+                        glib::ParamFlags::READABLE,
+                    ),
                 ]
             });
             PROPERTIES.as_ref()
@@ -106,6 +133,9 @@ mod imp {
                 "metadata" => obj.metadata().to_value(),
                 "buffer" => obj.metadata().to_value(),
                 "is-saved" => obj.is_saved().to_value(),
+                "is-conflicted" => obj.is_conflicted().to_value(),
+                "last-edit-device" => obj.last_edit_device().to_value(),
+                "is-local-only" => obj.is_local_only().to_value(),
                 _ => unimplemented!(),
             }
         }
@@ -160,16 +190,7 @@ impl Note {
             return Ok(());
         }
 
-        // FIXME replace with non hacky implementation
-        let mut bytes = serde_yaml::to_string(&self.metadata())?.into_bytes();
-
-        let delimiter = "---\n";
-        bytes.append(&mut delimiter.into());
-
-        let buffer = self.buffer();
-        let (start_iter, end_iter) = buffer.bounds();
-        let buffer_text = buffer.text(&start_iter, &end_iter, true).to_string();
-        bytes.append(&mut buffer_text.into_bytes());
+        let bytes = self.text()?.into_bytes();
 
         self.file()
             .replace_contents_future(bytes, None, false, gio::FileCreateFlags::NONE)
@@ -183,6 +204,20 @@ impl Note {
         Ok(())
     }
 
+    /// The metadata and content as they are written on save, including unsaved changes
+    pub fn text(&self) -> anyhow::Result<String> {
+        // FIXME replace with non hacky implementation
+        let mut text = serde_yaml::to_string(&self.metadata())?;
+
+        text.push_str("---\n");
+
+        let buffer = self.buffer();
+        let (start_iter, end_iter) = buffer.bounds();
+        text.push_str(&buffer.text(&start_iter, &end_iter, true));
+
+        Ok(text)
+    }
+
     pub fn metadata(&self) -> &NoteMetadata {
         self.imp().metadata.get().unwrap()
     }
@@ -208,6 +243,60 @@ impl Note {
         self.connect_notify_local(Some("is-saved"), move |obj, _| f(obj))
     }
 
+    /// The versions of this note that could not be merged cleanly
+    pub fn conflict(&self) -> Option<NoteConflict> {
+        self.imp().conflict.borrow().clone()
+    }
+
+    pub fn set_conflict(&self, conflict: Option<NoteConflict>) {
+        if conflict == self.conflict() {
+            return;
+        }
+
+        self.imp().conflict.replace(conflict);
+        self.notify("is-conflicted");
+    }
+
+    pub fn is_conflicted(&self) -> bool {
+        self.imp().conflict.borrow().is_some()
+    }
+
+    pub fn connect_is_conflicted_notify<F>(&self, f: F) -> glib::SignalHandlerId
+    where
+        F: Fn(&Self) + 'static,
+    {
+        self.connect_notify_local(Some("is-conflicted"), move |obj, _| f(obj))
+    }
+
+    /// Name of the device where the last committed change of this note was made
+    pub fn last_edit_device(&self) -> Option<String> {
+        self.imp().last_edit_device.borrow().clone()
+    }
+
+    pub fn set_last_edit_device(&self, last_edit_device: Option<String>) {
+        if last_edit_device == self.last_edit_device() {
+            return;
+        }
+
+        self.imp().last_edit_device.replace(last_edit_device);
+        self.notify("last-edit-device");
+    }
+
+    /// Whether the note is kept out of sync on this device. It is not stored in the note, as it
+    /// would be synced with it.
+    pub fn is_local_only(&self) -> bool {
+        self.imp().is_local_only.get()
+    }
+
+    pub fn set_is_local_only(&self, is_local_only: bool) {
+        if is_local_only == self.is_local_only() {
+            return;
+        }
+
+        self.imp().is_local_only.set(is_local_only);
+        self.notify("is-local-only");
+    }
+
     pub fn connect_metadata_changed<F>(&self, f: F) -> glib::SignalHandlerId
     where
         F: Fn(&Self) + 'static,
@@ -229,12 +318,33 @@ impl Note {
         Ok(())
     }
 
+    /// Replace the file with `text`, e.g., from an older version, then load it. The note is
+    /// marked unsaved, so it is committed on the next sync.
+    pub async fn restore(&self, text: &str) -> anyhow::Result<()> {
+        self.file()
+            .replace_contents_future(
+                text.as_bytes().to_vec(),
+                None,
+                false,
+                gio::FileCreateFlags::NONE,
+            )
+            .await
+            .map_err(|err| err.1)?;
+
+        self.update().await?;
+        self.set_is_saved(false);
+
+        log::info!("Restored `{}`", self);
+
+        Ok(())
+    }
+
     fn set_is_saved(&self, is_saved: bool) {
         self.imp().is_saved.set(is_saved);
         self.notify("is-saved");
     }
 
-    fn file(&self) -> &gio::File {
+    pub fn file(&self) -> &gio::File {
         self.imp().file.get().unwrap()
     }
 
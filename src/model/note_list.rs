@@ -16,6 +16,8 @@ use crate::core::FileType;
 
 mod imp {
     use super::*;
+    use glib::subclass::Signal;
+    use once_cell::sync::Lazy;
 
     #[derive(Debug, Default)]
     pub struct NoteList {
@@ -30,7 +32,19 @@ mod imp {
         type Interfaces = (gio::ListModel,);
     }
 
-    impl ObjectImpl for NoteList {}
+    impl ObjectImpl for NoteList {
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: Lazy<Vec<Signal>> = Lazy::new(|| {
+                vec![Signal::builder(
+                    "note-modified",
+                    &[Note::static_type().into()],
+                    <()>::static_type().into(),
+                )
+                .build()]
+            });
+            SIGNALS.as_ref()
+        }
+    }
 
     impl ListModelImpl for NoteList {
         fn item_type(&self, _list_model: &Self::Type) -> glib::Type {
@@ -177,6 +191,19 @@ impl NoteList {
         self.imp().list.borrow().get_index_of(note_id)
     }
 
+    /// Emitted on every change on a note that is not saved yet, e.g., on each keystroke
+    pub fn connect_note_modified<F>(&self, f: F) -> glib::SignalHandlerId
+    where
+        F: Fn(&Self, &Note) + 'static,
+    {
+        self.connect_local("note-modified", true, move |values| {
+            let obj = values[0].get::<Self>().unwrap();
+            let note = values[1].get::<Note>().unwrap();
+            f(&obj, &note);
+            None
+        })
+    }
+
     /// Clear and get all unsaved notes
     pub fn take_unsaved_notes(&self) -> HashSet<Note> {
         self.imp().unsaved_notes.take()
@@ -209,6 +236,13 @@ impl NoteList {
             }
         }));
 
+        // So filters depending on it get updated
+        note.connect_is_conflicted_notify(clone!(@weak self as obj => move |note| {
+            if let Some(position) = obj.get_index_of(note.id()) {
+                obj.items_changed(position as u32, 1, 1);
+            }
+        }));
+
         note.connect_is_saved_notify(clone!(@weak self as obj => move |note| {
             let mut unsaved_notes = obj.imp().unsaved_notes.borrow_mut();
 
@@ -218,6 +252,9 @@ impl NoteList {
             } else {
                 let res = unsaved_notes.insert(note.clone());
                 log::info!("Inserted unsaved note with ret `{}`", res);
+                drop(unsaved_notes);
+
+                obj.emit_by_name::<()>("note-modified", &[note]);
             }
         }));
 
@@ -132,6 +132,61 @@ impl TagList {
         !self.contains_with_name(name) && !name.is_empty()
     }
 
+    pub fn names(&self) -> Vec<String> {
+        self.imp().list.borrow().keys().cloned().collect()
+    }
+
+    /// Apply the changes from the tag names `base` to `remote`, e.g., made on another device,
+    /// while keeping the tags added here since `base`. When a single tag was removed and another
+    /// added, it is taken as renamed, so everything holding the tag keeps it.
+    ///
+    /// Returns the removed tags.
+    pub fn merge_remote(&self, base: &[String], remote: &[String]) -> Vec<Tag> {
+        let removed_names = base
+            .iter()
+            .filter(|name| !remote.contains(name) && self.contains_with_name(name))
+            .collect::<Vec<_>>();
+        let added_names = remote
+            .iter()
+            .filter(|name| !base.contains(name) && !self.contains_with_name(name))
+            .collect::<Vec<_>>();
+
+        if let ([removed_name], [added_name]) = (removed_names.as_slice(), added_names.as_slice()) {
+            let tag = self.get_with_name(removed_name).unwrap();
+
+            if let Err(err) = self.rename_tag(&tag, added_name) {
+                log::warn!(
+                    "Failed to rename tag `{}` to `{}`: {:?}",
+                    removed_name,
+                    added_name,
+                    err
+                );
+            }
+
+            return Vec::new();
+        }
+
+        let mut removed_tags = Vec::new();
+
+        for name in removed_names {
+            let tag = self.get_with_name(name).unwrap();
+
+            if let Err(err) = self.remove(&tag) {
+                log::warn!("Failed to remove tag `{}`: {:?}", name, err);
+            } else {
+                removed_tags.push(tag);
+            }
+        }
+
+        for name in added_names {
+            if let Err(err) = self.append(Tag::new(name)) {
+                log::warn!("Failed to append tag `{}`: {:?}", name, err);
+            }
+        }
+
+        removed_tags
+    }
+
     fn get_index_of(&self, tag: &Tag) -> Option<usize> {
         self.imp().list.borrow().get_index_of(&tag.name())
     }
@@ -566,4 +621,62 @@ mod test {
         assert!(tag_list.contains_with_name("C"));
         assert_eq!(tag_list.n_items(), 3);
     }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn merge_remote() {
+        let tag_list = TagList::new();
+        let kept_tag = Tag::new("Kept");
+        tag_list.append(kept_tag.clone()).unwrap();
+        tag_list.append(Tag::new("Removed A")).unwrap();
+        tag_list.append(Tag::new("Removed B")).unwrap();
+        tag_list.append(Tag::new("Local")).unwrap();
+
+        let removed_tags = tag_list.merge_remote(
+            &names(&["Kept", "Removed A", "Removed B"]),
+            &names(&["Kept", "Remote A", "Remote B"]),
+        );
+
+        assert_eq!(
+            tag_list.names(),
+            names(&["Kept", "Local", "Remote A", "Remote B"])
+        );
+        assert_eq!(tag_list.get_with_name("Kept"), Some(kept_tag));
+        assert_eq!(
+            removed_tags.iter().map(Tag::name).collect::<Vec<_>>(),
+            names(&["Removed A", "Removed B"])
+        );
+    }
+
+    #[test]
+    fn merge_remote_renamed() {
+        let tag_list = TagList::new();
+        let tag = Tag::new("Old");
+        tag_list.append(tag.clone()).unwrap();
+        tag_list.append(Tag::new("Other")).unwrap();
+
+        let removed_tags =
+            tag_list.merge_remote(&names(&["Old", "Other"]), &names(&["New", "Other"]));
+
+        assert!(removed_tags.is_empty());
+        assert_eq!(tag.name(), "New");
+        assert_eq!(tag_list.get_with_name("New"), Some(tag));
+        assert_eq!(tag_list.names(), names(&["New", "Other"]));
+    }
+
+    #[test]
+    fn merge_remote_added_on_both() {
+        let tag_list = TagList::new();
+        let tag = Tag::new("Same");
+        tag_list.append(tag.clone()).unwrap();
+
+        let removed_tags = tag_list.merge_remote(&[], &names(&["Same"]));
+
+        assert!(removed_tags.is_empty());
+        assert_eq!(tag_list.n_items(), 1);
+        assert_eq!(tag_list.get_with_name("Same"), Some(tag));
+    }
 }
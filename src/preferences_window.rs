@@ -0,0 +1,245 @@
+use adw::subclass::prelude::*;
+use gtk::{
+    gio,
+    glib::{self, clone},
+    prelude::*,
+    subclass::prelude::*,
+};
+
+use crate::{core::PullStrategy, Application};
+
+/// In the same order as the rows of the pull strategy combo row
+const PULL_STRATEGIES: [PullStrategy; 3] = [
+    PullStrategy::Merge,
+    PullStrategy::Rebase,
+    PullStrategy::FastForwardOnly,
+];
+
+/// In the same order as the rows of the commit signing combo row
+const COMMIT_SIGNING_SETTINGS: [&str; 3] = ["config", "always", "never"];
+
+/// In the same order as the rows of the signing format combo row
+const SIGNING_FORMAT_SETTINGS: [&str; 3] = ["", "openpgp", "ssh"];
+
+mod imp {
+    use super::*;
+    use gtk::CompositeTemplate;
+
+    #[derive(Debug, Default, CompositeTemplate)]
+    #[template(resource = "/io/github/seadve/Noteworthy/ui/preferences-window.ui")]
+    pub struct PreferencesWindow {
+        #[template_child]
+        pub author_name_entry: TemplateChild<gtk::Entry>,
+        #[template_child]
+        pub author_email_entry: TemplateChild<gtk::Entry>,
+        #[template_child]
+        pub device_name_entry: TemplateChild<gtk::Entry>,
+        #[template_child]
+        pub commit_signing_combo_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        pub signing_format_combo_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        pub signing_key_entry: TemplateChild<gtk::Entry>,
+        #[template_child]
+        pub is_auto_sync_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub sync_interval_spin_button: TemplateChild<gtk::SpinButton>,
+        #[template_child]
+        pub remote_name_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub branch_name_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub ssh_key_path_entry: TemplateChild<gtk::Entry>,
+        #[template_child]
+        pub remote_poll_interval_spin_button: TemplateChild<gtk::SpinButton>,
+        #[template_child]
+        pub pull_strategy_combo_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        pub ignored_patterns_entry: TemplateChild<gtk::Entry>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for PreferencesWindow {
+        const NAME: &'static str = "NwtyPreferencesWindow";
+        type Type = super::PreferencesWindow;
+        type ParentType = adw::PreferencesWindow;
+
+        fn class_init(klass: &mut Self::Class) {
+            Self::bind_template(klass);
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for PreferencesWindow {
+        fn constructed(&self, obj: &Self::Type) {
+            self.parent_constructed(obj);
+
+            obj.setup_settings();
+        }
+    }
+
+    impl WidgetImpl for PreferencesWindow {}
+
+    impl WindowImpl for PreferencesWindow {
+        fn close_request(&self, obj: &Self::Type) -> gtk::Inhibit {
+            obj.save_ignored_patterns();
+
+            self.parent_close_request(obj)
+        }
+    }
+
+    impl AdwWindowImpl for PreferencesWindow {}
+    impl PreferencesWindowImpl for PreferencesWindow {}
+}
+
+glib::wrapper! {
+    pub struct PreferencesWindow(ObjectSubclass<imp::PreferencesWindow>)
+        @extends gtk::Widget, gtk::Window, adw::Window, adw::PreferencesWindow;
+}
+
+impl PreferencesWindow {
+    pub fn new(transient_for: &impl IsA<gtk::Window>) -> Self {
+        glib::Object::new(&[("transient-for", transient_for)])
+            .expect("Failed to create PreferencesWindow.")
+    }
+
+    fn setup_settings(&self) {
+        let imp = self.imp();
+        let settings = Application::default().settings();
+
+        settings
+            .bind("author-name", &imp.author_name_entry.get(), "text")
+            .build();
+        settings
+            .bind("author-email", &imp.author_email_entry.get(), "text")
+            .build();
+        settings
+            .bind("device-name", &imp.device_name_entry.get(), "text")
+            .build();
+        settings
+            .bind("signing-key", &imp.signing_key_entry.get(), "text")
+            .build();
+        Self::bind_combo_row(
+            &settings,
+            "commit-signing",
+            &imp.commit_signing_combo_row,
+            &COMMIT_SIGNING_SETTINGS,
+        );
+        Self::bind_combo_row(
+            &settings,
+            "signing-format",
+            &imp.signing_format_combo_row,
+            &SIGNING_FORMAT_SETTINGS,
+        );
+        settings
+            .bind("is-auto-sync", &imp.is_auto_sync_switch.get(), "active")
+            .build();
+        settings
+            .bind(
+                "sync-interval",
+                &imp.sync_interval_spin_button.get(),
+                "value",
+            )
+            .build();
+        // These can't be changed without setting up sync again
+        settings
+            .bind("remote-name", &imp.remote_name_label.get(), "label")
+            .flags(gio::SettingsBindFlags::GET)
+            .build();
+        settings
+            .bind("branch-name", &imp.branch_name_label.get(), "label")
+            .flags(gio::SettingsBindFlags::GET)
+            .build();
+        settings
+            .bind("ssh-key-path", &imp.ssh_key_path_entry.get(), "text")
+            .build();
+        settings
+            .bind(
+                "remote-poll-interval",
+                &imp.remote_poll_interval_spin_button.get(),
+                "value",
+            )
+            .build();
+
+        let pull_strategy =
+            PullStrategy::from_setting(&settings.string("pull-strategy")).unwrap_or_default();
+        let position = PULL_STRATEGIES
+            .iter()
+            .position(|strategy| *strategy == pull_strategy)
+            .unwrap();
+        imp.pull_strategy_combo_row.set_selected(position as u32);
+        imp.pull_strategy_combo_row
+            .connect_selected_notify(move |combo_row| {
+                let pull_strategy = PULL_STRATEGIES[combo_row.selected() as usize];
+
+                if let Err(err) = settings.set_string("pull-strategy", pull_strategy.as_setting()) {
+                    log::warn!("Failed to save pull strategy: {:?}", err);
+                }
+            });
+
+        // Saved only when done editing, as a partly typed pattern may ignore too much
+        let ignored_patterns = settings.strv("ignored-patterns");
+        imp.ignored_patterns_entry.set_text(
+            &ignored_patterns
+                .iter()
+                .map(|pattern| pattern.as_str())
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+        imp.ignored_patterns_entry
+            .connect_activate(clone!(@weak self as obj => move |_| {
+                obj.save_ignored_patterns();
+            }));
+
+        // Show what is used when the setting is left empty
+        imp.device_name_entry
+            .set_placeholder_text(Some(&glib::host_name()));
+    }
+
+    /// Select the row of `combo_row` for the value of the string setting `key`, and save the
+    /// value of the selected row. The rows are in the same order as `values`.
+    fn bind_combo_row(
+        settings: &gio::Settings,
+        key: &'static str,
+        combo_row: &adw::ComboRow,
+        values: &'static [&'static str],
+    ) {
+        let value = settings.string(key);
+        let position = values
+            .iter()
+            .position(|v| *v == value.as_str())
+            .unwrap_or_default();
+        combo_row.set_selected(position as u32);
+
+        combo_row.connect_selected_notify(clone!(@strong settings => move |combo_row| {
+            let value = values[combo_row.selected() as usize];
+
+            if let Err(err) = settings.set_string(key, value) {
+                log::warn!("Failed to save `{}`: {:?}", key, err);
+            }
+        }));
+    }
+
+    fn save_ignored_patterns(&self) {
+        let settings = Application::default().settings();
+
+        let text = self.imp().ignored_patterns_entry.text();
+        let patterns = text.split_whitespace().collect::<Vec<_>>();
+
+        let saved_patterns = settings.strv("ignored-patterns");
+        if saved_patterns
+            .iter()
+            .map(|pattern| pattern.as_str())
+            .eq(patterns.iter().copied())
+        {
+            return;
+        }
+
+        if let Err(err) = settings.set_strv("ignored-patterns", &patterns) {
+            log::warn!("Failed to save ignored patterns: {:?}", err);
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use gtk::{
+    glib::{self, clone},
+    prelude::*,
+    subclass::prelude::*,
+};
+use once_cell::unsync::OnceCell;
+
+use std::{
+    cell::{Cell, RefCell},
+    time::Duration,
+};
+
+use super::NoteManager;
+use crate::{spawn, Application};
+
+/// How long to wait after the last edit before committing
+const COMMIT_DELAY: Duration = Duration::from_secs(5);
+
+mod imp {
+    use super::*;
+    use once_cell::sync::Lazy;
+
+    #[derive(Debug, Default)]
+    pub struct AutoSync {
+        pub note_manager: OnceCell<NoteManager>,
+        pub is_paused: Cell<bool>,
+        pub commit_source_id: RefCell<Option<glib::SourceId>>,
+        pub sync_source_id: RefCell<Option<glib::SourceId>>,
+        pub settings_handler_id: RefCell<Option<glib::SignalHandlerId>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for AutoSync {
+        const NAME: &'static str = "NwtyAutoSync";
+        type Type = super::AutoSync;
+    }
+
+    impl ObjectImpl for AutoSync {
+        fn properties() -> &'static [glib::ParamSpec] {
+            static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
+                vec![
+                    glib::ParamSpecObject::new(
+                        "note-manager",
+                        "Note Manager",
+                        "Note manager whose notes are synced",
+                        NoteManager::static_type(),
+                        glib::ParamFlags::READWRITE | glib::ParamFlags::CONSTRUCT_ONLY,
+                    ),
+                    glib::ParamSpecBoolean::new(
+                        "is-paused",
+                        "Is Paused",
+                        "Whether notes are only synced when asked to",
+                        false,
+                        glib::ParamFlags::READWRITE | glib::ParamFlags::EXPLICIT_NOTIFY,
+                    ),
+                ]
+            });
+            PROPERTIES.as_ref()
+        }
+
+        fn set_property(
+            &self,
+            obj: &Self::Type,
+            _id: usize,
+            value: &glib::Value,
+            pspec: &glib::ParamSpec,
+        ) {
+            match pspec.name() {
+                "note-manager" => {
+                    let note_manager = value.get().unwrap();
+                    self.note_manager.set(note_manager).unwrap();
+                }
+                "is-paused" => {
+                    let is_paused = value.get().unwrap();
+                    obj.set_is_paused(is_paused);
+                }
+                _ => unimplemented!(),
+            }
+        }
+
+        fn property(&self, obj: &Self::Type, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+            match pspec.name() {
+                "note-manager" => obj.note_manager().to_value(),
+                "is-paused" => obj.is_paused().to_value(),
+                _ => unimplemented!(),
+            }
+        }
+
+        fn constructed(&self, obj: &Self::Type) {
+            self.parent_constructed(obj);
+
+            obj.setup_signals();
+            obj.restart_sync_timer();
+        }
+
+        fn dispose(&self, _obj: &Self::Type) {
+            if let Some(source_id) = self.commit_source_id.take() {
+                source_id.remove();
+            }
+
+            if let Some(source_id) = self.sync_source_id.take() {
+                source_id.remove();
+            }
+
+            if let Some(handler_id) = self.settings_handler_id.take() {
+                Application::default().settings().disconnect(handler_id);
+            }
+        }
+    }
+}
+
+glib::wrapper! {
+    pub struct AutoSync(ObjectSubclass<imp::AutoSync>);
+}
+
+impl AutoSync {
+    /// Commit the notes shortly after they are edited, and sync them with the remote every
+    /// `sync-interval` seconds and when the remote changes. The notes of `note_manager` must be
+    /// loaded already.
+    pub fn new(note_manager: &NoteManager) -> Self {
+        glib::Object::new(&[("note-manager", note_manager)]).expect("Failed to create AutoSync.")
+    }
+
+    pub fn note_manager(&self) -> &NoteManager {
+        self.imp().note_manager.get().unwrap()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.imp().is_paused.get()
+    }
+
+    pub fn set_is_paused(&self, is_paused: bool) {
+        if is_paused == self.is_paused() {
+            return;
+        }
+
+        self.imp().is_paused.set(is_paused);
+        self.notify("is-paused");
+
+        if is_paused {
+            if let Some(source_id) = self.imp().commit_source_id.take() {
+                source_id.remove();
+            }
+        } else {
+            // Catch up with what was missed while paused
+            self.sync();
+        }
+    }
+
+    fn schedule_commit(&self) {
+        if self.is_paused() {
+            return;
+        }
+
+        let imp = self.imp();
+
+        if let Some(source_id) = imp.commit_source_id.take() {
+            source_id.remove();
+        }
+
+        let source_id = glib::timeout_add_local_once(
+            COMMIT_DELAY,
+            clone!(@weak self as obj => move || {
+                obj.imp().commit_source_id.replace(None);
+                obj.commit();
+            }),
+        );
+        imp.commit_source_id.replace(Some(source_id));
+    }
+
+    fn commit(&self) {
+        let note_manager = self.note_manager();
+
+        // Queued after the running sync, which may have saved the notes before they were edited
+        spawn!(clone!(@weak note_manager => async move {
+            if let Err(err) = note_manager.commit_changes().await {
+                log::error!("Failed to commit changes: {:?}", err);
+            }
+        }));
+    }
+
+    fn sync(&self) {
+        let note_manager = self.note_manager();
+
+        if self.is_paused() || note_manager.is_offline_mode() {
+            return;
+        }
+
+        spawn!(clone!(@weak note_manager => async move {
+            if let Err(err) = note_manager.sync().await {
+                log::error!("Failed to sync: {:?}", err);
+            }
+        }));
+    }
+
+    fn restart_sync_timer(&self) {
+        let imp = self.imp();
+
+        if let Some(source_id) = imp.sync_source_id.take() {
+            source_id.remove();
+        }
+
+        let interval = Application::default().settings().uint("sync-interval");
+        let source_id = glib::timeout_add_seconds_local(
+            interval,
+            clone!(@weak self as obj => @default-return Continue(false), move || {
+                log::info!("Auto sync: Syncing...");
+                obj.sync();
+                Continue(true)
+            }),
+        );
+        imp.sync_source_id.replace(Some(source_id));
+    }
+
+    fn setup_signals(&self) {
+        self.note_manager().note_list().connect_note_modified(
+            clone!(@weak self as obj => move |_, _| {
+                obj.schedule_commit();
+            }),
+        );
+
+        self.note_manager().repository().connect_remote_changed(
+            clone!(@weak self as obj => move |_| {
+                log::info!("Auto sync: New remote changes");
+                obj.sync();
+            }),
+        );
+
+        let handler_id = Application::default().settings().connect_changed(
+            Some("sync-interval"),
+            clone!(@weak self as obj => move |_, _| {
+                obj.restart_sync_timer();
+            }),
+        );
+        self.imp().settings_handler_id.replace(Some(handler_id));
+    }
+}
@@ -0,0 +1,189 @@
+use gtk::{gio, glib, prelude::*};
+
+use std::path::PathBuf;
+
+use crate::{
+    core::{NoteRepository, Upstream},
+    utils,
+};
+
+/// How the notes of a session are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionMode {
+    /// Only stored in the notes directory
+    Offline,
+    /// Also synced with a remote
+    Online,
+}
+
+impl SessionMode {
+    fn from_setting(value: &str) -> Option<Self> {
+        match value {
+            "offline" => Some(Self::Offline),
+            "online" => Some(Self::Online),
+            _ => None,
+        }
+    }
+
+    const fn as_setting(self) -> &'static str {
+        match self {
+            Self::Offline => "offline",
+            Self::Online => "online",
+        }
+    }
+}
+
+/// What was chosen on setup. It is stored in the settings, so the same session is restored on the
+/// next launch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionConfig {
+    pub mode: SessionMode,
+    pub notes_directory: PathBuf,
+    pub remote_url: Option<String>,
+    /// Name of the remote in the repository, `origin` if `None`
+    pub remote_name: Option<String>,
+    /// Branch of the remote the notes are on, its default branch if `None`
+    pub branch_name: Option<String>,
+    /// Id of the Git host of the remote, e.g., `github`
+    pub git_host: Option<String>,
+    pub is_auto_sync: bool,
+}
+
+impl SessionConfig {
+    pub fn offline(notes_directory: PathBuf) -> Self {
+        Self {
+            mode: SessionMode::Offline,
+            notes_directory,
+            remote_url: None,
+            remote_name: None,
+            branch_name: None,
+            git_host: None,
+            is_auto_sync: true,
+        }
+    }
+
+    /// The stored config, or `None` if the app is not set up yet
+    pub fn load(settings: &gio::Settings) -> Option<Self> {
+        let mode = SessionMode::from_setting(&settings.string("session-mode"))?;
+        let non_empty =
+            |key: &str| Some(settings.string(key).to_string()).filter(|s| !s.is_empty());
+
+        Some(Self {
+            mode,
+            notes_directory: non_empty("notes-directory")
+                .map_or_else(utils::default_notes_dir, PathBuf::from),
+            remote_url: non_empty("remote-url"),
+            remote_name: non_empty("remote-name"),
+            branch_name: non_empty("branch-name"),
+            git_host: non_empty("git-host"),
+            is_auto_sync: settings.boolean("is-auto-sync"),
+        })
+    }
+
+    /// Like [`SessionConfig::load`], but detects the config of installs from before it was
+    /// stored, then stores it
+    pub async fn load_or_migrate(settings: &gio::Settings) -> Option<Self> {
+        if let Some(config) = Self::load(settings) {
+            return Some(config);
+        }
+
+        let config = Self::migrate(settings).await?;
+        log::info!("Migrated session config: {:?}", config);
+
+        if let Err(err) = config.save(settings) {
+            log::warn!("Failed to save migrated session config: {:?}", err);
+        }
+
+        Some(config)
+    }
+
+    pub fn save(&self, settings: &gio::Settings) -> Result<(), glib::BoolError> {
+        settings.set_string("session-mode", self.mode.as_setting())?;
+        settings.set_string(
+            "notes-directory",
+            &self.notes_directory.display().to_string(),
+        )?;
+        settings.set_string("remote-url", self.remote_url.as_deref().unwrap_or_default())?;
+        settings.set_string(
+            "remote-name",
+            self.remote_name.as_deref().unwrap_or_default(),
+        )?;
+        settings.set_string(
+            "branch-name",
+            self.branch_name.as_deref().unwrap_or_default(),
+        )?;
+        settings.set_string("git-host", self.git_host.as_deref().unwrap_or_default())?;
+        settings.set_boolean("is-auto-sync", self.is_auto_sync)?;
+
+        Ok(())
+    }
+
+    /// Store that the session is now synced with the remote at `remote_url`, or only stored
+    /// locally if it is `None`
+    pub fn save_remote(
+        settings: &gio::Settings,
+        remote_url: Option<&str>,
+    ) -> Result<(), glib::BoolError> {
+        let mode = if remote_url.is_some() {
+            SessionMode::Online
+        } else {
+            SessionMode::Offline
+        };
+
+        settings.set_string("session-mode", mode.as_setting())?;
+        settings.set_string("remote-url", remote_url.unwrap_or_default())?;
+
+        Ok(())
+    }
+
+    /// Store the remote branch that the notes ended up synced with, so the choice is kept even
+    /// if it was left to the defaults
+    pub fn set_upstream(&mut self, upstream: Upstream) {
+        self.remote_name = Some(upstream.remote_name);
+        self.branch_name = Some(upstream.branch_name);
+    }
+
+    /// Like [`SessionConfig::set_upstream`], but stores it right away
+    pub fn save_upstream(
+        settings: &gio::Settings,
+        upstream: &Upstream,
+    ) -> Result<(), glib::BoolError> {
+        settings.set_string("remote-name", &upstream.remote_name)?;
+        settings.set_string("branch-name", &upstream.branch_name)?;
+
+        Ok(())
+    }
+
+    /// Older installs always stored the notes in the default directory and only remembered that
+    /// they were set up by its existence. Whether they are synced is told by the repository
+    /// having a remote.
+    async fn migrate(settings: &gio::Settings) -> Option<Self> {
+        let notes_directory = utils::default_notes_dir();
+
+        if !notes_directory.exists() {
+            return None;
+        }
+
+        let remote_url = match NoteRepository::open(&gio::File::for_path(&notes_directory)).await {
+            Ok(repository) => repository.remote_url().await,
+            Err(err) => {
+                log::warn!("Failed to open repository to migrate: {:?}", err);
+                None
+            }
+        };
+
+        Some(Self {
+            mode: if remote_url.is_some() {
+                SessionMode::Online
+            } else {
+                SessionMode::Offline
+            },
+            notes_directory,
+            remote_url,
+            remote_name: None,
+            branch_name: None,
+            git_host: None,
+            is_auto_sync: settings.boolean("is-auto-sync"),
+        })
+    }
+}
@@ -1,5 +1,8 @@
 use adw::prelude::*;
-use gtk::{glib, subclass::prelude::*};
+use gtk::{
+    glib::{self, clone},
+    subclass::prelude::*,
+};
 
 use std::cell::RefCell;
 
@@ -19,6 +22,7 @@ mod imp {
         pub content: TemplateChild<adw::Bin>,
 
         pub attachment: RefCell<Option<Attachment>>,
+        pub file_changed_handler_id: RefCell<Option<glib::SignalHandlerId>>,
     }
 
     #[glib::object_subclass]
@@ -113,13 +117,26 @@ impl Row {
             return;
         }
 
+        let imp = self.imp();
+
+        if let Some(handler_id) = imp.file_changed_handler_id.take() {
+            self.attachment().unwrap().disconnect(handler_id);
+        }
+
         if let Some(ref attachment) = attachment {
             self.replace_child(attachment);
+
+            // The inner rows only load the file when created
+            let handler_id =
+                attachment.connect_file_changed(clone!(@weak self as obj => move |attachment| {
+                    obj.replace_child(attachment);
+                }));
+            imp.file_changed_handler_id.replace(Some(handler_id));
         } else {
             self.remove_child();
         }
 
-        self.imp().attachment.replace(attachment);
+        imp.attachment.replace(attachment);
         self.notify("attachment");
     }
 
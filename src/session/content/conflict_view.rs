@@ -0,0 +1,486 @@
+use adw::{prelude::*, subclass::prelude::*};
+use gettextrs::gettext;
+use gtk::{
+    glib::{self, clone},
+    subclass::prelude::*,
+};
+
+use std::cell::RefCell;
+
+use super::super::Session;
+use crate::{
+    core::{find_conflict, render_with_markers, NoteConflict, RawNote, TextChunk, OURS_MARKER},
+    model::Note,
+    spawn,
+};
+
+#[derive(Debug, Clone, Copy)]
+enum Choice {
+    Mine,
+    Theirs,
+    Both,
+}
+
+mod imp {
+    use super::*;
+    use gtk::CompositeTemplate;
+    use once_cell::sync::Lazy;
+
+    #[derive(Debug, Default, CompositeTemplate)]
+    #[template(resource = "/io/github/seadve/Noteworthy/ui/content-conflict-view.ui")]
+    pub struct ConflictView {
+        #[template_child]
+        pub fields_group: TemplateChild<adw::PreferencesGroup>,
+        #[template_child]
+        pub hunks_group: TemplateChild<adw::PreferencesGroup>,
+        #[template_child]
+        pub sides_box: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub ours_view: TemplateChild<gtk_source::View>,
+        #[template_child]
+        pub theirs_view: TemplateChild<gtk_source::View>,
+        #[template_child]
+        pub merged_view: TemplateChild<gtk_source::View>,
+
+        pub note: RefCell<Option<Note>>,
+        pub chunks: RefCell<Vec<TextChunk>>,
+        pub field_rows: RefCell<Vec<adw::ActionRow>>,
+        pub hunk_rows: RefCell<Vec<adw::ActionRow>>,
+        pub buffer_changed_id: RefCell<Option<glib::SignalHandlerId>>,
+        pub is_conflicted_handler_id: RefCell<Option<glib::SignalHandlerId>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for ConflictView {
+        const NAME: &'static str = "NwtyContentConflictView";
+        type Type = super::ConflictView;
+        type ParentType = adw::Bin;
+
+        fn class_init(klass: &mut Self::Class) {
+            Self::bind_template(klass);
+
+            klass.install_action("conflict-view.resolve", None, move |obj, _, _| {
+                obj.resolve();
+            });
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for ConflictView {
+        fn properties() -> &'static [glib::ParamSpec] {
+            static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
+                vec![glib::ParamSpecObject::new(
+                    "note",
+                    "Note",
+                    "Conflicted note in the view",
+                    Note::static_type(),
+                    glib::ParamFlags::READWRITE | glib::ParamFlags::EXPLICIT_NOTIFY,
+                )]
+            });
+            PROPERTIES.as_ref()
+        }
+
+        fn set_property(
+            &self,
+            obj: &Self::Type,
+            _id: usize,
+            value: &glib::Value,
+            pspec: &glib::ParamSpec,
+        ) {
+            match pspec.name() {
+                "note" => {
+                    let note = value.get().unwrap();
+                    obj.set_note(note);
+                }
+                _ => unimplemented!(),
+            }
+        }
+
+        fn property(&self, obj: &Self::Type, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+            match pspec.name() {
+                "note" => obj.note().to_value(),
+                _ => unimplemented!(),
+            }
+        }
+
+        fn constructed(&self, obj: &Self::Type) {
+            self.parent_constructed(obj);
+
+            obj.action_set_enabled("conflict-view.resolve", false);
+        }
+    }
+
+    impl WidgetImpl for ConflictView {}
+    impl BinImpl for ConflictView {}
+}
+
+glib::wrapper! {
+    pub struct ConflictView(ObjectSubclass<imp::ConflictView>)
+        @extends gtk::Widget, adw::Bin;
+}
+
+impl ConflictView {
+    pub fn new() -> Self {
+        glib::Object::new(&[]).expect("Failed to create ConflictView.")
+    }
+
+    pub fn note(&self) -> Option<Note> {
+        self.imp().note.borrow().clone()
+    }
+
+    pub fn set_note(&self, note: Option<Note>) {
+        if self.note() == note {
+            return;
+        }
+
+        let imp = self.imp();
+
+        if let Some(old_note) = self.note() {
+            if let Some(handler_id) = imp.buffer_changed_id.take() {
+                old_note.buffer().disconnect(handler_id);
+            }
+            if let Some(handler_id) = imp.is_conflicted_handler_id.take() {
+                old_note.disconnect(handler_id);
+            }
+        }
+
+        if let Some(ref note) = note {
+            let handler_id = note
+                .buffer()
+                .connect_changed(clone!(@weak self as obj => move |_| {
+                    obj.update_resolve_action();
+                }));
+            imp.buffer_changed_id.replace(Some(handler_id));
+
+            // The conflict may change while the note is shown, e.g., when syncing again
+            let handler_id =
+                note.connect_is_conflicted_notify(clone!(@weak self as obj => move |_| {
+                    obj.update_conflict();
+                }));
+            imp.is_conflicted_handler_id.replace(Some(handler_id));
+        }
+
+        imp.merged_view
+            .set_buffer(note.as_ref().map(|note| note.buffer()));
+
+        imp.note.replace(note);
+        self.notify("note");
+
+        self.update_conflict();
+    }
+
+    fn update_conflict(&self) {
+        let imp = self.imp();
+
+        for row in imp.field_rows.borrow_mut().drain(..) {
+            imp.fields_group.remove(&row);
+        }
+
+        imp.chunks.borrow_mut().clear();
+
+        if let Some(note) = self.note() {
+            if let Some(conflict) = note.conflict() {
+                if let Err(err) = self.load_conflict(&note, &conflict) {
+                    log::error!("Failed to load conflict of `{}`: {:?}", note, err);
+                }
+            }
+        }
+
+        self.update_hunk_rows();
+        self.update_resolve_action();
+    }
+
+    fn load_conflict(&self, note: &Note, conflict: &NoteConflict) -> anyhow::Result<()> {
+        let imp = self.imp();
+
+        let parsed = conflict
+            .merge()
+            .and_then(|outcome| Ok((conflict.ours_note()?, conflict.theirs_note()?, outcome)));
+        let (ours, theirs, outcome) = match parsed {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                // Only the merged text can be shown, as the sides cannot be compared field by field
+                log::warn!(
+                    "Failed to parse the sides of the conflict on `{}`; showing the merged text only: {:?}",
+                    note,
+                    err
+                );
+                imp.sides_box.set_visible(false);
+                imp.fields_group.set_visible(false);
+                imp.chunks.replace(conflict.merge_bodies());
+                return Ok(());
+            }
+        };
+
+        imp.sides_box.set_visible(true);
+        imp.ours_view.buffer().set_text(&ours.body);
+        imp.theirs_view.buffer().set_text(&theirs.body);
+
+        for field in outcome.conflicting_fields {
+            let row = self.field_row(note, field, &ours, &theirs);
+            imp.fields_group.add(&row);
+            imp.field_rows.borrow_mut().push(row);
+        }
+        imp.fields_group
+            .set_visible(!imp.field_rows.borrow().is_empty());
+
+        imp.chunks.replace(outcome.body_chunks);
+
+        Ok(())
+    }
+
+    fn field_row(
+        &self,
+        note: &Note,
+        field: &str,
+        ours: &RawNote,
+        theirs: &RawNote,
+    ) -> adw::ActionRow {
+        let (title, ours_value, theirs_value) = match field {
+            "title" => (
+                gettext("Title"),
+                ours.metadata.title.clone(),
+                theirs.metadata.title.clone(),
+            ),
+            "is_pinned" => (
+                gettext("Pinned"),
+                yes_no(ours.metadata.is_pinned),
+                yes_no(theirs.metadata.is_pinned),
+            ),
+            "is_trashed" => (
+                gettext("Trashed"),
+                yes_no(ours.metadata.is_trashed),
+                yes_no(theirs.metadata.is_trashed),
+            ),
+            other => unreachable!("Unknown conflicting field `{}`", other),
+        };
+
+        let row = adw::ActionRow::builder()
+            .title(&title)
+            .subtitle(&gettext!("Mine: {} — Theirs: {}", ours_value, theirs_value))
+            .build();
+
+        let mine_button = gtk::ToggleButton::with_label(&gettext("Mine"));
+        let theirs_button = gtk::ToggleButton::with_label(&gettext("Theirs"));
+        theirs_button.set_group(Some(&mine_button));
+
+        let ours_metadata = ours.metadata.clone();
+        let theirs_metadata = theirs.metadata.clone();
+        let field = field.to_string();
+        let apply_choice = clone!(@weak note => move |choice: Choice| {
+            let metadata = if matches!(choice, Choice::Mine) {
+                &ours_metadata
+            } else {
+                &theirs_metadata
+            };
+            let note_metadata = note.metadata();
+
+            match field.as_str() {
+                "title" => note_metadata.set_title(&metadata.title),
+                "is_pinned" => note_metadata.set_is_pinned(metadata.is_pinned),
+                "is_trashed" => note_metadata.set_is_trashed(metadata.is_trashed),
+                _ => unreachable!(),
+            }
+        });
+
+        let apply_choice_clone = apply_choice.clone();
+        mine_button.connect_toggled(move |button| {
+            if button.is_active() {
+                apply_choice_clone(Choice::Mine);
+            }
+        });
+        theirs_button.connect_toggled(move |button| {
+            if button.is_active() {
+                apply_choice(Choice::Theirs);
+            }
+        });
+
+        let button_box = gtk::Box::builder()
+            .valign(gtk::Align::Center)
+            .css_classes(vec!["linked".into()])
+            .build();
+        button_box.append(&mine_button);
+        button_box.append(&theirs_button);
+        row.add_suffix(&button_box);
+
+        row
+    }
+
+    fn update_hunk_rows(&self) {
+        let imp = self.imp();
+
+        for row in imp.hunk_rows.borrow_mut().drain(..) {
+            imp.hunks_group.remove(&row);
+        }
+
+        let mut n_hunks = 0;
+
+        for (index, chunk) in imp.chunks.borrow().iter().enumerate() {
+            if let TextChunk::Conflict { ours, .. } = chunk {
+                n_hunks += 1;
+
+                let row = adw::ActionRow::builder()
+                    .title(&gettext!("Change {}", n_hunks))
+                    .subtitle(ours.lines().next().unwrap_or_default())
+                    .build();
+
+                let button_box = gtk::Box::builder()
+                    .valign(gtk::Align::Center)
+                    .css_classes(vec!["linked".into()])
+                    .build();
+
+                for (label, choice) in [
+                    (gettext("Mine"), Choice::Mine),
+                    (gettext("Theirs"), Choice::Theirs),
+                    (gettext("Both"), Choice::Both),
+                ] {
+                    let button = gtk::Button::with_label(&label);
+                    button.connect_clicked(clone!(@weak self as obj => move |_| {
+                        obj.choose_hunk(index, choice);
+                    }));
+                    button_box.append(&button);
+                }
+
+                row.add_suffix(&button_box);
+                imp.hunks_group.add(&row);
+                imp.hunk_rows.borrow_mut().push(row);
+            }
+        }
+
+        imp.hunks_group.set_visible(n_hunks > 0);
+    }
+
+    /// Replace the conflicting hunk at `index` with the chosen side in the merged text, keeping
+    /// the manual edits to the rest of it
+    fn choose_hunk(&self, index: usize, choice: Choice) {
+        let imp = self.imp();
+
+        let note = match self.note() {
+            Some(note) => note,
+            None => return,
+        };
+
+        let (ours, theirs) = match &imp.chunks.borrow()[index] {
+            TextChunk::Conflict { ours, theirs } => (ours.clone(), theirs.clone()),
+            TextChunk::Resolved(_) => return,
+        };
+        let resolved = match choice {
+            Choice::Mine => ours.clone(),
+            Choice::Theirs => theirs.clone(),
+            Choice::Both => format!("{}{}", ours, theirs),
+        };
+
+        let buffer = note.buffer();
+        let (start, end) = buffer.bounds();
+        let text = buffer.text(&start, &end, true);
+
+        match find_conflict(&text, &ours, &theirs) {
+            Some(range) => {
+                let start_offset = text[..range.start].chars().count() as i32;
+                let end_offset = text[..range.end].chars().count() as i32;
+
+                let mut start = buffer.iter_at_offset(start_offset);
+                let mut end = buffer.iter_at_offset(end_offset);
+                buffer.begin_user_action();
+                buffer.delete(&mut start, &mut end);
+                buffer.insert(&mut start, &resolved);
+                buffer.end_user_action();
+            }
+            // The hunk was edited by hand, so only the whole text can be regenerated
+            None => {
+                self.show_overwrite_edits_dialog(index, resolved);
+                return;
+            }
+        }
+
+        imp.chunks.borrow_mut()[index] = TextChunk::Resolved(resolved);
+        self.update_hunk_rows();
+    }
+
+    fn show_overwrite_edits_dialog(&self, index: usize, resolved: String) {
+        let dialog = gtk::MessageDialog::builder()
+            .text(&gettext("Discard Your Edits?"))
+            .secondary_text(&gettext(
+                "This change was edited by hand. Choosing a side for it regenerates the merged text, which discards all of your edits to it.",
+            ))
+            .message_type(gtk::MessageType::Question)
+            .modal(true)
+            .build();
+        dialog.set_transient_for(
+            self.root()
+                .map(|w| w.downcast::<gtk::Window>().unwrap())
+                .as_ref(),
+        );
+        dialog.add_buttons(&[
+            (&gettext("Cancel"), gtk::ResponseType::Cancel),
+            (&gettext("Discard"), gtk::ResponseType::Accept),
+        ]);
+        if let Some(button) = dialog.widget_for_response(gtk::ResponseType::Accept) {
+            button.add_css_class("destructive-action");
+        }
+
+        dialog.connect_response(clone!(@weak self as obj => move |dialog, response| {
+            dialog.destroy();
+
+            if response != gtk::ResponseType::Accept {
+                return;
+            }
+
+            let imp = obj.imp();
+            imp.chunks.borrow_mut()[index] = TextChunk::Resolved(resolved.clone());
+
+            if let Some(note) = obj.note() {
+                note.buffer()
+                    .set_text(&render_with_markers(&imp.chunks.borrow()));
+            }
+
+            obj.update_hunk_rows();
+        }));
+
+        dialog.present();
+    }
+
+    fn update_resolve_action(&self) {
+        let has_markers = self.note().map_or(true, |note| {
+            let buffer = note.buffer();
+            let (start, end) = buffer.bounds();
+            buffer
+                .text(&start, &end, true)
+                .lines()
+                .any(|line| line == OURS_MARKER)
+        });
+
+        self.action_set_enabled("conflict-view.resolve", !has_markers);
+    }
+
+    fn resolve(&self) {
+        let note = match self.note() {
+            Some(note) => note,
+            None => return,
+        };
+
+        spawn!(async move {
+            let session = Session::default();
+
+            if let Err(err) = session.note_manager().resolve_conflict(&note).await {
+                log::error!("Failed to resolve conflict: {:?}", err);
+                return;
+            }
+
+            if let Err(err) = session.sync().await {
+                log::error!("Failed to sync after resolving conflict: {:?}", err);
+            }
+        });
+    }
+}
+
+fn yes_no(value: bool) -> String {
+    if value {
+        gettext("Yes")
+    } else {
+        gettext("No")
+    }
+}
@@ -0,0 +1,180 @@
+use adw::{prelude::*, subclass::prelude::*};
+use gettextrs::{gettext, ngettext};
+use gtk::{glib, subclass::prelude::*};
+
+use std::cell::RefCell;
+
+use crate::core::{LineChange, MetadataChange, NoteDiff};
+
+const ADDED_TAG: &str = "added";
+const REMOVED_TAG: &str = "removed";
+
+mod imp {
+    use super::*;
+    use gtk::CompositeTemplate;
+
+    #[derive(Debug, Default, CompositeTemplate)]
+    #[template(resource = "/io/github/seadve/Noteworthy/ui/content-diff-view.ui")]
+    pub struct DiffView {
+        #[template_child]
+        pub stack: TemplateChild<gtk::Stack>,
+        #[template_child]
+        pub empty_page: TemplateChild<adw::StatusPage>,
+        #[template_child]
+        pub diff_page: TemplateChild<gtk::ScrolledWindow>,
+        #[template_child]
+        pub metadata_group: TemplateChild<adw::PreferencesGroup>,
+        #[template_child]
+        pub source_view: TemplateChild<gtk_source::View>,
+
+        pub metadata_rows: RefCell<Vec<adw::ActionRow>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for DiffView {
+        const NAME: &'static str = "NwtyContentDiffView";
+        type Type = super::DiffView;
+        type ParentType = adw::Bin;
+
+        fn class_init(klass: &mut Self::Class) {
+            Self::bind_template(klass);
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for DiffView {
+        fn constructed(&self, obj: &Self::Type) {
+            self.parent_constructed(obj);
+
+            obj.setup_tags();
+            obj.set_diff(None);
+        }
+    }
+
+    impl WidgetImpl for DiffView {}
+    impl BinImpl for DiffView {}
+}
+
+glib::wrapper! {
+    pub struct DiffView(ObjectSubclass<imp::DiffView>)
+        @extends gtk::Widget, adw::Bin;
+}
+
+impl DiffView {
+    pub fn new() -> Self {
+        glib::Object::new(&[]).expect("Failed to create DiffView.")
+    }
+
+    /// Show the changes of `diff`, or that there is nothing to compare if it is `None`
+    pub fn set_diff(&self, diff: Option<&NoteDiff>) {
+        let imp = self.imp();
+
+        for row in imp.metadata_rows.borrow_mut().drain(..) {
+            imp.metadata_group.remove(&row);
+        }
+
+        let buffer = imp.source_view.buffer();
+        buffer.set_text("");
+
+        let diff = match diff {
+            Some(diff) if !diff.is_empty() => diff,
+            _ => {
+                imp.stack.set_visible_child(&imp.empty_page.get());
+                return;
+            }
+        };
+
+        for change in &diff.metadata_changes {
+            let row = adw::ActionRow::builder()
+                .title(&glib::markup_escape_text(&describe_metadata_change(change)))
+                .build();
+            imp.metadata_group.add(&row);
+            imp.metadata_rows.borrow_mut().push(row);
+        }
+        imp.metadata_group
+            .set_visible(!diff.metadata_changes.is_empty());
+
+        let mut iter = buffer.end_iter();
+        for line in &diff.lines {
+            match line.change {
+                LineChange::Unchanged => buffer.insert(&mut iter, &format!("  {}\n", line.text)),
+                LineChange::Added => buffer.insert_with_tags_by_name(
+                    &mut iter,
+                    &format!("+ {}\n", line.text),
+                    &[ADDED_TAG],
+                ),
+                LineChange::Removed => buffer.insert_with_tags_by_name(
+                    &mut iter,
+                    &format!("- {}\n", line.text),
+                    &[REMOVED_TAG],
+                ),
+            }
+        }
+
+        imp.stack.set_visible_child(&imp.diff_page.get());
+    }
+
+    fn setup_tags(&self) {
+        let tag_table = self.imp().source_view.buffer().tag_table();
+
+        // Same hues as the success and destructive colors of Adwaita
+        let added_tag = gtk::TextTag::builder()
+            .name(ADDED_TAG)
+            .paragraph_background("rgba(38, 162, 105, 0.2)")
+            .build();
+        tag_table.add(&added_tag);
+
+        let removed_tag = gtk::TextTag::builder()
+            .name(REMOVED_TAG)
+            .paragraph_background("rgba(224, 27, 36, 0.2)")
+            .strikethrough(true)
+            .build();
+        tag_table.add(&removed_tag);
+    }
+}
+
+fn describe_metadata_change(change: &MetadataChange) -> String {
+    match change {
+        MetadataChange::TitleChanged { old, new } if old.is_empty() => {
+            gettext!("Titled “{}”", new)
+        }
+        MetadataChange::TitleChanged { old, new } => {
+            gettext!("Renamed from “{}” to “{}”", old, new)
+        }
+        MetadataChange::TagsAdded(tags) => ngettext!(
+            "Added tag {}",
+            "Added tags {}",
+            n_items(tags.len()),
+            tags.join(", ")
+        ),
+        MetadataChange::TagsRemoved(tags) => ngettext!(
+            "Removed tag {}",
+            "Removed tags {}",
+            n_items(tags.len()),
+            tags.join(", ")
+        ),
+        MetadataChange::Pinned => gettext("Pinned"),
+        MetadataChange::Unpinned => gettext("Unpinned"),
+        MetadataChange::Trashed => gettext("Moved to trash"),
+        MetadataChange::Restored => gettext("Restored from trash"),
+        MetadataChange::AttachmentsAdded(n) => ngettext!(
+            "Added {} attachment",
+            "Added {} attachments",
+            n_items(*n),
+            n
+        ),
+        MetadataChange::AttachmentsRemoved(n) => ngettext!(
+            "Removed {} attachment",
+            "Removed {} attachments",
+            n_items(*n),
+            n
+        ),
+    }
+}
+
+fn n_items(len: usize) -> u32 {
+    u32::try_from(len).unwrap_or(u32::MAX)
+}
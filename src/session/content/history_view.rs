@@ -0,0 +1,374 @@
+use adw::{prelude::*, subclass::prelude::*};
+use gettextrs::gettext;
+use gtk::{
+    glib::{self, clone},
+    subclass::prelude::*,
+};
+
+use std::cell::RefCell;
+
+use super::{super::Session, diff_view::DiffView};
+use crate::{
+    core::{DateTime, NoteDiff, RawNote, Revision},
+    model::Note,
+    spawn,
+};
+
+mod imp {
+    use super::*;
+    use glib::subclass::Signal;
+    use gtk::CompositeTemplate;
+    use once_cell::sync::Lazy;
+
+    #[derive(Debug, Default, CompositeTemplate)]
+    #[template(resource = "/io/github/seadve/Noteworthy/ui/content-history-view.ui")]
+    pub struct HistoryView {
+        #[template_child]
+        pub stack: TemplateChild<gtk::Stack>,
+        #[template_child]
+        pub empty_page: TemplateChild<adw::StatusPage>,
+        #[template_child]
+        pub history_page: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub revision_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub preview_stack: TemplateChild<gtk::Stack>,
+        #[template_child]
+        pub preview_view: TemplateChild<gtk_source::View>,
+        #[template_child]
+        pub diff_view: TemplateChild<DiffView>,
+        #[template_child]
+        pub compare_drop_down: TemplateChild<gtk::DropDown>,
+        #[template_child]
+        pub compare_model: TemplateChild<gtk::StringList>,
+
+        pub note: RefCell<Option<Note>>,
+        pub revisions: RefCell<Vec<Revision>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for HistoryView {
+        const NAME: &'static str = "NwtyContentHistoryView";
+        type Type = super::HistoryView;
+        type ParentType = adw::Bin;
+
+        fn class_init(klass: &mut Self::Class) {
+            DiffView::static_type();
+            Self::bind_template(klass);
+
+            klass.install_action("history-view.restore", None, move |obj, _, _| {
+                obj.restore();
+            });
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for HistoryView {
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: Lazy<Vec<Signal>> = Lazy::new(|| {
+                vec![Signal::builder("restored", &[], <()>::static_type().into()).build()]
+            });
+            SIGNALS.as_ref()
+        }
+
+        fn properties() -> &'static [glib::ParamSpec] {
+            static PROPERTIES: Lazy<Vec<glib::ParamSpec>> = Lazy::new(|| {
+                vec![glib::ParamSpecObject::new(
+                    "note",
+                    "Note",
+                    "Note whose history is shown",
+                    Note::static_type(),
+                    glib::ParamFlags::READWRITE | glib::ParamFlags::EXPLICIT_NOTIFY,
+                )]
+            });
+            PROPERTIES.as_ref()
+        }
+
+        fn set_property(
+            &self,
+            obj: &Self::Type,
+            _id: usize,
+            value: &glib::Value,
+            pspec: &glib::ParamSpec,
+        ) {
+            match pspec.name() {
+                "note" => {
+                    let note = value.get().unwrap();
+                    obj.set_note(note);
+                }
+                _ => unimplemented!(),
+            }
+        }
+
+        fn property(&self, obj: &Self::Type, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+            match pspec.name() {
+                "note" => obj.note().to_value(),
+                _ => unimplemented!(),
+            }
+        }
+
+        fn constructed(&self, obj: &Self::Type) {
+            self.parent_constructed(obj);
+
+            self.revision_list
+                .connect_row_selected(clone!(@weak obj => move |_, _| {
+                    obj.update_preview();
+                    obj.select_previous_version();
+                }));
+
+            self.compare_drop_down
+                .connect_selected_notify(clone!(@weak obj => move |_| {
+                    obj.update_diff();
+                }));
+
+            self.preview_stack
+                .connect_visible_child_name_notify(clone!(@weak obj => move |_| {
+                    obj.update_diff();
+                }));
+
+            obj.update_preview();
+            obj.update_diff();
+        }
+    }
+
+    impl WidgetImpl for HistoryView {}
+    impl BinImpl for HistoryView {}
+}
+
+glib::wrapper! {
+    pub struct HistoryView(ObjectSubclass<imp::HistoryView>)
+        @extends gtk::Widget, adw::Bin;
+}
+
+impl HistoryView {
+    pub fn new() -> Self {
+        glib::Object::new(&[]).expect("Failed to create HistoryView.")
+    }
+
+    pub fn connect_restored<F>(&self, f: F) -> glib::SignalHandlerId
+    where
+        F: Fn(&Self) + 'static,
+    {
+        self.connect_local("restored", true, move |values| {
+            let obj = values[0].get::<Self>().unwrap();
+            f(&obj);
+            None
+        })
+    }
+
+    pub fn note(&self) -> Option<Note> {
+        self.imp().note.borrow().clone()
+    }
+
+    pub fn set_note(&self, note: Option<Note>) {
+        if self.note() == note {
+            return;
+        }
+
+        self.imp().note.replace(note);
+        self.notify("note");
+
+        self.set_revisions(Vec::new());
+    }
+
+    /// Load the commits that changed the note. This is not done when the note is set, as walking
+    /// the history is only worth it when the view is shown.
+    pub fn load_history(&self) {
+        let note = match self.note() {
+            Some(note) => note,
+            None => return,
+        };
+
+        spawn!(clone!(@weak self as obj => async move {
+            let path = note.file().path().unwrap();
+            let res = Session::default()
+                .note_manager()
+                .repository()
+                .file_history(&path)
+                .await;
+
+            // The note may have changed while loading
+            if obj.note().as_ref() != Some(&note) {
+                return;
+            }
+
+            match res {
+                Ok(revisions) => obj.set_revisions(revisions),
+                Err(err) => {
+                    log::error!("Failed to load history of `{}`: {:?}", note, err);
+                    obj.set_revisions(Vec::new());
+                }
+            }
+        }));
+    }
+
+    fn set_revisions(&self, revisions: Vec<Revision>) {
+        let imp = self.imp();
+
+        while let Some(row) = imp.revision_list.first_child() {
+            imp.revision_list.remove(&row);
+        }
+
+        // The first version to compare with is the note as it is now, including unsaved changes
+        let mut compare_items = vec![gettext("Current Note")];
+        for revision in &revisions {
+            imp.revision_list.append(&Self::revision_row(revision));
+            compare_items.push(revision.summary.clone());
+        }
+        let compare_items = compare_items.iter().map(String::as_str).collect::<Vec<_>>();
+        imp.compare_model
+            .splice(0, imp.compare_model.n_items(), &compare_items);
+
+        if revisions.is_empty() {
+            imp.stack.set_visible_child(&imp.empty_page.get());
+        } else {
+            imp.stack.set_visible_child(&imp.history_page.get());
+        }
+
+        imp.revisions.replace(revisions);
+
+        imp.revision_list
+            .select_row(imp.revision_list.row_at_index(0).as_ref());
+        self.update_preview();
+        self.select_previous_version();
+    }
+
+    fn revision_row(revision: &Revision) -> adw::ActionRow {
+        let time = DateTime::from_unix(revision.time)
+            .map(|date_time| date_time.fuzzy_display())
+            .unwrap_or_default();
+
+        let subtitle = match revision.device {
+            // Translators: The first {} is a time, e.g., "Sep 03", and the second {} is the name of
+            // a device, e.g., "Laptop"
+            Some(ref device) => gettext!("{} on {}", time, device),
+            None => time,
+        };
+
+        adw::ActionRow::builder()
+            .title(&glib::markup_escape_text(&revision.summary))
+            .subtitle(&glib::markup_escape_text(&subtitle))
+            .build()
+    }
+
+    fn selected_revision(&self) -> Option<Revision> {
+        let imp = self.imp();
+        let index = imp.revision_list.selected_row()?.index();
+        imp.revisions
+            .borrow()
+            .get(usize::try_from(index).ok()?)
+            .cloned()
+    }
+
+    /// Versions are indexed from the newest, where 0 is the current note and `n` is the `n`th
+    /// revision in the list
+    fn selected_version(&self) -> Option<u32> {
+        let index = self.imp().revision_list.selected_row()?.index();
+        u32::try_from(index).ok().map(|index| index + 1)
+    }
+
+    /// The text of the version at `index`, or `None` if the note did not exist in it
+    fn version_text(&self, index: u32) -> Option<String> {
+        if index == 0 {
+            return self.note().and_then(|note| {
+                note.text()
+                    .map_err(|err| log::warn!("Failed to serialize `{}`: {:?}", note, err))
+                    .ok()
+            });
+        }
+
+        self.imp()
+            .revisions
+            .borrow()
+            .get(usize::try_from(index - 1).ok()?)
+            .and_then(|revision| revision.content.clone())
+    }
+
+    /// Compare the selected revision with the one before it, so the changes made in it are shown.
+    /// The oldest one is compared with the current note instead.
+    fn select_previous_version(&self) {
+        let imp = self.imp();
+
+        let previous = self
+            .selected_version()
+            .map(|selected| selected + 1)
+            .filter(|&previous| previous < imp.compare_model.n_items())
+            .unwrap_or(0);
+        imp.compare_drop_down.set_selected(previous);
+
+        self.update_diff();
+    }
+
+    fn update_diff(&self) {
+        let imp = self.imp();
+
+        // Only compute the diff when it is shown, as the current note may be long
+        if imp.preview_stack.visible_child_name().as_deref() != Some("changes") {
+            imp.compare_drop_down.set_visible(false);
+            return;
+        }
+        imp.compare_drop_down.set_visible(true);
+
+        let compared = imp.compare_drop_down.selected();
+        let diff = self
+            .selected_version()
+            .filter(|_| compared != gtk::INVALID_LIST_POSITION)
+            .and_then(|selected| {
+                let (older, newer) = if compared > selected {
+                    (compared, selected)
+                } else {
+                    (selected, compared)
+                };
+
+                let older_text = self.version_text(older);
+                let newer_text = self.version_text(newer).unwrap_or_default();
+
+                NoteDiff::from_texts(older_text.as_deref(), &newer_text)
+                    .map_err(|err| log::warn!("Failed to compare versions: {:?}", err))
+                    .ok()
+            });
+
+        imp.diff_view.set_diff(diff.as_ref());
+    }
+
+    fn update_preview(&self) {
+        let content = self
+            .selected_revision()
+            .and_then(|revision| revision.content);
+
+        // Only show the body, as the front matter is not shown on the editor either
+        let preview = content.as_deref().map_or_else(String::new, |content| {
+            RawNote::parse(content).map_or_else(|_| content.to_string(), |note| note.body)
+        });
+        self.imp().preview_view.buffer().set_text(&preview);
+
+        self.action_set_enabled("history-view.restore", content.is_some());
+    }
+
+    fn restore(&self) {
+        let note = match self.note() {
+            Some(note) => note,
+            None => return,
+        };
+
+        let content = match self
+            .selected_revision()
+            .and_then(|revision| revision.content)
+        {
+            Some(content) => content,
+            None => return,
+        };
+
+        spawn!(clone!(@weak self as obj => async move {
+            if let Err(err) = note.restore(&content).await {
+                log::error!("Failed to restore `{}`: {:?}", note, err);
+                return;
+            }
+
+            obj.emit_by_name::<()>("restored", &[]);
+        }));
+    }
+}
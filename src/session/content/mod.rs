@@ -1,11 +1,21 @@
 mod attachment_view;
+mod conflict_view;
+mod diff_view;
+mod history_view;
 mod view;
 
-use gtk::{glib, prelude::*, subclass::prelude::*};
+use gtk::{
+    glib::{self, clone},
+    prelude::*,
+    subclass::prelude::*,
+};
 
 use std::cell::{Cell, RefCell};
 
-use self::{attachment_view::AttachmentView, view::View};
+use self::{
+    attachment_view::AttachmentView, conflict_view::ConflictView, history_view::HistoryView,
+    view::View,
+};
 use crate::model::Note;
 
 mod imp {
@@ -25,18 +35,27 @@ mod imp {
         #[template_child]
         pub no_selected_view: TemplateChild<adw::StatusPage>,
         #[template_child]
+        pub conflict_view: TemplateChild<ConflictView>,
+        #[template_child]
+        pub history_view: TemplateChild<HistoryView>,
+        #[template_child]
+        pub history_button: TemplateChild<gtk::ToggleButton>,
+        #[template_child]
         pub edit_tags_button: TemplateChild<gtk::Button>,
         #[template_child]
         pub is_pinned_button: TemplateChild<gtk::ToggleButton>,
         #[template_child]
         pub is_trashed_button: TemplateChild<gtk::ToggleButton>,
         #[template_child]
+        pub is_local_only_button: TemplateChild<gtk::ToggleButton>,
+        #[template_child]
         pub view_flap_button: TemplateChild<gtk::ToggleButton>,
 
         pub compact: Cell<bool>,
         pub note: RefCell<Option<Note>>,
 
         pub bindings: RefCell<Vec<glib::Binding>>,
+        pub is_conflicted_handler_id: RefCell<Option<glib::SignalHandlerId>>,
     }
 
     #[glib::object_subclass]
@@ -47,6 +66,8 @@ mod imp {
 
         fn class_init(klass: &mut Self::Class) {
             View::static_type();
+            ConflictView::static_type();
+            HistoryView::static_type();
             Self::bind_template(klass);
         }
 
@@ -109,6 +130,32 @@ mod imp {
         fn constructed(&self, obj: &Self::Type) {
             self.parent_constructed(obj);
 
+            self.history_button
+                .connect_toggled(clone!(@weak obj => move |_| {
+                    obj.update_stack();
+                }));
+
+            // It is not bound both ways, as changing it can fail
+            self.is_local_only_button
+                .connect_toggled(clone!(@weak obj => move |button| {
+                    let is_local_only = button.is_active();
+
+                    // It is also toggled when another note is shown
+                    if matches!(obj.note(), Some(note) if note.is_local_only() != is_local_only) {
+                        button
+                            .activate_action(
+                                "session.set-selected-note-local-only",
+                                Some(&is_local_only.to_variant()),
+                            )
+                            .unwrap();
+                    }
+                }));
+
+            self.history_view
+                .connect_restored(clone!(@weak obj => move |_| {
+                    obj.imp().history_button.set_active(false);
+                }));
+
             obj.update_buttons_visibility();
             obj.update_stack();
         }
@@ -144,10 +191,17 @@ impl Content {
 
         let imp = self.imp();
 
+        // History is of the previous note
+        imp.history_button.set_active(false);
+
         for binding in imp.bindings.borrow_mut().drain(..) {
             binding.unbind();
         }
 
+        if let Some(handler_id) = imp.is_conflicted_handler_id.take() {
+            self.note().unwrap().disconnect(handler_id);
+        }
+
         if let Some(ref note) = note {
             let mut bindings = imp.bindings.borrow_mut();
             let note_metadata = note.metadata();
@@ -163,6 +217,19 @@ impl Content {
                 .flags(glib::BindingFlags::SYNC_CREATE | glib::BindingFlags::BIDIRECTIONAL)
                 .build();
             bindings.push(is_trashed);
+
+            let is_local_only = note
+                .bind_property("is-local-only", &imp.is_local_only_button.get(), "active")
+                .flags(glib::BindingFlags::SYNC_CREATE)
+                .build();
+            bindings.push(is_local_only);
+
+            let handler_id =
+                note.connect_is_conflicted_notify(clone!(@weak self as obj => move |_| {
+                    obj.update_buttons_visibility();
+                    obj.update_stack();
+                }));
+            imp.is_conflicted_handler_id.replace(Some(handler_id));
         }
 
         imp.note.replace(note);
@@ -175,20 +242,28 @@ impl Content {
     fn update_stack(&self) {
         let imp = self.imp();
 
-        if self.note().is_some() {
-            imp.stack.set_visible_child(&imp.view_flap.get());
-        } else {
-            imp.stack.set_visible_child(&imp.no_selected_view.get());
+        match self.note() {
+            Some(note) if note.is_conflicted() => {
+                imp.stack.set_visible_child(&imp.conflict_view.get());
+            }
+            Some(_) if imp.history_button.is_active() => {
+                imp.stack.set_visible_child(&imp.history_view.get());
+                imp.history_view.load_history();
+            }
+            Some(_) => imp.stack.set_visible_child(&imp.view_flap.get()),
+            None => imp.stack.set_visible_child(&imp.no_selected_view.get()),
         }
     }
 
     fn update_buttons_visibility(&self) {
         let imp = self.imp();
-        let has_note = self.note().is_some();
+        let has_note = self.note().map_or(false, |note| !note.is_conflicted());
 
         imp.is_pinned_button.set_visible(has_note);
         imp.is_trashed_button.set_visible(has_note);
+        imp.is_local_only_button.set_visible(has_note);
         imp.edit_tags_button.set_visible(has_note);
         imp.view_flap_button.set_visible(has_note);
+        imp.history_button.set_visible(has_note);
     }
 }
@@ -153,12 +153,29 @@ impl View {
     }
 
     fn setup_expressions(&self) {
-        Self::this_expression("note")
+        let last_modified_expression = Self::this_expression("note")
             .chain_property::<Note>("metadata")
-            .chain_property::<NoteMetadata>("last-modified")
-            .chain_closure::<String>(closure!(|_: Self, last_modified: DateTime| {
-                gettext!("Last edited {}", last_modified.fuzzy_display())
-            }))
-            .bind(&self.imp().last_modified_label.get(), "label", Some(self));
+            .chain_property::<NoteMetadata>("last-modified");
+        let last_edit_device_expression =
+            Self::this_expression("note").chain_property::<Note>("last-edit-device");
+
+        gtk::ClosureExpression::new::<String, _, _>(
+            [last_modified_expression, last_edit_device_expression],
+            closure!(
+                |_: Self, last_modified: DateTime, last_edit_device: Option<String>| {
+                    match last_edit_device {
+                        // Translators: The first {} is a time, e.g., "2 minutes ago", and the
+                        // second {} is the name of a device, e.g., "Laptop"
+                        Some(device) => gettext!(
+                            "Last edited {} on {}",
+                            last_modified.fuzzy_display(),
+                            device
+                        ),
+                        None => gettext!("Last edited {}", last_modified.fuzzy_display()),
+                    }
+                }
+            ),
+        )
+        .bind(&self.imp().last_modified_label.get(), "label", Some(self));
     }
 }
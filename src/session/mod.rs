@@ -1,11 +1,15 @@
+mod auto_sync;
+mod config;
 mod content;
 mod note_manager;
 mod note_tag_dialog;
 mod picture_viewer;
 mod sidebar;
+mod sync_queue;
 mod tag_editor;
 
 use adw::subclass::prelude::*;
+use gettextrs::gettext;
 use gtk::{
     gio,
     glib::{self, clone},
@@ -19,12 +23,17 @@ use std::{
     path::PathBuf,
 };
 
+pub use self::{
+    config::{SessionConfig, SessionMode},
+    note_manager::NoteManager,
+};
+
 use self::{
-    content::Content, note_manager::NoteManager, note_tag_dialog::NoteTagDialog,
+    auto_sync::AutoSync, content::Content, note_tag_dialog::NoteTagDialog,
     picture_viewer::PictureViewer, sidebar::Sidebar, tag_editor::TagEditor,
 };
 use crate::{
-    core::FileType,
+    core::{AuthError, Credentials, FileType, NoteRepository, Problem, RemoteUrl, SyncError},
     model::{Attachment, Note},
     spawn, Application,
 };
@@ -37,6 +46,8 @@ mod imp {
     #[derive(Debug, Default, CompositeTemplate)]
     #[template(resource = "/io/github/seadve/Noteworthy/ui/session.ui")]
     pub struct Session {
+        #[template_child]
+        pub toast_overlay: TemplateChild<adw::ToastOverlay>,
         #[template_child]
         pub stack: TemplateChild<gtk::Stack>,
         #[template_child]
@@ -49,8 +60,12 @@ mod imp {
         pub picture_viewer: TemplateChild<PictureViewer>,
 
         pub note_manager: OnceCell<NoteManager>,
+        pub auto_sync: OnceCell<AutoSync>,
         pub selected_note: RefCell<Option<Note>>,
         pub is_syncing: Cell<bool>,
+        pub auth_dialog: RefCell<Option<gtk::MessageDialog>>,
+        pub problem_dialog: RefCell<Option<gtk::MessageDialog>>,
+        pub sync_error_toast: RefCell<Option<adw::Toast>>,
     }
 
     #[glib::object_subclass]
@@ -74,6 +89,18 @@ mod imp {
                 }));
             });
 
+            klass.install_action("session.cancel-sync", None, move |obj, _, _| {
+                obj.note_manager().repository().cancel_sync();
+            });
+
+            klass.install_action("session.attach-remote", None, move |obj, _, _| {
+                obj.show_attach_remote_dialog();
+            });
+
+            klass.install_action("session.detach-remote", None, move |obj, _, _| {
+                obj.show_detach_remote_dialog();
+            });
+
             klass.install_action("session.create-note", None, move |obj, _, _| {
                 let note_manager = obj.note_manager();
                 note_manager.create_note();
@@ -109,6 +136,34 @@ mod imp {
                 note_tag_dialog.present();
             });
 
+            klass.install_action(
+                "session.set-selected-note-local-only",
+                Some("b"),
+                move |obj, _, args| {
+                    let is_local_only = args.and_then(|args| args.get::<bool>()).unwrap();
+                    let note = match obj.selected_note() {
+                        Some(note) => note,
+                        None => return,
+                    };
+
+                    spawn!(clone!(@weak obj => async move {
+                        let note_manager = obj.note_manager();
+                        let res = note_manager.set_note_local_only(&note, is_local_only).await;
+
+                        if let Err(err) = res {
+                            log::error!("Failed to set note local only: {:?}", err);
+                            obj.show_error_dialog(
+                                &gettext("Failed to Change Whether the Note Is Synced"),
+                                &err,
+                            );
+
+                            // Show the state that was kept
+                            note.notify("is-local-only");
+                        }
+                    }));
+                },
+            );
+
             klass.install_action(
                 "session.edit-multi-selected-note-tags",
                 None,
@@ -220,14 +275,26 @@ glib::wrapper! {
 }
 
 impl Session {
-    pub async fn new(directory: &gio::File) -> Self {
-        let note_manager = NoteManager::for_directory(directory, false).await;
-        glib::Object::new(&[("note-manager", &note_manager)]).expect("Failed to create Session.")
+    pub async fn new(directory: &gio::File) -> anyhow::Result<Self> {
+        let note_manager = NoteManager::for_directory(directory, false).await?;
+        Ok(glib::Object::new(&[("note-manager", &note_manager)])
+            .expect("Failed to create Session."))
     }
 
-    pub async fn new_offline(directory: &gio::File) -> Self {
-        let note_manager = NoteManager::for_directory(directory, true).await;
-        glib::Object::new(&[("note-manager", &note_manager)]).expect("Failed to create Session.")
+    pub async fn new_offline(directory: &gio::File) -> anyhow::Result<Self> {
+        let note_manager = NoteManager::for_directory(directory, true).await?;
+        Ok(glib::Object::new(&[("note-manager", &note_manager)])
+            .expect("Failed to create Session."))
+    }
+
+    /// Restore the session that was set up with `config`
+    pub async fn for_config(config: &SessionConfig) -> anyhow::Result<Self> {
+        let directory = gio::File::for_path(&config.notes_directory);
+
+        match config.mode {
+            SessionMode::Online => Self::new(&directory).await,
+            SessionMode::Offline => Self::new_offline(&directory).await,
+        }
     }
 
     pub fn directory(&self) -> PathBuf {
@@ -245,14 +312,16 @@ impl Session {
 
         // FIXME this is unexpected for this function, maybe let the caller handle this syncing
         // Sync session before switching to other notes
-        spawn!(
-            glib::PRIORITY_DEFAULT_IDLE,
-            clone!(@weak self as obj => async move {
-                if let Err(err) = obj.sync().await {
-                    log::error!("Failed to sync session: {:?}", err);
-                }
-            })
-        );
+        if !self.is_auto_sync_paused() {
+            spawn!(
+                glib::PRIORITY_DEFAULT_IDLE,
+                clone!(@weak self as obj => async move {
+                    if let Err(err) = obj.sync().await {
+                        log::error!("Failed to sync session: {:?}", err);
+                    }
+                })
+            );
+        }
 
         let imp = self.imp();
 
@@ -276,15 +345,44 @@ impl Session {
         imp.sidebar.set_note_list(&note_manager.note_list());
         imp.sidebar.set_tag_list(&note_manager.tag_list());
 
+        let auto_sync = AutoSync::new(note_manager);
+        Application::default()
+            .settings()
+            .bind("is-auto-sync", &auto_sync, "is-paused")
+            .flags(gio::SettingsBindFlags::GET | gio::SettingsBindFlags::INVERT_BOOLEAN)
+            .build();
+        imp.auto_sync.set(auto_sync).unwrap();
+
         Ok(())
     }
 
     pub async fn sync(&self) -> anyhow::Result<()> {
-        self.note_manager().sync().await?;
+        if let Err(err) = self.note_manager().sync().await {
+            match err.downcast_ref::<SyncError>() {
+                Some(SyncError::Cancelled) => {
+                    log::info!("Session sync cancelled");
+                    return Ok(());
+                }
+                Some(SyncError::Auth(auth_error)) => self.show_auth_dialog(auth_error),
+                _ => {}
+            }
+            return Err(err);
+        }
+
         log::info!("Session synced");
         Ok(())
     }
 
+    /// Push the last changes, or only commit them if automatic sync is paused. Either way, it
+    /// waits for the running sync to finish first.
+    pub async fn sync_before_close(&self) -> anyhow::Result<()> {
+        if self.is_auto_sync_paused() {
+            return self.note_manager().commit_changes().await;
+        }
+
+        self.sync().await
+    }
+
     pub fn show_attachment(&self, attachment: Attachment) {
         let imp = self.imp();
 
@@ -299,6 +397,427 @@ impl Session {
         }
     }
 
+    /// Show why the last sync failed until a sync succeeds
+    fn update_sync_error_toast(&self) {
+        let imp = self.imp();
+
+        if let Some(toast) = imp.sync_error_toast.take() {
+            toast.dismiss();
+        }
+
+        let sync_error = match self.note_manager().repository().sync_error() {
+            Some(sync_error) => sync_error,
+            None => return,
+        };
+
+        let toast = adw::Toast::new(&glib::markup_escape_text(&Self::describe_sync_error(
+            &sync_error,
+        )));
+        toast.set_priority(adw::ToastPriority::High);
+        toast.set_timeout(0);
+
+        if sync_error.is_retryable() {
+            toast.set_button_label(Some(&gettext("_Retry")));
+            toast.set_action_name(Some("session.sync"));
+        } else if matches!(sync_error, SyncError::Signing(_)) {
+            // Signing can only be fixed in the preferences
+            toast.set_button_label(Some(&gettext("_Preferences")));
+            toast.set_action_name(Some("app.preferences"));
+        }
+
+        imp.toast_overlay.add_toast(&toast);
+        imp.sync_error_toast.replace(Some(toast));
+    }
+
+    fn describe_sync_error(sync_error: &SyncError) -> String {
+        match sync_error {
+            SyncError::Auth(auth_error) => {
+                gettext!("Failed to authenticate with the remote: {}", auth_error)
+            }
+            SyncError::Network(_) => gettext("Failed to sync: the remote could not be reached"),
+            SyncError::Conflict(_) => {
+                gettext("Failed to sync: the remote has changes that conflict with yours")
+            }
+            SyncError::Corrupt(_) => gettext("Failed to sync: the notes repository is damaged"),
+            SyncError::Diverged => gettext(
+                "Failed to sync: there are changes both here and on the remote, and only fast-forwarding is allowed",
+            ),
+            SyncError::Signing(signing_error) => {
+                gettext!("Failed to sync: the changes could not be signed: {}", signing_error)
+            }
+            SyncError::Cancelled => gettext("Sync was cancelled"),
+            SyncError::Other(message) => gettext!("Failed to sync: {}", message),
+        }
+    }
+
+    /// Walk through the problems of the repository that could not be repaired without asking, one
+    /// at a time
+    fn show_problem_dialog(&self) {
+        let imp = self.imp();
+
+        if imp.problem_dialog.borrow().is_some() {
+            return;
+        }
+
+        let problem = match self
+            .note_manager()
+            .repository()
+            .problems()
+            .into_iter()
+            .next()
+        {
+            Some(problem) => problem,
+            None => return,
+        };
+
+        let dialog = gtk::MessageDialog::builder()
+            .text(&gettext("Notes Need Repair"))
+            .secondary_text(&Self::describe_problem(&problem))
+            .message_type(gtk::MessageType::Warning)
+            .modal(true)
+            .build();
+        dialog.set_transient_for(
+            self.root()
+                .map(|w| w.downcast::<gtk::Window>().unwrap())
+                .as_ref(),
+        );
+
+        if problem.can_repair() {
+            dialog.add_buttons(&[
+                (&gettext("Later"), gtk::ResponseType::Cancel),
+                (&gettext("Repair"), gtk::ResponseType::Accept),
+            ]);
+            if let Some(button) = dialog.widget_for_response(gtk::ResponseType::Accept) {
+                button.add_css_class("destructive-action");
+            }
+        } else {
+            dialog.add_buttons(&[
+                (&gettext("Later"), gtk::ResponseType::Cancel),
+                (&gettext("Open Notes Folder"), gtk::ResponseType::Accept),
+            ]);
+        }
+
+        dialog.connect_response(clone!(@weak self as obj => move |dialog, response| {
+            obj.imp().problem_dialog.replace(None);
+            dialog.destroy();
+
+            if response != gtk::ResponseType::Accept {
+                return;
+            }
+
+            if !problem.can_repair() {
+                let uri = gio::File::for_path(obj.directory()).uri();
+                let res = gio::AppInfo::launch_default_for_uri(&uri, gio::AppLaunchContext::NONE);
+                if let Err(err) = res {
+                    log::error!("Failed to open `{}`: {:?}", uri, err);
+                }
+                return;
+            }
+
+            // The next problem, if any, is shown once this one is gone
+            let problem = problem.clone();
+            spawn!(clone!(@weak obj => async move {
+                if let Err(err) = obj.note_manager().repair_problem(problem).await {
+                    log::error!("Failed to repair problem: {:?}", err);
+                    obj.show_error_dialog(&gettext("Failed to Repair the Notes"), &err);
+                }
+            }));
+        }));
+
+        imp.problem_dialog.replace(Some(dialog.clone()));
+        dialog.present();
+    }
+
+    fn describe_problem(problem: &Problem) -> String {
+        match problem {
+            Problem::StaleIndexLock => {
+                gettext("Another program seems to have stopped while changing the notes.")
+            }
+            Problem::InterruptedMerge | Problem::InterruptedRebase => gettext(
+                "Combining your changes with the ones on the remote was interrupted. Repairing cancels it, and it is done again on the next sync.",
+            ),
+            Problem::UnfinishedOperation(operation) => gettext!(
+                "A “{}” was left unfinished in the notes repository. Repairing cancels it and throws away what it changed.",
+                operation
+            ),
+            Problem::DetachedHead {
+                branch: Some(branch),
+                ..
+            } => gettext!(
+                "The notes repository is not on a branch. Repairing moves the branch “{}” to the current notes, and its changes that are not in them are lost.",
+                branch
+            ),
+            Problem::DetachedHead { branch: None, .. } => gettext(
+                "The notes repository is not on a branch, and there is no single branch to return to. Switch to the branch of your notes, e.g., with “git switch”.",
+            ),
+            Problem::UnbornBranch { branches } => gettext!(
+                "The notes repository is on a branch without any notes, unlike {}. Switch to the branch of your notes, e.g., with “git switch”.",
+                branches
+                    .iter()
+                    .map(|branch| format!("“{}”", branch))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+
+    fn show_auth_dialog(&self, auth_error: &AuthError) {
+        let imp = self.imp();
+
+        // Syncing can fail multiple times while the dialog is shown
+        if imp.auth_dialog.borrow().is_some() {
+            return;
+        }
+
+        let dialog = gtk::MessageDialog::builder()
+            .text(&gettext("Failed to Authenticate"))
+            .secondary_text(&auth_error.to_string())
+            .message_type(gtk::MessageType::Error)
+            .modal(true)
+            .build();
+        dialog.set_transient_for(
+            self.root()
+                .map(|w| w.downcast::<gtk::Window>().unwrap())
+                .as_ref(),
+        );
+
+        if let AuthError::PassphraseRequired { key_path, is_retry } = auth_error {
+            let key_path = key_path.clone();
+
+            dialog.set_text(Some(&gettext("Unlock SSH Key")));
+            dialog.set_secondary_text(Some(&if *is_retry {
+                gettext!(
+                    "The passphrase for “{}” may be wrong. Please try again.",
+                    key_path.display()
+                )
+            } else {
+                gettext!("Enter the passphrase for “{}”.", key_path.display())
+            }));
+            dialog.set_message_type(gtk::MessageType::Question);
+            dialog.add_buttons(&[
+                (&gettext("Cancel"), gtk::ResponseType::Cancel),
+                (&gettext("Unlock"), gtk::ResponseType::Accept),
+            ]);
+            dialog.set_default_response(gtk::ResponseType::Accept);
+
+            let passphrase_entry = gtk::PasswordEntry::builder()
+                .show_peek_icon(true)
+                .activates_default(true)
+                .build();
+            dialog
+                .message_area()
+                .downcast::<gtk::Box>()
+                .unwrap()
+                .append(&passphrase_entry);
+
+            dialog.connect_response(
+                clone!(@weak self as obj, @weak passphrase_entry => move |dialog, response| {
+                    obj.imp().auth_dialog.replace(None);
+                    dialog.destroy();
+
+                    if response != gtk::ResponseType::Accept {
+                        return;
+                    }
+
+                    let passphrase = passphrase_entry.text().to_string();
+                    let key_path = key_path.clone();
+                    spawn!(clone!(@weak obj => async move {
+                        let repository = obj.note_manager().repository();
+                        if let Err(err) = repository.set_ssh_key_passphrase(key_path, passphrase).await {
+                            log::warn!("Failed to store passphrase: {:?}", err);
+                        }
+
+                        if let Err(err) = obj.sync().await {
+                            log::error!("Failed to sync: {:?}", err);
+                        }
+                    }));
+                }),
+            );
+        } else {
+            dialog.add_button(&gettext("Ok"), gtk::ResponseType::Ok);
+
+            dialog.connect_response(clone!(@weak self as obj => move |dialog, _| {
+                obj.imp().auth_dialog.replace(None);
+                dialog.destroy();
+            }));
+        }
+
+        imp.auth_dialog.replace(Some(dialog.clone()));
+        dialog.present();
+    }
+
+    fn show_attach_remote_dialog(&self) {
+        let dialog = gtk::MessageDialog::builder()
+            .text(&gettext("Sync With a Remote"))
+            .secondary_text(&gettext(
+                "Your notes will be stored on the remote, so they can be accessed from your other devices. Notes already on the remote are combined with yours.",
+            ))
+            .message_type(gtk::MessageType::Question)
+            .modal(true)
+            .build();
+        dialog.set_transient_for(
+            self.root()
+                .map(|w| w.downcast::<gtk::Window>().unwrap())
+                .as_ref(),
+        );
+        dialog.add_buttons(&[
+            (&gettext("Cancel"), gtk::ResponseType::Cancel),
+            (&gettext("Sync"), gtk::ResponseType::Accept),
+        ]);
+        dialog.set_default_response(gtk::ResponseType::Accept);
+        dialog.set_response_sensitive(gtk::ResponseType::Accept, false);
+
+        let url_entry = gtk::Entry::builder()
+            .placeholder_text(&gettext("Remote URL"))
+            .activates_default(true)
+            .build();
+        let username_entry = gtk::Entry::builder()
+            .placeholder_text(&gettext("Username"))
+            .visible(false)
+            .build();
+        let token_entry = gtk::PasswordEntry::builder()
+            .placeholder_text(&gettext("Token"))
+            .show_peek_icon(true)
+            .activates_default(true)
+            .visible(false)
+            .build();
+        let branch_name_entry = gtk::Entry::builder()
+            .placeholder_text(&gettext("Branch (Optional)"))
+            .activates_default(true)
+            .build();
+
+        let message_area = dialog.message_area().downcast::<gtk::Box>().unwrap();
+        message_area.append(&url_entry);
+        message_area.append(&username_entry);
+        message_area.append(&token_entry);
+        message_area.append(&branch_name_entry);
+
+        url_entry.connect_changed(
+            clone!(@weak dialog, @weak username_entry, @weak token_entry => move |entry| {
+                let remote_url = RemoteUrl::parse(entry.text().trim());
+                let needs_credentials = remote_url
+                    .as_ref()
+                    .map_or(false, |remote_url| remote_url.scheme().needs_credentials());
+
+                username_entry.set_visible(needs_credentials);
+                token_entry.set_visible(needs_credentials);
+                dialog.set_response_sensitive(gtk::ResponseType::Accept, remote_url.is_ok());
+            }),
+        );
+
+        dialog.connect_response(
+            clone!(@weak self as obj, @weak url_entry, @weak username_entry, @weak token_entry, @weak branch_name_entry => move |dialog, response| {
+                dialog.destroy();
+
+                if response != gtk::ResponseType::Accept {
+                    return;
+                }
+
+                let url = url_entry.text().trim().to_string();
+                let remote_url = match RemoteUrl::parse(&url) {
+                    Ok(remote_url) => remote_url,
+                    Err(err) => {
+                        log::warn!("Invalid remote url `{}`: {}", url, err);
+                        return;
+                    }
+                };
+                let credentials = remote_url
+                    .scheme()
+                    .needs_credentials()
+                    .then(|| Credentials::new(&username_entry.text(), &token_entry.text()).ok())
+                    .flatten();
+                let non_empty =
+                    |text: &str| Some(text.trim().to_string()).filter(|text| !text.is_empty());
+                let remote_name =
+                    non_empty(&Application::default().settings().string("remote-name"));
+                let branch_name = non_empty(&branch_name_entry.text());
+
+                spawn!(clone!(@weak obj => async move {
+                    if let Some(credentials) = credentials {
+                        if let Err(err) = NoteRepository::store_credentials(remote_url, credentials).await {
+                            log::error!("Failed to store credentials: {:?}", err);
+                        }
+                    }
+
+                    let note_manager = obj.note_manager();
+                    if let Err(err) = note_manager.attach_remote(url, remote_name, branch_name).await {
+                        log::error!("Failed to attach remote: {:?}", err);
+                        obj.show_error_dialog(&gettext("Failed to Sync With the Remote"), &err);
+                    }
+                }));
+            }),
+        );
+
+        dialog.present();
+    }
+
+    fn show_detach_remote_dialog(&self) {
+        let dialog = gtk::MessageDialog::builder()
+            .text(&gettext("Stop Syncing?"))
+            .secondary_text(&gettext(
+                "Your notes will only be stored on this device. They and their history are kept, and the notes on the remote are left as they are.",
+            ))
+            .message_type(gtk::MessageType::Question)
+            .modal(true)
+            .build();
+        dialog.set_transient_for(
+            self.root()
+                .map(|w| w.downcast::<gtk::Window>().unwrap())
+                .as_ref(),
+        );
+        dialog.add_buttons(&[
+            (&gettext("Cancel"), gtk::ResponseType::Cancel),
+            (&gettext("Stop Syncing"), gtk::ResponseType::Accept),
+        ]);
+        if let Some(button) = dialog.widget_for_response(gtk::ResponseType::Accept) {
+            button.add_css_class("destructive-action");
+        }
+
+        dialog.connect_response(clone!(@weak self as obj => move |dialog, response| {
+            dialog.destroy();
+
+            if response != gtk::ResponseType::Accept {
+                return;
+            }
+
+            spawn!(clone!(@weak obj => async move {
+                if let Err(err) = obj.note_manager().detach_remote().await {
+                    log::error!("Failed to detach remote: {:?}", err);
+                    obj.show_error_dialog(&gettext("Failed to Stop Syncing"), &err);
+                }
+            }));
+        }));
+
+        dialog.present();
+    }
+
+    fn show_error_dialog(&self, text: &str, err: &anyhow::Error) {
+        let dialog = gtk::MessageDialog::builder()
+            .text(text)
+            .secondary_text(&err.to_string())
+            .message_type(gtk::MessageType::Error)
+            .buttons(gtk::ButtonsType::Ok)
+            .modal(true)
+            .build();
+        dialog.set_transient_for(
+            self.root()
+                .map(|w| w.downcast::<gtk::Window>().unwrap())
+                .as_ref(),
+        );
+        dialog.connect_response(|dialog, _| dialog.destroy());
+        dialog.present();
+    }
+
+    fn is_auto_sync_paused(&self) -> bool {
+        matches!(self.imp().auto_sync.get(), Some(auto_sync) if auto_sync.is_paused())
+    }
+
+    fn update_remote_actions(&self) {
+        let is_offline_mode = self.note_manager().is_offline_mode();
+        self.action_set_enabled("session.attach-remote", is_offline_mode);
+        self.action_set_enabled("session.detach-remote", !is_offline_mode);
+    }
+
     fn set_note_manager(&self, note_manager: NoteManager) {
         self.imp().note_manager.set(note_manager).unwrap();
     }
@@ -309,6 +828,40 @@ impl Session {
             .flags(glib::BindingFlags::SYNC_CREATE)
             .build();
 
+        self.note_manager()
+            .repository()
+            .bind_property("sync-progress", &self.imp().sidebar.get(), "sync-progress")
+            .flags(glib::BindingFlags::SYNC_CREATE)
+            .build();
+
+        self.note_manager().repository().connect_notify_local(
+            Some("sync-error"),
+            clone!(@weak self as obj => move |_, _| {
+                obj.update_sync_error_toast();
+            }),
+        );
+        self.update_sync_error_toast();
+
+        self.note_manager().repository().connect_problems_changed(
+            clone!(@weak self as obj => move |_| {
+                obj.show_problem_dialog();
+            }),
+        );
+        self.show_problem_dialog();
+
+        self.connect_notify_local(Some("is-syncing"), |obj, _| {
+            obj.action_set_enabled("session.cancel-sync", obj.imp().is_syncing.get());
+        });
+        self.action_set_enabled("session.cancel-sync", self.imp().is_syncing.get());
+
+        self.note_manager().connect_notify_local(
+            Some("is-offline-mode"),
+            clone!(@weak self as obj => move |_, _| {
+                obj.update_remote_actions();
+            }),
+        );
+        self.update_remote_actions();
+
         self.imp().leaflet.connect_child_transition_running_notify(
             clone!(@weak self as obj => move |leaflet| {
                 // Only deselect the note when the content is fully hidden
@@ -1,3 +1,4 @@
+use gettextrs::gettext;
 use gtk::{
     gio,
     glib::{self, clone},
@@ -9,15 +10,29 @@ use serde::{Deserialize, Serialize};
 
 use std::{
     cell::{Cell, RefCell},
-    path::PathBuf,
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    path::{Path, PathBuf},
 };
 
+use super::{
+    sync_queue::{Job, SyncQueue},
+    SessionConfig,
+};
 use crate::{
-    core::{NoteRepository, SyncState},
+    core::{
+        ChangedFile, Identity, NoteRepository, Problem, PullStrategy, SigningFormat,
+        SigningOptions, SyncError, SyncState,
+    },
     model::{Note, NoteId, NoteList, TagList},
-    spawn,
+    spawn, Application,
 };
 
+/// Whether the file at `path` is a note, which works even if it does not exist anymore
+fn is_note_path(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("md"))
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(default)]
 struct Data {
@@ -36,6 +51,11 @@ mod imp {
         pub tag_list: RefCell<Option<TagList>>,
         pub is_syncing: Cell<bool>,
         pub is_offline_mode: Cell<bool>,
+        pub sync_queue: SyncQueue<Result<(), SyncError>>,
+        pub inhibit_cookie: Cell<Option<u32>>,
+        /// Tag names last loaded from or saved to the data file
+        pub synced_tag_names: RefCell<Vec<String>>,
+        pub settings_handler_ids: RefCell<Vec<glib::SignalHandlerId>>,
     }
 
     #[glib::object_subclass]
@@ -88,7 +108,9 @@ mod imp {
                         "Is Offline Mode",
                         "Whether the repo syncs to a remote repo",
                         false,
-                        glib::ParamFlags::READWRITE | glib::ParamFlags::CONSTRUCT,
+                        glib::ParamFlags::READWRITE
+                            | glib::ParamFlags::CONSTRUCT
+                            | glib::ParamFlags::EXPLICIT_NOTIFY,
                     ),
                 ]
             });
@@ -97,7 +119,7 @@ mod imp {
 
         fn set_property(
             &self,
-            _obj: &Self::Type,
+            obj: &Self::Type,
             _id: usize,
             value: &glib::Value,
             pspec: &glib::ParamSpec,
@@ -125,7 +147,7 @@ mod imp {
                 }
                 "is-offline-mode" => {
                     let is_offline_mode = value.get().unwrap();
-                    self.is_offline_mode.set(is_offline_mode);
+                    obj.set_is_offline_mode(is_offline_mode);
                 }
                 _ => unimplemented!(),
             }
@@ -149,6 +171,17 @@ mod imp {
             obj.setup_bindings();
             obj.setup_signals();
         }
+
+        fn dispose(&self, _obj: &Self::Type) {
+            if let Some(cookie) = self.inhibit_cookie.take() {
+                Application::default().uninhibit(cookie);
+            }
+
+            let settings = Application::default().settings();
+            for handler_id in self.settings_handler_ids.take() {
+                settings.disconnect(handler_id);
+            }
+        }
     }
 }
 
@@ -157,30 +190,38 @@ glib::wrapper! {
 }
 
 impl NoteManager {
-    // TODO add ways to convert offline mode to online mode
-    pub async fn for_directory(directory: &gio::File, is_offline_mode: bool) -> Self {
-        let repository = {
-            let res = if is_offline_mode {
-                NoteRepository::init(directory).await
-            } else {
-                NoteRepository::clone("git@github.com:SeaDve/test.git".into(), directory).await
-            };
-
-            if let Err(err) = res {
-                log::warn!("Failed to clone or init repo: {:?}", err);
-                log::info!("Opening existing instead...");
-                NoteRepository::open(directory).await.unwrap()
-            } else {
-                res.unwrap()
+    /// Fails if the repository cannot be opened, e.g., when the notes directory was removed
+    pub async fn for_directory(
+        directory: &gio::File,
+        is_offline_mode: bool,
+    ) -> anyhow::Result<Self> {
+        // Synced repositories are already cloned on setup
+        let repository = if is_offline_mode {
+            match NoteRepository::init(directory).await {
+                Ok(repository) => repository,
+                Err(err) => {
+                    log::warn!("Failed to init repo: {:?}", err);
+                    log::info!("Opening existing instead...");
+                    NoteRepository::open(directory).await?
+                }
             }
+        } else {
+            NoteRepository::open(directory).await?
         };
-
-        glib::Object::new(&[
+        repository
+            .set_ssh_key_path(Self::ssh_key_path_setting())
+            .await;
+        repository.set_identity(Self::identity_setting()).await;
+        repository
+            .set_signing_options(Self::signing_options_setting())
+            .await;
+
+        Ok(glib::Object::new(&[
             ("directory", directory),
             ("repository", &repository),
             ("is-offline-mode", &is_offline_mode),
         ])
-        .expect("Failed to create NoteManager.")
+        .expect("Failed to create NoteManager."))
     }
 
     pub fn directory(&self) -> gio::File {
@@ -211,6 +252,85 @@ impl NoteManager {
         self.property("is-offline-mode")
     }
 
+    /// Whether a sync or a commit is running, which may not be transferring with the remote yet
+    pub fn is_sync_requested(&self) -> bool {
+        self.imp().sync_queue.is_busy()
+    }
+
+    fn set_is_offline_mode(&self, is_offline_mode: bool) {
+        if is_offline_mode == self.imp().is_offline_mode.get() {
+            return;
+        }
+
+        self.imp().is_offline_mode.set(is_offline_mode);
+        self.notify("is-offline-mode");
+    }
+
+    /// Start syncing the notes, that were only stored locally, with the branch `branch_name` of the
+    /// remote at `remote_url`. Notes that are already on the remote are merged with the local ones.
+    pub async fn attach_remote(
+        &self,
+        remote_url: String,
+        remote_name: Option<String>,
+        branch_name: Option<String>,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.is_offline_mode(),
+            "Notes are already synced with a remote"
+        );
+
+        self.save_all_notes().await?;
+        self.save_data_file().await?;
+
+        let repository = self.repository();
+        let changed_files = repository
+            .attach_remote(remote_url.clone(), remote_name, branch_name)
+            .await?;
+        self.handle_changed_files(&changed_files).await?;
+        self.update_conflicts().await?;
+
+        self.set_is_offline_mode(false);
+        repository.start_watcher();
+
+        let settings = Application::default().settings();
+        if let Err(err) = SessionConfig::save_remote(&settings, Some(&remote_url)) {
+            log::warn!("Failed to save session config: {:?}", err);
+        }
+        match repository.upstream().await {
+            Ok(upstream) => {
+                if let Err(err) = SessionConfig::save_upstream(&settings, &upstream) {
+                    log::warn!("Failed to save session config: {:?}", err);
+                }
+            }
+            Err(err) => log::warn!("Failed to get upstream: {:?}", err),
+        }
+
+        self.update_last_edit_devices().await;
+
+        log::info!("Attached remote; notes are now synced");
+
+        Ok(())
+    }
+
+    /// Stop syncing the notes with the remote. They and their history are kept locally.
+    pub async fn detach_remote(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            !self.is_offline_mode(),
+            "Notes are not synced with a remote"
+        );
+
+        self.repository().detach_remote().await?;
+        self.set_is_offline_mode(true);
+
+        if let Err(err) = SessionConfig::save_remote(&Application::default().settings(), None) {
+            log::warn!("Failed to save session config: {:?}", err);
+        }
+
+        log::info!("Detached remote; notes are now only stored locally");
+
+        Ok(())
+    }
+
     async fn load_notes(&self) -> anyhow::Result<()> {
         let note_list = NoteList::load_from_dir(&self.directory()).await?;
 
@@ -220,14 +340,8 @@ impl NoteManager {
     }
 
     async fn load_data_file(&self) -> anyhow::Result<()> {
-        let data_file_path = self.data_file_path();
-        let file = gio::File::for_path(&data_file_path);
-
-        let data: Data = match file.load_contents_future().await {
-            Ok((file_content, _)) => {
-                log::info!("Data file found at `{}` is loaded successfully", file.uri(),);
-                serde_yaml::from_slice(&file_content).unwrap_or_default()
-            }
+        let data = match self.read_data_file().await {
+            Ok(data) => data,
             Err(err) => {
                 log::warn!(
                     "Falling back to default data, Failed to load data file: {:?}",
@@ -237,11 +351,19 @@ impl NoteManager {
             }
         };
 
+        self.imp().synced_tag_names.replace(data.tag_list.names());
         self.set_property("tag-list", data.tag_list);
 
         Ok(())
     }
 
+    async fn read_data_file(&self) -> anyhow::Result<Data> {
+        let file = gio::File::for_path(self.data_file_path());
+        let (file_content, _) = file.load_contents_future().await?;
+        log::info!("Data file found at `{}` is loaded successfully", file.uri());
+        Ok(serde_yaml::from_slice(&file_content)?)
+    }
+
     pub async fn save_all_notes(&self) -> anyhow::Result<()> {
         let unsaved_notes = self.note_list().take_unsaved_notes();
 
@@ -268,11 +390,34 @@ impl NoteManager {
             .await
             .map_err(|err| err.1)?;
 
+        self.imp().synced_tag_names.replace(data.tag_list.names());
+
         log::info!("Successfully saved data file");
 
         Ok(())
     }
 
+    /// Save the notes and commit them, without syncing with the remote. It waits for the running
+    /// sync to finish, and joins the one waiting for it, as it commits the changes too.
+    pub async fn commit_changes(&self) -> anyhow::Result<()> {
+        let res = self
+            .imp()
+            .sync_queue
+            .request(Job::Commit, || self.commit_inner())
+            .await;
+        self.update_inhibit();
+        res.map_err(anyhow::Error::from)
+    }
+
+    async fn commit_inner(&self) -> Result<(), SyncError> {
+        self.update_inhibit();
+
+        self.save_all_notes().await?;
+        self.save_data_file().await?;
+        self.repository().commit_changes().await?;
+        Ok(())
+    }
+
     pub fn create_note(&self) {
         let base_path = self.directory().path().unwrap();
         let new_note = Note::new(base_path);
@@ -283,22 +428,110 @@ impl NoteManager {
     }
 
     pub async fn load(&self) -> anyhow::Result<()> {
+        // The changed files are loaded anyway, as nothing is loaded yet
+        if let Err(err) = self.repository().check_health().await {
+            log::error!("Failed to check repository health: {:?}", err);
+        }
+
+        self.update_ignored_patterns().await;
+
         self.load_data_file().await?;
         self.load_notes().await?;
+        self.update_conflicts().await?;
+        self.update_last_edit_devices().await;
+        self.update_local_only_notes().await?;
 
         Ok(())
     }
 
-    // TODO Application::inhibit while syncing
-    // TODO Better way to handle trying to sync multiple times (maybe refactor to use a thread pool)
+    /// Keep `note` out of sync on this device. The other devices keep its last synced version.
+    pub async fn set_note_local_only(
+        &self,
+        note: &Note,
+        is_local_only: bool,
+    ) -> anyhow::Result<()> {
+        if is_local_only == note.is_local_only() {
+            return Ok(());
+        }
+
+        self.repository()
+            .set_local_only(&note.file().path().unwrap(), is_local_only)
+            .await?;
+        note.set_is_local_only(is_local_only);
+
+        log::info!("Set `{}` local only to `{}`", note, is_local_only);
+
+        Ok(())
+    }
+
+    /// Mark the conflict of the note as resolved. This must be called after the note is updated
+    /// with the resolved content.
+    pub async fn resolve_conflict(&self, note: &Note) -> anyhow::Result<()> {
+        let conflict = note
+            .conflict()
+            .ok_or_else(|| anyhow::anyhow!("`{}` has no conflict to resolve", note))?;
+
+        self.repository().resolve_conflict(conflict.path).await?;
+        note.set_conflict(None);
+
+        log::info!("Resolved conflict on `{}`", note);
+
+        Ok(())
+    }
+
+    /// Save the notes and sync them with the remote. A sync requested while another is running
+    /// waits for it to finish, and the ones requested meanwhile share a single sync.
     pub async fn sync(&self) -> anyhow::Result<()> {
-        let repo = self.repository();
+        let res = self
+            .imp()
+            .sync_queue
+            .request(Job::Sync, || self.sync_inner())
+            .await;
+        self.update_inhibit();
+        res.map_err(anyhow::Error::from)
+    }
 
-        if repo.sync_state() == SyncState::Pulling {
-            log::info!("Currently pulling. Returning and skipping session sync...");
-            return Ok(());
+    /// Repair `problem` of the repository, even if it loses something, and update the notes that
+    /// were changed by it
+    pub async fn repair_problem(&self, problem: Problem) -> anyhow::Result<()> {
+        anyhow::ensure!(!self.is_sync_requested(), "Cannot repair while syncing");
+
+        let changed_files = self.repository().repair(problem).await?;
+        self.handle_changed_files(&changed_files).await?;
+        self.update_conflicts().await?;
+
+        log::info!("Repaired repository problem");
+
+        Ok(())
+    }
+
+    async fn sync_inner(&self) -> Result<(), SyncError> {
+        self.update_inhibit();
+
+        let res = self.sync_steps().await;
+
+        // A crash in the middle of a previous sync may have left the repository broken
+        if matches!(
+            res,
+            Err(SyncError::Conflict(_) | SyncError::Corrupt(_) | SyncError::Other(_))
+        ) {
+            if let Err(err) = self.check_repository_health().await {
+                log::error!("Failed to check repository health: {:?}", err);
+            }
         }
 
+        res
+    }
+
+    async fn check_repository_health(&self) -> anyhow::Result<()> {
+        let changed_files = self.repository().check_health().await?;
+        self.handle_changed_files(&changed_files).await?;
+        self.update_conflicts().await
+    }
+
+    async fn sync_steps(&self) -> Result<(), SyncError> {
+        let repo = self.repository();
+
         self.save_all_notes().await?;
         self.save_data_file().await?;
 
@@ -308,54 +541,189 @@ impl NoteManager {
         } else {
             let changed_files = repo.sync().await?;
             self.handle_changed_files(&changed_files).await?;
+            self.update_conflicts().await?;
         }
 
+        self.update_last_edit_devices().await;
+
         log::info!("Session synced; is_offline_mode `{}`", is_offline_mode);
 
         Ok(())
     }
 
-    async fn handle_changed_files(
-        &self,
-        changed_files: &[(PathBuf, git2::Delta)],
-    ) -> anyhow::Result<()> {
-        let note_list = self.note_list();
+    async fn handle_changed_files(&self, changed_files: &[ChangedFile]) -> anyhow::Result<()> {
         let data_file_path = self.data_file_path();
 
-        for (path, delta) in changed_files {
-            if path == &data_file_path {
-                // FIXME handle changed data file too, especially the tag list
+        // Notes are loaded with the tags of the tag list, so the new tags must be there first
+        let is_data_file_changed = changed_files.iter().any(|changed_file| {
+            changed_file.path == data_file_path && changed_file.delta != git2::Delta::Deleted
+        });
+        if is_data_file_changed {
+            log::info!("Sync: Found changed data file; updating tags...");
+            self.handle_changed_data_file().await?;
+        }
+
+        for changed_file in changed_files {
+            if changed_file.path == data_file_path {
                 continue;
             }
 
-            match delta {
-                git2::Delta::Added => {
-                    log::info!("Sync: Found added files `{}`; appending...", path.display());
-                    let file = gio::File::for_path(path);
-                    let added_note = Note::load(&file).await?;
-                    note_list.append(added_note);
-                }
-                git2::Delta::Deleted => {
-                    log::info!(
-                        "Sync: Found removed files `{}`; removing...",
-                        path.display()
-                    );
-                    let note_id = NoteId::for_path(path);
-                    note_list.remove(&note_id);
-                }
-                git2::Delta::Modified => {
-                    log::info!(
-                        "Sync: Found modified files `{}`; updating...",
-                        path.display()
-                    );
-                    let note_id = NoteId::for_path(path);
-                    let note = note_list.get(&note_id).unwrap();
+            log::info!(
+                "Sync: Found {:?} file `{}`",
+                changed_file.delta,
+                changed_file.path.display()
+            );
+
+            // Notes are keyed by their path, so a renamed one is loaded again with its new id
+            if changed_file.delta == git2::Delta::Renamed && is_note_path(&changed_file.old_path) {
+                self.note_list()
+                    .remove(&NoteId::for_path(&changed_file.old_path));
+            }
+
+            if is_note_path(&changed_file.path) {
+                self.handle_changed_note(changed_file).await?;
+            } else {
+                self.handle_changed_attachment(changed_file);
+            }
+        }
+
+        // Notes kept on this device only follow their renames on the remote
+        if changed_files
+            .iter()
+            .any(|changed_file| changed_file.delta == git2::Delta::Renamed)
+        {
+            self.update_local_only_notes().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Apply the tags changed on the remote on the tag list, so the views showing it are updated
+    /// in place
+    async fn handle_changed_data_file(&self) -> anyhow::Result<()> {
+        let remote_names = self.read_data_file().await?.tag_list.names();
+        let base_names = self.imp().synced_tag_names.replace(remote_names.clone());
+
+        let note_list = self.note_list();
+
+        for tag in self.tag_list().merge_remote(&base_names, &remote_names) {
+            note_list.remove_tag_on_all(&tag);
+        }
+
+        Ok(())
+    }
+
+    async fn handle_changed_note(&self, changed_file: &ChangedFile) -> anyhow::Result<()> {
+        let note_list = self.note_list();
+        let note_id = NoteId::for_path(&changed_file.path);
+
+        match changed_file.delta {
+            git2::Delta::Deleted => {
+                note_list.remove(&note_id);
+            }
+            git2::Delta::Added
+            | git2::Delta::Modified
+            | git2::Delta::Renamed
+            | git2::Delta::Copied
+            | git2::Delta::Typechange => {
+                if let Some(note) = note_list.get(&note_id) {
                     note.update().await?;
-                }
-                other => {
-                    log::warn!("Found other delta type: {:?}", other);
+                } else {
+                    let file = gio::File::for_path(&changed_file.path);
+                    note_list.append(Note::load(&file).await?);
                 }
             }
+            other => {
+                log::warn!("Found other delta type: {:?}", other);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Let the attachments stored in the changed file show its new content
+    fn handle_changed_attachment(&self, changed_file: &ChangedFile) {
+        // The attachment is removed from its note by the change on the note itself
+        if changed_file.delta == git2::Delta::Deleted {
+            return;
+        }
+
+        for note in self.note_list().iter() {
+            if let Some(attachment) = note
+                .metadata()
+                .attachment_list()
+                .get_by_path(&changed_file.path)
+            {
+                attachment.emit_file_changed();
+            }
+        }
+    }
+
+    async fn update_conflicts(&self) -> anyhow::Result<()> {
+        let mut conflicts = self
+            .repository()
+            .conflicts()
+            .await?
+            .into_iter()
+            .map(|conflict| (NoteId::for_path(&conflict.path), conflict))
+            .collect::<HashMap<_, _>>();
+
+        for note in self.note_list().iter() {
+            note.set_conflict(conflicts.remove(note.id()));
+        }
+
+        for note_id in conflicts.keys() {
+            log::warn!("Found conflict on note `{:?}` that is not loaded", note_id);
+        }
+
+        Ok(())
+    }
+
+    async fn update_last_edit_devices(&self) {
+        let devices = match self.repository().last_edit_devices().await {
+            Ok(devices) => devices,
+            Err(err) => {
+                log::warn!(
+                    "Failed to get the devices where notes were last edited: {:?}",
+                    err
+                );
+                return;
+            }
+        };
+
+        let mut devices = devices
+            .into_iter()
+            .map(|(path, device)| (NoteId::for_path(&path), device))
+            .collect::<HashMap<_, _>>();
+
+        for note in self.note_list().iter() {
+            note.set_last_edit_device(devices.remove(note.id()));
+        }
+    }
+
+    async fn update_ignored_patterns(&self) {
+        let ignored_patterns = Self::ignored_patterns_setting();
+
+        if let Err(err) = self
+            .repository()
+            .set_ignored_patterns(ignored_patterns)
+            .await
+        {
+            log::error!("Failed to update ignored patterns: {:?}", err);
+        }
+    }
+
+    async fn update_local_only_notes(&self) -> anyhow::Result<()> {
+        let local_only_ids = self
+            .repository()
+            .local_only_paths()
+            .await?
+            .iter()
+            .map(NoteId::for_path)
+            .collect::<HashSet<_>>();
+
+        for note in self.note_list().iter() {
+            note.set_is_local_only(local_only_ids.contains(note.id()));
         }
 
         Ok(())
@@ -372,7 +740,7 @@ impl NoteManager {
             .bind_property("sync-state", self, "is-syncing")
             .transform_to(|_, value| {
                 let sync_state: SyncState = value.get().unwrap();
-                let is_syncing = sync_state != SyncState::Idle;
+                let is_syncing = sync_state.is_syncing();
 
                 Some(is_syncing.to_value())
             })
@@ -380,17 +748,166 @@ impl NoteManager {
             .build();
     }
 
+    /// Key chosen in the preferences, which is `None` to use the default ones
+    pub fn ssh_key_path_setting() -> Option<PathBuf> {
+        let ssh_key_path = Application::default().settings().string("ssh-key-path");
+
+        if ssh_key_path.is_empty() {
+            None
+        } else {
+            Some(PathBuf::from(ssh_key_path.as_str()))
+        }
+    }
+
+    fn ignored_patterns_setting() -> Vec<String> {
+        Application::default()
+            .settings()
+            .strv("ignored-patterns")
+            .iter()
+            .map(|pattern| pattern.trim().to_string())
+            .filter(|pattern| !pattern.is_empty())
+            .collect()
+    }
+
+    fn identity_setting() -> Identity {
+        let settings = Application::default().settings();
+        let non_empty =
+            |key: &str| Some(settings.string(key).to_string()).filter(|s| !s.is_empty());
+
+        Identity {
+            author_name: non_empty("author-name"),
+            author_email: non_empty("author-email"),
+            device_name: non_empty("device-name").or_else(|| Some(glib::host_name().to_string())),
+        }
+    }
+
+    fn signing_options_setting() -> SigningOptions {
+        let settings = Application::default().settings();
+
+        let is_enabled = match settings.string("commit-signing").as_str() {
+            "always" => Some(true),
+            "never" => Some(false),
+            _ => None,
+        };
+        let key = Some(settings.string("signing-key").to_string()).filter(|s| !s.is_empty());
+
+        SigningOptions {
+            is_enabled,
+            format: SigningFormat::from_setting(&settings.string("signing-format")),
+            key,
+        }
+    }
+
+    /// Keep the user from logging out or suspending while committing or syncing, as it would stop
+    /// them halfway through. This is called whenever a job of the sync queue starts or ends.
+    fn update_inhibit(&self) {
+        let imp = self.imp();
+        let app = Application::default();
+
+        if imp.is_syncing.get() || self.is_sync_requested() {
+            if imp.inhibit_cookie.get().is_some() {
+                return;
+            }
+
+            let cookie = app.inhibit(
+                app.active_window().as_ref(),
+                gtk::ApplicationInhibitFlags::LOGOUT | gtk::ApplicationInhibitFlags::SUSPEND,
+                Some(&gettext("Syncing notes")),
+            );
+
+            // Zero means that the request failed
+            if cookie == 0 {
+                log::warn!("Failed to inhibit logout and suspend while syncing");
+            } else {
+                imp.inhibit_cookie.set(Some(cookie));
+            }
+        } else if let Some(cookie) = imp.inhibit_cookie.take() {
+            app.uninhibit(cookie);
+        }
+    }
+
     fn setup_signals(&self) {
+        self.connect_notify_local(Some("is-syncing"), |obj, _| {
+            obj.update_inhibit();
+        });
+
+        // The settings outlive the manager, so the handlers are disconnected when it is disposed
+        let settings = Application::default().settings();
+        let mut handler_ids = self.imp().settings_handler_ids.borrow_mut();
+
+        handler_ids.push(settings.connect_changed(
+            Some("ssh-key-path"),
+            clone!(@weak self as obj => move |_, _| {
+                spawn!(async move {
+                    obj.repository()
+                        .set_ssh_key_path(Self::ssh_key_path_setting())
+                        .await;
+                });
+            }),
+        ));
+
+        handler_ids.push(settings.connect_changed(
+            None,
+            clone!(@weak self as obj => move |_, key| {
+                if !matches!(key, "author-name" | "author-email" | "device-name") {
+                    return;
+                }
+
+                spawn!(async move {
+                    obj.repository().set_identity(Self::identity_setting()).await;
+                });
+            }),
+        ));
+
+        handler_ids.push(settings.connect_changed(
+            None,
+            clone!(@weak self as obj => move |_, key| {
+                if !matches!(key, "commit-signing" | "signing-format" | "signing-key") {
+                    return;
+                }
+
+                spawn!(async move {
+                    obj.repository()
+                        .set_signing_options(Self::signing_options_setting())
+                        .await;
+                });
+            }),
+        ));
+
+        handler_ids.push(settings.connect_changed(
+            Some("ignored-patterns"),
+            clone!(@weak self as obj => move |_, _| {
+                spawn!(async move {
+                    obj.update_ignored_patterns().await;
+                });
+            }),
+        ));
+
+        // The watcher is only started when online, but it may be started later on when a remote is
+        // attached. The remote changes are pulled by the auto sync.
+        let repository = self.repository();
+
+        repository.set_remote_poll_interval(settings.uint("remote-poll-interval"));
+        handler_ids.push(settings.connect_changed(
+            Some("remote-poll-interval"),
+            clone!(@weak repository => move |settings, key| {
+                repository.set_remote_poll_interval(settings.uint(key));
+            }),
+        ));
+
+        let pull_strategy_setting = |settings: &gio::Settings| {
+            PullStrategy::from_setting(&settings.string("pull-strategy"))
+        };
+        repository.set_pull_strategy(pull_strategy_setting(&settings).unwrap_or_default());
+        handler_ids.push(settings.connect_changed(
+            Some("pull-strategy"),
+            clone!(@weak repository => move |settings, _| {
+                repository.set_pull_strategy(pull_strategy_setting(settings).unwrap_or_default());
+            }),
+        ));
+
         if !self.is_offline_mode() {
-            self.repository()
-                .connect_remote_changed(clone!(@weak self as obj => move |_| {
-                    log::info!("New remote changes! Syncing...");
-                    spawn!(async move {
-                        if let Err(err) = obj.sync().await {
-                            log::error!("Failed to sync: {:?}", err);
-                        }
-                    });
-                }));
+            repository.start_watcher();
         }
     }
 }
@@ -18,7 +18,10 @@ use self::{
     sync_button::SyncButton,
     view_switcher::{ItemKind, ViewSwitcher},
 };
-use crate::model::{Note, NoteList, TagList};
+use crate::{
+    core::{SyncPhase, SyncProgress},
+    model::{Note, NoteList, TagList},
+};
 
 mod imp {
     use super::*;
@@ -48,6 +51,8 @@ mod imp {
         pub trash_button: TemplateChild<gtk::ToggleButton>,
         #[template_child]
         pub tag_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub sync_progress_bar: TemplateChild<gtk::ProgressBar>,
 
         pub compact: Cell<bool>,
         pub selection_mode: Cell<SelectionMode>,
@@ -129,6 +134,13 @@ mod imp {
                         false,
                         glib::ParamFlags::READWRITE,
                     ),
+                    glib::ParamSpecBoxed::new(
+                        "sync-progress",
+                        "Sync Progress",
+                        "Progress of the current fetch or push",
+                        SyncProgress::static_type(),
+                        glib::ParamFlags::WRITABLE,
+                    ),
                 ]
             });
             PROPERTIES.as_ref()
@@ -162,6 +174,10 @@ mod imp {
                     let is_syncing = value.get().unwrap();
                     self.is_syncing.set(is_syncing);
                 }
+                "sync-progress" => {
+                    let sync_progress = value.get().unwrap();
+                    obj.set_sync_progress(&sync_progress);
+                }
                 _ => unimplemented!(),
             }
         }
@@ -267,6 +283,17 @@ impl Sidebar {
         self.imp().view_switcher.set_tag_list(tag_list);
     }
 
+    pub fn set_sync_progress(&self, sync_progress: &SyncProgress) {
+        let progress_bar = &self.imp().sync_progress_bar;
+
+        match sync_progress.fraction() {
+            Some(fraction) => progress_bar.set_fraction(fraction),
+            None => progress_bar.pulse(),
+        }
+
+        progress_bar.set_tooltip_text(Some(&Self::describe_sync_progress(sync_progress)));
+    }
+
     pub fn selection_mode(&self) -> SelectionMode {
         self.imp().selection_mode.get()
     }
@@ -326,6 +353,36 @@ impl Sidebar {
         selected_notes
     }
 
+    fn describe_sync_progress(sync_progress: &SyncProgress) -> String {
+        match sync_progress.phase {
+            SyncPhase::Connecting => gettext("Connecting to the remote…"),
+            // Translators: The last {} is an amount of data, e.g., "2.1 MB"
+            SyncPhase::Receiving => gettext!(
+                "Receiving objects {}/{} ({})",
+                sync_progress.received_objects,
+                sync_progress.total_objects,
+                glib::format_size(sync_progress.received_bytes as u64)
+            ),
+            SyncPhase::Resolving => gettext!(
+                "Resolving changes {}/{}",
+                sync_progress.indexed_deltas,
+                sync_progress.total_deltas
+            ),
+            SyncPhase::Packing => gettext!(
+                "Compressing objects {}/{}",
+                sync_progress.packed_objects,
+                sync_progress.total_packed_objects
+            ),
+            // Translators: The last {} is an amount of data, e.g., "2.1 MB"
+            SyncPhase::Pushing => gettext!(
+                "Pushing objects {}/{} ({})",
+                sync_progress.pushed_objects,
+                sync_progress.total_pushed_objects,
+                glib::format_size(sync_progress.pushed_bytes as u64)
+            ),
+        }
+    }
+
     fn note_filter(&self) -> gtk::BoolFilter {
         let selected_type_expression = self
             .imp()
@@ -335,10 +392,12 @@ impl Sidebar {
         let filter_expression = gtk::ClosureExpression::new::<bool, _, _>(
             &[selected_type_expression],
             closure!(|note: Note, selected_type: ItemKind| {
+                let is_conflicted = note.is_conflicted();
                 let note = note.metadata();
 
                 match selected_type {
                     ItemKind::AllNotes => !note.is_trashed(),
+                    ItemKind::Conflicted => is_conflicted,
                     ItemKind::Trash => note.is_trashed(),
                     ItemKind::Tag(ref tag) => note.tag_list().contains(tag) && !note.is_trashed(),
                     ItemKind::Separator | ItemKind::Category | ItemKind::EditTags => {
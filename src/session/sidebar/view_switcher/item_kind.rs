@@ -8,6 +8,7 @@ pub enum ItemKind {
     Separator,
     Category,
     AllNotes,
+    Conflicted,
     EditTags,
     Tag(Tag),
     Trash,
@@ -166,7 +166,7 @@ impl ItemRow {
         if let Some(item) = self.item() {
             if let Some(item) = item.downcast_ref::<Item>() {
                 match item.kind() {
-                    ItemKind::AllNotes | ItemKind::Trash => {
+                    ItemKind::AllNotes | ItemKind::Conflicted | ItemKind::Trash => {
                         imp.label_child.set_label(&item.display_name().unwrap());
                         self.insert_before_select_icon(&imp.label_child.get());
                     }
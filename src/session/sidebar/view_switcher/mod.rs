@@ -129,11 +129,15 @@ impl ViewSwitcher {
     }
 
     pub fn set_tag_list(&self, tag_list: &TagList) {
-        let items: &[glib::Object; 6] = &[
+        let items: &[glib::Object; 7] = &[
             Item::builder(ItemKind::AllNotes)
                 .display_name(&gettext("All Notes"))
                 .build()
                 .upcast(),
+            Item::builder(ItemKind::Conflicted)
+                .display_name(&gettext("Conflicts"))
+                .build()
+                .upcast(),
             Item::builder(ItemKind::Separator).build().upcast(),
             Item::builder(ItemKind::Category)
                 .display_name(&gettext("Tags"))
@@ -265,7 +269,10 @@ impl ViewSwitcher {
                     ItemKind::Separator | ItemKind::Category | ItemKind::EditTags => {
                         list_item.set_selectable(false);
                     }
-                    ItemKind::AllNotes | ItemKind::Tag(_) | ItemKind::Trash => (),
+                    ItemKind::AllNotes
+                    | ItemKind::Conflicted
+                    | ItemKind::Tag(_)
+                    | ItemKind::Trash => (),
                 }
             }
         });
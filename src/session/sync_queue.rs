@@ -0,0 +1,357 @@
+//! Serializes the syncs and commits of a session.
+//!
+//! Only one job runs at a time. A job requested while another is running is queued, and the
+//! requests made while one is already queued join it if it does at least as much, as it includes
+//! their changes anyway. Every caller gets the result of the first job that started after its
+//! request and covers it.
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+
+/// Result of a sync, shared by everyone waiting on it
+#[derive(Debug)]
+struct Slot<T> {
+    result: RefCell<Option<T>>,
+    wakers: RefCell<Vec<Waker>>,
+}
+
+impl<T> Slot<T> {
+    fn new() -> Rc<Self> {
+        Rc::new(Self {
+            result: RefCell::new(None),
+            wakers: RefCell::new(Vec::new()),
+        })
+    }
+
+    fn finish(&self, result: T) {
+        self.result.replace(Some(result));
+
+        for waker in self.wakers.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Resolves once the sync of the slot is finished
+struct SlotFuture<T>(Rc<Slot<T>>);
+
+impl<T: Clone> Future for SlotFuture<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if let Some(result) = self.0.result.borrow().as_ref() {
+            return Poll::Ready(result.clone());
+        }
+
+        let mut wakers = self.0.wakers.borrow_mut();
+        if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            wakers.push(cx.waker().clone());
+        }
+
+        Poll::Pending
+    }
+}
+
+/// What a request needs done. A job covers the requests for the jobs before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Job {
+    /// Commit the local changes
+    Commit,
+    /// Commit the local changes and sync them with the remote
+    Sync,
+}
+
+#[derive(Debug)]
+struct State<T> {
+    running: Option<Rc<Slot<T>>>,
+    /// Only non-empty while a job is running. A commit may be queued before a sync, but never
+    /// the other way around.
+    pending: VecDeque<(Job, Rc<Slot<T>>)>,
+}
+
+#[derive(Debug)]
+pub struct SyncQueue<T> {
+    state: RefCell<State<T>>,
+}
+
+impl<T> Default for SyncQueue<T> {
+    fn default() -> Self {
+        Self {
+            state: RefCell::new(State {
+                running: None,
+                pending: VecDeque::new(),
+            }),
+        }
+    }
+}
+
+impl<T: Clone> SyncQueue<T> {
+    /// Whether a job is running
+    pub fn is_busy(&self) -> bool {
+        self.state.borrow().running.is_some()
+    }
+
+    /// Run `run_job`, which does `job`, once the running job and the ones queued before are
+    /// finished, or join the last queued job if it covers `job`. `run_job` is not called when
+    /// joining.
+    ///
+    /// The returned future must be run to completion, otherwise the requests that joined it never
+    /// finish.
+    pub async fn request<F, Fut>(&self, job: Job, run_job: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        enum Role<T> {
+            Run(Rc<Slot<T>>),
+            RunAfter(Rc<Slot<T>>, Rc<Slot<T>>),
+            Join(Rc<Slot<T>>),
+        }
+
+        let role = {
+            let mut state = self.state.borrow_mut();
+            let state = &mut *state;
+
+            match (&state.running, state.pending.back()) {
+                (None, _) => {
+                    let slot = Slot::new();
+                    state.running = Some(Rc::clone(&slot));
+                    Role::Run(slot)
+                }
+                (Some(_), Some((pending_job, pending))) if *pending_job >= job => {
+                    Role::Join(Rc::clone(pending))
+                }
+                (Some(running), last_pending) => {
+                    let previous = last_pending.map_or(running, |(_, pending)| pending);
+                    let previous = Rc::clone(previous);
+                    let slot = Slot::new();
+                    state.pending.push_back((job, Rc::clone(&slot)));
+                    Role::RunAfter(previous, slot)
+                }
+            }
+        };
+
+        let slot = match role {
+            Role::Run(slot) => slot,
+            Role::RunAfter(previous, slot) => {
+                // The pending slot is made the running one before this is woken up
+                SlotFuture(previous).await;
+                slot
+            }
+            Role::Join(pending) => return SlotFuture(pending).await,
+        };
+
+        let result = run_job().await;
+
+        {
+            let mut state = self.state.borrow_mut();
+            state.running = state.pending.pop_front().map(|(_, slot)| slot);
+        }
+
+        slot.finish(result.clone());
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{
+        cell::Cell,
+        sync::Arc,
+        task::{Wake, Waker},
+    };
+
+    type BoxFuture = Pin<Box<dyn Future<Output = usize>>>;
+
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    /// Pending until it is opened
+    struct Gate(Rc<Cell<bool>>);
+
+    impl Future for Gate {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+            if self.0.get() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Keeps track of the syncs run by the queue
+    #[derive(Default)]
+    struct Syncs {
+        n_started: Cell<usize>,
+        n_running: Cell<usize>,
+        is_gate_open: Rc<Cell<bool>>,
+    }
+
+    impl Syncs {
+        /// Request a sync that resolves to the number of the sync that covered it
+        fn request(self: &Rc<Self>, queue: &Rc<SyncQueue<usize>>) -> BoxFuture {
+            self.request_job(queue, Job::Sync)
+        }
+
+        /// Request `job`, numbered along with the syncs
+        fn request_job(self: &Rc<Self>, queue: &Rc<SyncQueue<usize>>, job: Job) -> BoxFuture {
+            let queue = Rc::clone(queue);
+            let syncs = Rc::clone(self);
+
+            Box::pin(async move {
+                queue
+                    .request(job, || async move {
+                        syncs.n_running.set(syncs.n_running.get() + 1);
+                        assert_eq!(syncs.n_running.get(), 1, "syncs overlapped");
+
+                        syncs.n_started.set(syncs.n_started.get() + 1);
+                        let n = syncs.n_started.get();
+
+                        Gate(Rc::clone(&syncs.is_gate_open)).await;
+
+                        syncs.n_running.set(syncs.n_running.get() - 1);
+                        n
+                    })
+                    .await
+            })
+        }
+    }
+
+    fn poll_all(futures: &mut [(BoxFuture, Option<usize>)]) {
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+
+        for (future, result) in futures {
+            if result.is_none() {
+                if let Poll::Ready(n) = future.as_mut().poll(&mut cx) {
+                    *result = Some(n);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn one_at_a_time() {
+        let queue = Rc::new(SyncQueue::default());
+        let syncs = Rc::new(Syncs::default());
+        syncs.is_gate_open.set(true);
+
+        for n in 1..=3 {
+            let mut futures = vec![(syncs.request(&queue), None)];
+            poll_all(&mut futures);
+            assert_eq!(futures[0].1, Some(n));
+            assert!(!queue.is_busy());
+        }
+    }
+
+    #[test]
+    fn coalesce() {
+        let queue = Rc::new(SyncQueue::default());
+        let syncs = Rc::new(Syncs::default());
+
+        let mut futures = (0..10)
+            .map(|_| (syncs.request(&queue), None))
+            .collect::<Vec<_>>();
+
+        poll_all(&mut futures);
+        assert!(queue.is_busy());
+        assert_eq!(syncs.n_started.get(), 1);
+        assert!(futures.iter().all(|(_, result)| result.is_none()));
+
+        syncs.is_gate_open.set(true);
+        poll_all(&mut futures);
+        assert!(!queue.is_busy());
+        assert_eq!(syncs.n_started.get(), 2);
+
+        let results = futures
+            .iter()
+            .map(|(_, result)| result.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(results, [1, 2, 2, 2, 2, 2, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn commit_does_not_cover_sync() {
+        let queue = Rc::new(SyncQueue::default());
+        let syncs = Rc::new(Syncs::default());
+
+        let mut futures = vec![
+            (syncs.request(&queue), None),
+            (syncs.request_job(&queue, Job::Commit), None),
+            (syncs.request_job(&queue, Job::Commit), None),
+            (syncs.request(&queue), None),
+            (syncs.request_job(&queue, Job::Commit), None),
+            (syncs.request(&queue), None),
+        ];
+
+        poll_all(&mut futures);
+        assert_eq!(syncs.n_started.get(), 1);
+
+        syncs.is_gate_open.set(true);
+        poll_all(&mut futures);
+        assert!(!queue.is_busy());
+        assert_eq!(syncs.n_started.get(), 3);
+
+        let results = futures
+            .iter()
+            .map(|(_, result)| result.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(results, [1, 2, 2, 3, 3, 3]);
+    }
+
+    #[test]
+    fn hammer() {
+        let queue = Rc::new(SyncQueue::default());
+        let syncs = Rc::new(Syncs::default());
+
+        let mut futures = Vec::new();
+        // Number of syncs that were started when each request was made
+        let mut n_started_before = Vec::new();
+        let mut seed = 7_u32;
+
+        for _ in 0..200 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+
+            for i in 0..(seed >> 16) % 4 {
+                let job = if (seed >> (24 + i)) & 1 == 0 {
+                    Job::Sync
+                } else {
+                    Job::Commit
+                };
+                futures.push((syncs.request_job(&queue, job), None));
+                n_started_before.push(syncs.n_started.get());
+            }
+
+            syncs.is_gate_open.set((seed >> 20) & 1 == 0);
+            poll_all(&mut futures);
+        }
+
+        syncs.is_gate_open.set(true);
+        poll_all(&mut futures);
+
+        assert!(!queue.is_busy());
+        assert!(syncs.n_started.get() < futures.len());
+
+        for ((_, result), n_started_before) in futures.iter().zip(n_started_before) {
+            let n = result.expect("request never finished");
+            assert!(
+                n > n_started_before,
+                "request was covered by a sync started before it"
+            );
+        }
+    }
+}
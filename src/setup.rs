@@ -1,4 +1,5 @@
 use adw::prelude::*;
+use gettextrs::gettext;
 use gtk::{
     gio,
     glib::{self, clone},
@@ -6,9 +7,15 @@ use gtk::{
 };
 use num_enum::TryFromPrimitive;
 
-use std::{cell::RefCell, convert::TryFrom};
+use std::{cell::RefCell, convert::TryFrom, sync::Arc, time::Duration};
 
-use crate::{core::NoteRepository, session::Session, spawn, utils};
+use crate::{
+    core::{Cancelled, Credentials, NoteRepository, RemoteUrl, TransferMonitor},
+    session::{NoteManager, Session, SessionConfig, SessionMode},
+    spawn, spawn_blocking, utils, Application,
+};
+
+const CLONE_PROGRESS_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 mod imp {
     use super::*;
@@ -31,6 +38,35 @@ mod imp {
                 Err(err) => panic!("Failed to get GitHost from u8: {:?}", err),
             }
         }
+
+        /// Stored in the session config
+        pub const fn id(&self) -> &'static str {
+            match self {
+                Self::Github => "github",
+                Self::Gitlab => "gitlab",
+                Self::Custom => "custom",
+            }
+        }
+
+        /// Page where a new repository can be created, if it is known
+        pub const fn new_repository_uri(&self) -> Option<&'static str> {
+            match self {
+                Self::Github => Some("https://github.com/new"),
+                Self::Gitlab => Some("https://gitlab.com/projects/new"),
+                Self::Custom => None,
+            }
+        }
+
+        pub const fn clone_url_placeholder(&self, is_https: bool) -> &'static str {
+            match (self, is_https) {
+                (Self::Github, false) => "git@github.com:username/notes.git",
+                (Self::Github, true) => "https://github.com/username/notes.git",
+                (Self::Gitlab, false) => "git@gitlab.com:username/notes.git",
+                (Self::Gitlab, true) => "https://gitlab.com/username/notes.git",
+                (Self::Custom, false) => "git@example.com:username/notes.git",
+                (Self::Custom, true) => "https://example.com/username/notes.git",
+            }
+        }
     }
 
     #[derive(Debug, Default)]
@@ -38,6 +74,7 @@ mod imp {
         pub provider: Option<GitHost>,
         pub is_automatic: Option<bool>,
         pub clone_url: Option<String>,
+        pub credentials: Option<Credentials>,
     }
 
     #[derive(Debug, Default, CompositeTemplate)]
@@ -58,9 +95,33 @@ mod imp {
 
         // create repo page
         #[template_child]
+        pub clone_error_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub create_new_webpage_button: TemplateChild<gtk::Button>,
+        #[template_child]
         pub clone_url_entry: TemplateChild<gtk::Entry>,
+        #[template_child]
+        pub https_credentials_row: TemplateChild<adw::ExpanderRow>,
+        #[template_child]
+        pub username_entry: TemplateChild<gtk::Entry>,
+        #[template_child]
+        pub token_entry: TemplateChild<gtk::PasswordEntry>,
+        #[template_child]
+        pub ssh_key_provider_row: TemplateChild<adw::ExpanderRow>,
+        #[template_child]
+        pub remote_name_entry: TemplateChild<gtk::Entry>,
+        #[template_child]
+        pub branch_name_entry: TemplateChild<gtk::Entry>,
+
+        // cloning page
+        #[template_child]
+        pub cloning_page: TemplateChild<adw::StatusPage>,
+        #[template_child]
+        pub cloning_progress_bar: TemplateChild<gtk::ProgressBar>,
 
         pub config: RefCell<SetupConfig>,
+        pub clone_monitor: RefCell<Option<Arc<TransferMonitor>>>,
+        pub clone_progress_source_id: RefCell<Option<glib::SourceId>>,
     }
 
     #[glib::object_subclass]
@@ -80,10 +141,19 @@ mod imp {
                 obj.navigate_forward();
             });
 
+            klass.install_action("setup.cancel-clone", None, move |obj, _, _| {
+                obj.cancel_clone();
+            });
+
             klass.install_action("setup.setup-offline-mode", None, move |obj, _, _| {
                 spawn!(clone!(@weak obj => async move {
-                    let new_session = obj.setup_offline_session().await;
-                    obj.emit_by_name::<()>("session-setup-done", &[&new_session]);
+                    match obj.setup_offline_session().await {
+                        Ok(new_session) => {
+                            obj.emit_by_name::<()>("session-setup-done", &[&new_session]);
+                        }
+                        // TODO add user facing error dialog
+                        Err(err) => log::error!("Failed to setup offline session: {:?}", err),
+                    }
                 }));
             });
 
@@ -135,23 +205,41 @@ mod imp {
 
             self.content
                 .connect_visible_child_notify(clone!(@weak obj => move |content| {
-                    let is_main_page = content.visible_child_name().unwrap().as_str() == "welcome";
+                    let is_navigable = !matches!(
+                        content.visible_child_name().unwrap().as_str(),
+                        "welcome" | "cloning"
+                    );
                     let imp = obj.imp();
-                    imp.navigate_back_button.set_visible(!is_main_page);
-                    imp.navigate_forward_button.set_visible(!is_main_page);
+                    imp.navigate_back_button.set_visible(is_navigable);
+                    imp.navigate_forward_button.set_visible(is_navigable);
+                }));
+
+            self.create_new_webpage_button
+                .connect_clicked(clone!(@weak obj => move |_| {
+                    obj.open_new_repository_page();
                 }));
 
             self.clone_url_entry
-                .connect_text_notify(clone!(@weak obj => move |entry| {
-                    if obj.imp().content.visible_child_name().unwrap().as_str() == "create-repo" {
-                        let entry_text = entry.text();
-                        let is_valid = NoteRepository::validate_remote_url(&entry_text);
-                        obj.action_set_enabled("setup.navigate-forward", is_valid);
-                    }
+                .connect_text_notify(clone!(@weak obj => move |_| {
+                    obj.update_create_repo_page();
+                }));
+
+            self.username_entry
+                .connect_text_notify(clone!(@weak obj => move |_| {
+                    obj.update_create_repo_page();
+                }));
+
+            self.token_entry
+                .connect_text_notify(clone!(@weak obj => move |_| {
+                    obj.update_create_repo_page();
                 }));
         }
 
         fn dispose(&self, obj: &Self::Type) {
+            if let Some(source_id) = self.clone_progress_source_id.take() {
+                source_id.remove();
+            }
+
             while let Some(child) = obj.first_child() {
                 child.unparent();
             }
@@ -183,7 +271,7 @@ impl Setup {
         })
     }
 
-    async fn setup_offline_session(&self) -> Session {
+    async fn setup_offline_session(&self) -> anyhow::Result<Session> {
         let notes_folder = gio::File::for_path(utils::default_notes_dir());
         if let Err(err) = notes_folder
             .make_directory_future(glib::PRIORITY_HIGH_IDLE)
@@ -193,7 +281,14 @@ impl Setup {
             log::error!("Failed to create note folder: {:?}", err);
         }
 
-        Session::new_offline(&notes_folder).await
+        let config = SessionConfig::offline(utils::default_notes_dir());
+        let session = Session::for_config(&config).await?;
+
+        if let Err(err) = config.save(&Application::default().settings()) {
+            log::error!("Failed to save session config: {:?}", err);
+        }
+
+        Ok(session)
     }
 
     fn navigate_forward(&self) {
@@ -203,6 +298,7 @@ impl Setup {
         match visible_page_name.as_str() {
             "select-provider" => {
                 self.select_provider();
+                self.update_create_repo_defaults();
 
                 imp.content.set_visible_child_name("create-repo");
                 imp.clone_url_entry.notify("text");
@@ -242,15 +338,293 @@ impl Setup {
         config.provider = Some(provider);
     }
 
-    fn create_repo(&self) {
+    fn update_create_repo_page(&self) {
         let imp = self.imp();
-        let mut config = imp.config.borrow_mut();
+
+        if imp.content.visible_child_name().unwrap().as_str() != "create-repo" {
+            return;
+        }
 
         let clone_url = imp.clone_url_entry.text();
-        config.clone_url = Some(clone_url.to_string());
+        let remote_url = RemoteUrl::parse(&clone_url);
+
+        let (needs_credentials, is_ssh) =
+            remote_url.as_ref().map_or((false, false), |remote_url| {
+                let scheme = remote_url.scheme();
+                (scheme.needs_credentials(), scheme.is_ssh())
+            });
+
+        imp.https_credentials_row.set_visible(needs_credentials);
+        imp.https_credentials_row.set_expanded(needs_credentials);
+        imp.ssh_key_provider_row.set_visible(is_ssh);
+
+        match remote_url {
+            Err(ref err) if !clone_url.is_empty() => {
+                imp.clone_url_entry.add_css_class("error");
+                imp.clone_url_entry.set_tooltip_text(Some(&err.to_string()));
+            }
+            _ => {
+                imp.clone_url_entry.remove_css_class("error");
+                imp.clone_url_entry.set_tooltip_text(None);
+            }
+        }
+
+        let is_valid = remote_url.is_ok() && (!needs_credentials || self.credentials().is_some());
+        self.action_set_enabled("setup.navigate-forward", is_valid);
+    }
+
+    fn credentials(&self) -> Option<Credentials> {
+        let imp = self.imp();
+
+        let username = imp.username_entry.text();
+        let token = imp.token_entry.text();
+
+        if username.is_empty() || token.is_empty() {
+            return None;
+        }
+
+        Credentials::new(&username, &token).ok()
+    }
+
+    fn create_repo(&self) {
+        let imp = self.imp();
+
+        let clone_url = imp.clone_url_entry.text().trim().to_string();
+
+        let remote_url = match RemoteUrl::parse(&clone_url) {
+            Ok(remote_url) => remote_url,
+            Err(err) => {
+                log::warn!("Invalid remote url `{}`: {}", clone_url, err);
+                return;
+            }
+        };
+
+        let credentials = if remote_url.scheme().needs_credentials() {
+            self.credentials()
+        } else {
+            None
+        };
+
+        let git_host = {
+            let mut config = imp.config.borrow_mut();
+            config.clone_url = Some(clone_url.clone());
+            config.credentials = credentials.clone();
+            config
+                .provider
+                .as_ref()
+                .map(|provider| provider.id().to_string())
+        };
+
+        imp.clone_error_label.set_visible(false);
+        imp.cloning_page.set_description(Some(&gettext!(
+            "Downloading notes from {}…",
+            remote_url.host().unwrap_or(&clone_url)
+        )));
+        imp.content.set_visible_child_name("cloning");
+        self.action_set_enabled("setup.navigate-back", false);
+        self.action_set_enabled("setup.navigate-forward", false);
+
+        let non_empty = |entry: &gtk::Entry| {
+            Some(entry.text().trim().to_string()).filter(|text| !text.is_empty())
+        };
+
+        let config = SessionConfig {
+            mode: SessionMode::Online,
+            notes_directory: utils::default_notes_dir(),
+            remote_url: Some(clone_url),
+            remote_name: non_empty(&imp.remote_name_entry),
+            branch_name: non_empty(&imp.branch_name_entry),
+            git_host,
+            is_auto_sync: true,
+        };
+
+        let monitor = self.start_clone_progress_polling();
+
+        spawn!(clone!(@weak self as obj => async move {
+            let res = Self::setup_online_session(remote_url, credentials, config, monitor).await;
+            obj.stop_clone_progress_polling();
+
+            match res {
+                Ok(session) => {
+                    obj.emit_by_name::<()>("session-setup-done", &[&session]);
+                }
+                Err(err) if err.is::<Cancelled>() => {
+                    log::info!("Cloning was cancelled");
+                    obj.show_create_repo_page();
+                }
+                Err(err) => {
+                    log::error!("Failed to setup online session: {:?}", err);
+                    obj.show_clone_error(&err);
+                }
+            }
+        }));
+    }
+
+    /// Clone the remote into the notes directory, then create a session for it
+    async fn setup_online_session(
+        remote_url: RemoteUrl,
+        credentials: Option<Credentials>,
+        mut config: SessionConfig,
+        monitor: Arc<TransferMonitor>,
+    ) -> anyhow::Result<Session> {
+        if let Some(credentials) = credentials {
+            NoteRepository::store_credentials(remote_url, credentials).await?;
+        }
+
+        let notes_directory = config.notes_directory.clone();
+        let had_notes_directory = notes_directory.exists();
+
+        match Self::clone_session(&mut config, monitor).await {
+            Ok(session) => {
+                if let Err(err) = config.save(&Application::default().settings()) {
+                    log::error!("Failed to save session config: {:?}", err);
+                }
+
+                Ok(session)
+            }
+            Err(err) => {
+                // Cloning fails on a non-empty directory, so remove what was cloned to be able to
+                // try again
+                if !had_notes_directory && notes_directory.exists() {
+                    if let Err(err) =
+                        spawn_blocking!(move || std::fs::remove_dir_all(notes_directory)).await
+                    {
+                        log::error!("Failed to remove notes directory: {:?}", err);
+                    }
+                }
+
+                Err(err)
+            }
+        }
+    }
+
+    /// Also stores in `config` the branch that was checked out, in case it was left to the
+    /// default
+    async fn clone_session(
+        config: &mut SessionConfig,
+        monitor: Arc<TransferMonitor>,
+    ) -> anyhow::Result<Session> {
+        let notes_folder = gio::File::for_path(&config.notes_directory);
+        let clone_url = config.remote_url.clone().unwrap_or_default();
+        let repository = NoteRepository::clone(
+            clone_url,
+            config.remote_name.clone(),
+            config.branch_name.clone(),
+            &notes_folder,
+            NoteManager::ssh_key_path_setting(),
+            Arc::clone(&monitor),
+        )
+        .await?;
+        config.set_upstream(repository.upstream().await?);
+
+        // Cancelling after the download is done should still not leave a session behind
+        if monitor.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+
+        let session = Session::for_config(config).await?;
+        let repository = session.note_manager().repository();
+
+        // The remote may be a newly created one without any commit, which can't be pulled from
+        if let Err(err) = repository.initialize_if_empty().await {
+            // The notes directory is removed afterwards, so nothing must still be using it
+            repository.stop_watcher().await;
+            drop(session);
+            return Err(err);
+        }
+
+        Ok(session)
+    }
+
+    fn start_clone_progress_polling(&self) -> Arc<TransferMonitor> {
+        let imp = self.imp();
+        let monitor = Arc::<TransferMonitor>::default();
+
+        imp.cloning_progress_bar.set_fraction(0.0);
+        self.action_set_enabled("setup.cancel-clone", true);
+
+        let source_id = glib::timeout_add_local(
+            CLONE_PROGRESS_POLL_INTERVAL,
+            clone!(@weak self as obj, @strong monitor => @default-return Continue(false), move || {
+                let progress_bar = &obj.imp().cloning_progress_bar;
+
+                match monitor.progress().fraction() {
+                    Some(fraction) => progress_bar.set_fraction(fraction),
+                    None => progress_bar.pulse(),
+                }
+
+                Continue(true)
+            }),
+        );
+        imp.clone_progress_source_id.replace(Some(source_id));
+        imp.clone_monitor.replace(Some(Arc::clone(&monitor)));
+
+        monitor
+    }
+
+    fn stop_clone_progress_polling(&self) {
+        let imp = self.imp();
+
+        if let Some(source_id) = imp.clone_progress_source_id.take() {
+            source_id.remove();
+        }
+        imp.clone_monitor.replace(None);
+    }
+
+    fn cancel_clone(&self) {
+        if let Some(monitor) = self.imp().clone_monitor.borrow().as_ref() {
+            monitor.cancel();
+        }
+
+        // Cancelling only takes effect once the remote responds
+        self.action_set_enabled("setup.cancel-clone", false);
+    }
+
+    fn show_clone_error(&self, err: &anyhow::Error) {
+        let imp = self.imp();
+
+        imp.clone_error_label.set_label(&err.to_string());
+        imp.clone_error_label.set_visible(true);
+        self.show_create_repo_page();
+    }
+
+    fn show_create_repo_page(&self) {
+        self.imp().content.set_visible_child_name("create-repo");
+
+        self.action_set_enabled("setup.navigate-back", true);
+        self.update_create_repo_page();
+    }
+
+    /// Adapt the create repo page to the chosen provider
+    fn update_create_repo_defaults(&self) {
+        let imp = self.imp();
+        let config = imp.config.borrow();
+
+        let provider = config.provider.as_ref().unwrap_or(&imp::GitHost::Custom);
+        // Logging in with the account is done with an access token, which only works with HTTPS
+        let is_https = config.is_automatic.unwrap_or_default();
+
+        imp.clone_url_entry
+            .set_placeholder_text(Some(provider.clone_url_placeholder(is_https)));
+        imp.create_new_webpage_button
+            .set_sensitive(provider.new_repository_uri().is_some());
+    }
+
+    fn open_new_repository_page(&self) {
+        let uri = match self
+            .imp()
+            .config
+            .borrow()
+            .provider
+            .as_ref()
+            .and_then(imp::GitHost::new_repository_uri)
+        {
+            Some(uri) => uri,
+            None => return,
+        };
 
-        if !NoteRepository::validate_remote_url(&clone_url) {
-            log::warn!("Invalid remote url `{}`", clone_url);
+        if let Err(err) = gio::AppInfo::launch_default_for_uri(uri, gio::AppLaunchContext::NONE) {
+            log::error!("Failed to open `{}`: {:?}", uri, err);
         }
     }
 }
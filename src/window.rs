@@ -1,4 +1,5 @@
 use adw::subclass::prelude::*;
+use gettextrs::gettext;
 use gtk::{
     gio,
     glib::{self, clone},
@@ -7,7 +8,20 @@ use gtk::{
 };
 use once_cell::unsync::OnceCell;
 
-use crate::{config::PROFILE, session::Session, setup::Setup, spawn, utils, Application};
+use std::{
+    cell::{Cell, RefCell},
+    time::Duration,
+};
+
+use crate::{
+    config::PROFILE,
+    session::{Session, SessionConfig},
+    setup::Setup,
+    spawn, Application,
+};
+
+/// How long syncing on close may take before telling the user why the window is still open
+const FINISHING_SYNC_DIALOG_DELAY: Duration = Duration::from_millis(500);
 
 mod imp {
     use super::*;
@@ -24,6 +38,10 @@ mod imp {
         pub loading: TemplateChild<gtk::WindowHandle>,
 
         pub session: OnceCell<Session>,
+        pub is_finishing_sync: Cell<bool>,
+        pub is_sync_finished: Cell<bool>,
+        pub finishing_sync_dialog: RefCell<Option<gtk::MessageDialog>>,
+        pub finishing_sync_dialog_source_id: RefCell<Option<glib::SourceId>>,
     }
 
     #[glib::object_subclass]
@@ -65,16 +83,28 @@ mod imp {
                 }));
 
             // If already setup
-            if utils::default_notes_dir().exists() {
-                let notes_folder = gio::File::for_path(utils::default_notes_dir());
-                spawn!(clone!(@weak obj => async move {
-                    // FIXME detect if it is offline mode or online
-                    let existing_session = Session::new_offline(&notes_folder).await;
-                    if let Err(err) = obj.load_session(existing_session).await {
-                        log::error!("Failed to load session: {:?}", err);
+            spawn!(clone!(@weak obj => async move {
+                let settings = Application::default().settings();
+                let config = match SessionConfig::load_or_migrate(&settings).await {
+                    Some(config) => config,
+                    None => return,
+                };
+
+                log::info!("Restoring session with config {:?}", config);
+                let existing_session = match Session::for_config(&config).await {
+                    Ok(session) => session,
+                    // The setup is still shown, so the notes can be set up again
+                    Err(err) => {
+                        log::error!("Failed to restore session: {:?}", err);
+                        obj.show_restore_error_dialog(&err);
+                        return;
                     }
-                }));
-            }
+                };
+
+                if let Err(err) = obj.load_session(existing_session).await {
+                    log::error!("Failed to load session: {:?}", err);
+                }
+            }));
         }
     }
 
@@ -86,14 +116,10 @@ mod imp {
                 log::warn!("Failed to save window state: {:?}", &err);
             }
 
-            // TODO what if app crashed? so maybe implement autosync
-            if let Some(session) = self.session.get() {
-                let ctx = glib::MainContext::default();
-                ctx.block_on(async move {
-                    if let Err(err) = session.sync().await {
-                        log::error!("Failed to sync session: {:?}", err);
-                    }
-                });
+            // Edits are committed shortly after they are made, but the last ones may not be yet
+            if self.session.get().is_some() && !self.is_sync_finished.get() {
+                obj.finish_sync_and_close();
+                return gtk::Inhibit(true);
             }
 
             self.parent_close_request(obj)
@@ -168,6 +194,94 @@ impl Window {
         Ok(())
     }
 
+    fn show_restore_error_dialog(&self, err: &anyhow::Error) {
+        let dialog = gtk::MessageDialog::builder()
+            .text(&gettext("Failed to Open Notes"))
+            .secondary_text(&gettext!(
+                "The notes could not be opened, so they have to be set up again: {}",
+                err
+            ))
+            .message_type(gtk::MessageType::Error)
+            .buttons(gtk::ButtonsType::Ok)
+            .modal(true)
+            .transient_for(self)
+            .build();
+        dialog.connect_response(|dialog, _| dialog.destroy());
+        dialog.present();
+    }
+
+    /// Sync the last changes, then close the window again. A dialog is shown if it takes a while,
+    /// so the window is not mistaken to be frozen.
+    fn finish_sync_and_close(&self) {
+        let imp = self.imp();
+
+        if imp.is_finishing_sync.get() {
+            return;
+        }
+        imp.is_finishing_sync.set(true);
+
+        let source_id = glib::timeout_add_local_once(
+            FINISHING_SYNC_DIALOG_DELAY,
+            clone!(@weak self as obj => move || {
+                obj.imp().finishing_sync_dialog_source_id.replace(None);
+                obj.show_finishing_sync_dialog();
+            }),
+        );
+        imp.finishing_sync_dialog_source_id.replace(Some(source_id));
+
+        spawn!(clone!(@weak self as obj => async move {
+            if let Err(err) = obj.session().sync_before_close().await {
+                log::error!("Failed to sync session: {:?}", err);
+            }
+
+            obj.close_after_sync();
+        }));
+    }
+
+    fn show_finishing_sync_dialog(&self) {
+        let dialog = gtk::MessageDialog::builder()
+            .text(&gettext("Finishing Sync…"))
+            .secondary_text(&gettext(
+                "Your last changes are being synced. Closing now stops the sync, and the changes will be synced the next time the app is opened.",
+            ))
+            .message_type(gtk::MessageType::Info)
+            .modal(true)
+            .transient_for(self)
+            .build();
+        dialog.add_button(&gettext("Close Anyway"), gtk::ResponseType::Close);
+
+        dialog.connect_response(clone!(@weak self as obj => move |_, _| {
+            obj.session()
+                .activate_action("session.cancel-sync", None)
+                .unwrap();
+            obj.close_after_sync();
+        }));
+
+        self.imp()
+            .finishing_sync_dialog
+            .replace(Some(dialog.clone()));
+        dialog.present();
+    }
+
+    fn close_after_sync(&self) {
+        let imp = self.imp();
+
+        if imp.is_sync_finished.get() {
+            return;
+        }
+        imp.is_sync_finished.set(true);
+
+        if let Some(source_id) = imp.finishing_sync_dialog_source_id.take() {
+            source_id.remove();
+        }
+
+        if let Some(dialog) = imp.finishing_sync_dialog.take() {
+            dialog.destroy();
+        }
+
+        self.close();
+    }
+
     fn save_window_size(&self) -> Result<(), glib::BoolError> {
         let settings = Application::default().settings();
 